@@ -19,3 +19,4 @@
 //!   - Domain 也不调用 Application (它不关心业务流程)
 
 pub mod mqtt_event;
+pub mod webhook;
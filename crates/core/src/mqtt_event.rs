@@ -1,8 +1,12 @@
 //! 处理 MQTT 事件.
 
+use internal_shared::json::{JsonSchema, Redactor};
+use internal_shared::trace::TraceContext;
 use rumqttc::v5::{AsyncClient, Event, Event::Incoming, mqttbytes::v5};
+use serde_json::Value;
 use std::time::Duration;
-use tokio::{sync::mpsc, time::sleep};
+use tokio::{sync::mpsc, task::JoinHandle, time::sleep};
+use tokio_util::sync::CancellationToken;
 
 /// MQTT 事件分发上下文.
 pub struct MqttEventDispatchContext {
@@ -10,14 +14,46 @@ pub struct MqttEventDispatchContext {
     pub client: AsyncClient,
     /// MQTT 事件循环接收器.
     pub event_loop: mpsc::Receiver<Event>,
+    /// 按主题校验消息体的 JSON Schema.
+    pub schemas: TopicSchemas,
+}
+
+/// 主题过滤器到 JSON Schema 的映射.
+///
+/// 过滤器支持 MQTT 通配符 `+` 和 `#`. 一条消息匹配多个过滤器时, 使用最先注册的那个.
+#[derive(Default)]
+pub struct TopicSchemas {
+    schemas: Vec<(String, JsonSchema)>,
+}
+
+impl TopicSchemas {
+    /// 为主题过滤器注册 Schema.
+    pub fn insert(&mut self, filter: impl Into<String>, schema: JsonSchema) {
+        self.schemas.push((filter.into(), schema));
+    }
+
+    /// 查找主题对应的 Schema.
+    pub fn get(&self, topic: &str) -> Option<&JsonSchema> {
+        self.schemas
+            .iter()
+            .find(|(filter, _)| topic_matches(filter, topic))
+            .map(|(_, schema)| schema)
+    }
 }
 
 /// 分发处理 MQTT 事件.
-pub fn dispatch_mqtt_events(mqtt_event_dispatch_context: MqttEventDispatchContext) {
+///
+/// 每条消息按用户属性中的 `traceparent` 或 `x-request-id` 建立追踪范围, 没有时生成新的 trace ID.
+/// `shutdown` 取消后, 处理完当前消息即退出.
+pub fn dispatch_mqtt_events(
+    mqtt_event_dispatch_context: MqttEventDispatchContext,
+    shutdown: CancellationToken,
+) -> JoinHandle<()> {
     let mut event_loop = mqtt_event_dispatch_context.event_loop;
+    let schemas = mqtt_event_dispatch_context.schemas;
     tokio::spawn(async move {
-        loop {
-            let Some(event) = event_loop.recv().await else {
+        while let Some(event) = shutdown.run_until_cancelled(event_loop.recv()).await {
+            let Some(event) = event else {
                 sleep(Duration::from_secs(1)).await;
                 continue;
             };
@@ -25,11 +61,30 @@ pub fn dispatch_mqtt_events(mqtt_event_dispatch_context: MqttEventDispatchContex
             let Some(event) = get_publish_value(event) else {
                 continue;
             };
-            log::debug!("收到原始MQTT#Publish事件: {event:?}");
+            let properties = event.properties.as_ref();
+            TraceContext::from_user_properties(properties.map_or(&[], |p| &p.user_properties))
+                .scope(handle_publish(&schemas, event))
+                .await;
+        }
+        log::info!("MQTT 事件分发已停止.");
+    })
+}
+
+/// 处理一条 publish 消息, 在消息的追踪范围内运行.
+async fn handle_publish(schemas: &TopicSchemas, event: v5::Publish) {
+    log::debug!(
+        "收到原始MQTT#Publish事件: topic={}, qos={:?}, payload={}",
+        String::from_utf8_lossy(&event.topic),
+        event.qos,
+        Redactor::global().redact_bytes(&event.payload)
+    );
 
+    match validate_publish(schemas, &event) {
+        Ok(()) => {
             // 调用业务逻辑处理.
         }
-    });
+        Err(e) => log::warn!("丢弃不合法的MQTT消息: {e}"),
+    }
 }
 
 /// 获取 publish 事件值
@@ -40,3 +95,65 @@ pub(crate) fn get_publish_value(event: Event) -> Option<v5::Publish> {
         None
     }
 }
+
+/// 如果主题注册了 Schema, 按 JSON 解析消息体并校验.
+fn validate_publish(schemas: &TopicSchemas, publish: &v5::Publish) -> anyhow::Result<()> {
+    let topic = String::from_utf8_lossy(&publish.topic);
+    let Some(schema) = schemas.get(&topic) else {
+        return Ok(());
+    };
+    let payload: Value = serde_json::from_slice(&publish.payload)
+        .map_err(|e| anyhow::anyhow!("主题 {topic} 的消息体不是合法的 JSON: {e}"))?;
+    schema
+        .validate(&payload)
+        .map_err(|e| anyhow::anyhow!("主题 {topic} {e}"))
+}
+
+/// 判断主题是否匹配过滤器, 支持 `+` (单层) 和 `#` (多层) 通配符.
+fn topic_matches(filter: &str, topic: &str) -> bool {
+    let mut topic_levels = topic.split('/');
+    for level in filter.split('/') {
+        match (level, topic_levels.next()) {
+            ("#", _) => return true,
+            ("+", Some(_)) => {}
+            (level, Some(t)) if level == t => {}
+            _ => return false,
+        }
+    }
+    topic_levels.next().is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rumqttc::v5::mqttbytes::QoS;
+    use serde_json::json;
+
+    fn publish(topic: &str, payload: &str) -> v5::Publish {
+        v5::Publish::new(topic, QoS::AtMostOnce, payload.to_owned(), None)
+    }
+
+    #[test]
+    fn topic_filters_support_wildcards() {
+        assert!(topic_matches("device/+/telemetry", "device/1/telemetry"));
+        assert!(!topic_matches("device/+/telemetry", "device/1/2/telemetry"));
+        assert!(topic_matches("device/#", "device/1/telemetry"));
+        assert!(topic_matches("device/#", "device"));
+        assert!(!topic_matches("device/1", "device/1/telemetry"));
+        assert!(!topic_matches("device/1/telemetry", "device/1"));
+    }
+
+    #[test]
+    fn validates_payload_of_matching_topics() {
+        let mut schemas = TopicSchemas::default();
+        let schema = json!({"type": "object", "required": ["value"]});
+        schemas.insert("device/+/telemetry", JsonSchema::compile(&schema).unwrap());
+
+        assert!(
+            validate_publish(&schemas, &publish("device/1/telemetry", r#"{"value": 1}"#)).is_ok()
+        );
+        assert!(validate_publish(&schemas, &publish("device/1/telemetry", "{}")).is_err());
+        assert!(validate_publish(&schemas, &publish("device/1/telemetry", "not json")).is_err());
+        assert!(validate_publish(&schemas, &publish("device/1/status", "not json")).is_ok());
+    }
+}
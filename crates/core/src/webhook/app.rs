@@ -0,0 +1,519 @@
+//! webhook 投递流程.
+
+use super::{DeliveryStatus, SendOutcome, WebhookDelivery, WebhookRepo, WebhookSender};
+use anyhow::{Context, Result};
+use chrono::{TimeDelta, Utc};
+use internal_shared::config::{ConfigErrors, Validate};
+use internal_shared::reqwest::RetryPolicy;
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+
+/// 锁定时间的上限, 1 天.
+const MAX_LEASE_MS: u64 = 86_400_000;
+
+/// 投递配置
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WebhookOptions {
+    /// 没有到期记录时, 多久查询一次, 毫秒
+    pub poll_interval_ms: u64,
+    /// 每次最多取出的记录数, 同一批并发发送
+    pub batch_size: usize,
+    /// 取出后锁定的时间, 毫秒, 应大于发送超时, 最多 1 天
+    pub lease_ms: u64,
+    /// 最多投递次数, 包括第一次
+    pub max_attempts: u32,
+    /// 第一次重试的间隔, 毫秒, 之后每次翻倍
+    pub base_delay_ms: u64,
+    /// 重试间隔的上限, 毫秒
+    pub max_delay_ms: u64,
+}
+
+impl Default for WebhookOptions {
+    fn default() -> Self {
+        Self {
+            poll_interval_ms: 1_000,
+            batch_size: 20,
+            lease_ms: 60_000,
+            max_attempts: 10,
+            base_delay_ms: 5_000,
+            max_delay_ms: 3_600_000,
+        }
+    }
+}
+
+impl Validate for WebhookOptions {
+    fn validate(&self, section: &str, errors: &mut ConfigErrors) {
+        errors.check(
+            self.poll_interval_ms > 0,
+            section,
+            "poll_interval_ms 必须大于 0",
+        );
+        errors.check(self.batch_size > 0, section, "batch_size 必须大于 0");
+        errors.check(
+            (1..=MAX_LEASE_MS).contains(&self.lease_ms),
+            section,
+            format!("lease_ms 必须在 1 到 {MAX_LEASE_MS} 之间"),
+        );
+        errors.check(self.max_attempts > 0, section, "max_attempts 必须大于 0");
+        errors.check(
+            self.base_delay_ms <= self.max_delay_ms,
+            section,
+            format!(
+                "base_delay_ms ({}) 不能大于 max_delay_ms ({})",
+                self.base_delay_ms, self.max_delay_ms
+            ),
+        );
+    }
+}
+
+/// webhook 投递器.
+///
+/// 创建后调用 [WebhookDispatcher::spawn] 启动后台投递.
+pub struct WebhookDispatcher {
+    repo: Arc<dyn WebhookRepo>,
+    sender: Arc<dyn WebhookSender>,
+    opt: WebhookOptions,
+    backoff: RetryPolicy,
+    notify: Notify,
+}
+
+impl WebhookDispatcher {
+    /// 创建投递器.
+    pub fn new(
+        repo: Arc<dyn WebhookRepo>,
+        sender: Arc<dyn WebhookSender>,
+        opt: WebhookOptions,
+    ) -> Self {
+        let backoff = RetryPolicy {
+            max_attempts: opt.max_attempts,
+            base_delay_ms: opt.base_delay_ms,
+            max_delay_ms: opt.max_delay_ms,
+            ..RetryPolicy::default()
+        };
+        Self {
+            repo,
+            sender,
+            opt,
+            backoff,
+            notify: Notify::new(),
+        }
+    }
+
+    /// 登记一次投递, 返回记录 ID. 记录保存后立即唤醒后台任务.
+    ///
+    /// # Arguments
+    ///
+    /// * `endpoint` - 接收方名称, 对应配置中的 endpoint.
+    /// * `event` - 事件类型.
+    /// * `payload` - 消息体.
+    ///
+    /// # Errors
+    ///
+    /// 保存失败时返回错误.
+    pub async fn enqueue(&self, endpoint: &str, event: &str, payload: &Value) -> Result<u64> {
+        let id = self
+            .repo
+            .insert(endpoint, event, &payload.to_string())
+            .await
+            .with_context(|| format!("保存 webhook {endpoint}/{event} 失败"))?;
+        log::debug!("已登记 webhook #{id}: {endpoint}/{event}");
+        self.notify.notify_one();
+        Ok(id)
+    }
+
+    /// 按 ID 查询投递记录.
+    ///
+    /// # Errors
+    ///
+    /// 查询失败时返回错误.
+    pub async fn get(&self, id: u64) -> Result<Option<WebhookDelivery>> {
+        self.repo.get(id).await
+    }
+
+    /// 按 ID 倒序查询投递记录.
+    ///
+    /// # Errors
+    ///
+    /// 查询失败时返回错误.
+    pub async fn list(
+        &self,
+        status: Option<DeliveryStatus>,
+        limit: usize,
+    ) -> Result<Vec<WebhookDelivery>> {
+        self.repo.list(status, limit).await
+    }
+
+    /// 重新投递死信, 记录不存在或不是死信时返回 `false`.
+    ///
+    /// # Errors
+    ///
+    /// 更新失败时返回错误.
+    pub async fn requeue(&self, id: u64) -> Result<bool> {
+        let requeued = self.repo.requeue(id).await?;
+        if requeued {
+            log::info!("webhook #{id} 已重新进入投递队列");
+            self.notify.notify_one();
+        }
+        Ok(requeued)
+    }
+
+    /// 启动后台投递.
+    ///
+    /// `shutdown` 取消后, 等正在投递的这一批完成后退出, 没有取出的记录留到下次启动后投递.
+    pub fn spawn(self: &Arc<Self>, shutdown: CancellationToken) -> JoinHandle<()> {
+        let this = self.clone();
+        let interval = Duration::from_millis(self.opt.poll_interval_ms);
+        tokio::spawn(async move {
+            while !shutdown.is_cancelled() {
+                let claimed = match this.run_once().await {
+                    Ok(n) => n,
+                    Err(e) => {
+                        log::error!("取出待投递的 webhook 失败: {e:?}");
+                        0
+                    }
+                };
+                // 取满一批说明可能还有到期的记录, 直接进入下一轮
+                if claimed < this.opt.batch_size {
+                    let wait = timeout(interval, this.notify.notified());
+                    let _ = shutdown.run_until_cancelled(wait).await;
+                }
+            }
+            log::info!("webhook 投递已停止.");
+        })
+    }
+
+    /// 取出一批到期的记录并发送, 返回取出的数量.
+    async fn run_once(self: &Arc<Self>) -> Result<usize> {
+        let lease = Duration::from_millis(self.opt.lease_ms);
+        let deliveries = self.repo.claim_due(self.opt.batch_size, lease).await?;
+        let claimed = deliveries.len();
+        let handles: Vec<_> = deliveries
+            .into_iter()
+            .map(|delivery| {
+                let this = self.clone();
+                tokio::spawn(async move { this.deliver(delivery).await })
+            })
+            .collect();
+        for handle in handles {
+            if let Err(e) = handle.await {
+                log::error!("投递 webhook 的任务异常退出: {e}");
+            }
+        }
+        Ok(claimed)
+    }
+
+    /// 发送一次并保存结果.
+    async fn deliver(&self, mut delivery: WebhookDelivery) {
+        delivery.attempts += 1;
+        let id = delivery.id;
+        let target = format!("{}/{}", delivery.endpoint, delivery.event);
+        match self.sender.send(&delivery).await {
+            SendOutcome::Delivered { status_code } => {
+                delivery.status = DeliveryStatus::Delivered;
+                delivery.last_status_code = Some(status_code);
+                delivery.last_error = None;
+                delivery.delivered_at = Some(Utc::now());
+                log::info!(
+                    "webhook #{id} {target} 投递成功, 第 {} 次",
+                    delivery.attempts
+                );
+            }
+            SendOutcome::Failed {
+                status_code,
+                error,
+                retryable,
+            } => {
+                if retryable && delivery.attempts < self.opt.max_attempts {
+                    let delay = self.backoff.backoff(delivery.attempts);
+                    delivery.next_attempt_at =
+                        Utc::now() + TimeDelta::from_std(delay).unwrap_or_default();
+                    log::warn!(
+                        "webhook #{id} {target} 第 {} 次投递失败: {error}, {delay:?} 后重试",
+                        delivery.attempts
+                    );
+                } else {
+                    delivery.status = DeliveryStatus::Dead;
+                    log::error!(
+                        "webhook #{id} {target} 第 {} 次投递失败: {error}, 已转为死信",
+                        delivery.attempts
+                    );
+                }
+                delivery.last_status_code = status_code;
+                delivery.last_error = Some(error);
+            }
+        }
+        // 保存失败时, 锁定时间过后会再次投递, 接收方需要按 ID 去重
+        if let Err(e) = self.repo.save_attempt(&delivery).await {
+            log::error!("保存 webhook #{id} 的投递结果失败: {e:?}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use chrono::DateTime;
+    use std::collections::{BTreeMap, VecDeque};
+    use std::sync::Mutex;
+
+    /// 保存在内存中的投递记录.
+    #[derive(Default)]
+    struct MemoryRepo {
+        rows: Mutex<BTreeMap<u64, WebhookDelivery>>,
+    }
+
+    #[async_trait]
+    impl WebhookRepo for MemoryRepo {
+        async fn insert(&self, endpoint: &str, event: &str, payload: &str) -> Result<u64> {
+            let mut rows = self.rows.lock().unwrap();
+            let id = rows.len() as u64 + 1;
+            let now = Utc::now();
+            rows.insert(
+                id,
+                WebhookDelivery {
+                    id,
+                    endpoint: endpoint.to_owned(),
+                    event: event.to_owned(),
+                    payload: payload.to_owned(),
+                    status: DeliveryStatus::Pending,
+                    attempts: 0,
+                    next_attempt_at: now,
+                    last_status_code: None,
+                    last_error: None,
+                    created_at: now,
+                    delivered_at: None,
+                },
+            );
+            Ok(id)
+        }
+
+        async fn claim_due(&self, limit: usize, lease: Duration) -> Result<Vec<WebhookDelivery>> {
+            let now = Utc::now();
+            let mut rows = self.rows.lock().unwrap();
+            let mut claimed = Vec::new();
+            for row in rows.values_mut() {
+                if claimed.len() < limit
+                    && row.status == DeliveryStatus::Pending
+                    && row.next_attempt_at <= now
+                {
+                    claimed.push(row.clone());
+                    row.next_attempt_at = now + TimeDelta::from_std(lease)?;
+                }
+            }
+            Ok(claimed)
+        }
+
+        async fn save_attempt(&self, delivery: &WebhookDelivery) -> Result<()> {
+            self.rows
+                .lock()
+                .unwrap()
+                .insert(delivery.id, delivery.clone());
+            Ok(())
+        }
+
+        async fn get(&self, id: u64) -> Result<Option<WebhookDelivery>> {
+            Ok(self.rows.lock().unwrap().get(&id).cloned())
+        }
+
+        async fn list(
+            &self,
+            status: Option<DeliveryStatus>,
+            limit: usize,
+        ) -> Result<Vec<WebhookDelivery>> {
+            let rows = self.rows.lock().unwrap();
+            Ok(rows
+                .values()
+                .rev()
+                .filter(|row| status.is_none_or(|s| row.status == s))
+                .take(limit)
+                .cloned()
+                .collect())
+        }
+
+        async fn requeue(&self, id: u64) -> Result<bool> {
+            let mut rows = self.rows.lock().unwrap();
+            match rows.get_mut(&id) {
+                Some(row) if row.status == DeliveryStatus::Dead => {
+                    row.status = DeliveryStatus::Pending;
+                    row.attempts = 0;
+                    row.next_attempt_at = Utc::now();
+                    Ok(true)
+                }
+                _ => Ok(false),
+            }
+        }
+    }
+
+    /// 按顺序返回预设的结果, 用完后一直返回最后一个.
+    struct ScriptedSender {
+        outcomes: Mutex<VecDeque<SendOutcome>>,
+    }
+
+    #[async_trait]
+    impl WebhookSender for ScriptedSender {
+        async fn send(&self, _delivery: &WebhookDelivery) -> SendOutcome {
+            let mut outcomes = self.outcomes.lock().unwrap();
+            if outcomes.len() > 1 {
+                outcomes.pop_front().unwrap()
+            } else {
+                outcomes[0].clone()
+            }
+        }
+    }
+
+    fn failed(status_code: u16, retryable: bool) -> SendOutcome {
+        SendOutcome::Failed {
+            status_code: Some(status_code),
+            error: format!("返回 {status_code}"),
+            retryable,
+        }
+    }
+
+    fn dispatcher(outcomes: Vec<SendOutcome>, opt: WebhookOptions) -> Arc<WebhookDispatcher> {
+        Arc::new(WebhookDispatcher::new(
+            Arc::new(MemoryRepo::default()),
+            Arc::new(ScriptedSender {
+                outcomes: Mutex::new(outcomes.into()),
+            }),
+            opt,
+        ))
+    }
+
+    /// 投递一次, 返回保存后的记录.
+    async fn deliver_once(dispatcher: &Arc<WebhookDispatcher>) -> WebhookDelivery {
+        let id = dispatcher
+            .enqueue("partner", "device.online", &serde_json::json!({"id": 1}))
+            .await
+            .unwrap();
+        assert_eq!(dispatcher.run_once().await.unwrap(), 1);
+        dispatcher.get(id).await.unwrap().unwrap()
+    }
+
+    fn between(at: DateTime<Utc>, from: TimeDelta, to: TimeDelta) -> bool {
+        let now = Utc::now();
+        // 留出测试执行的时间
+        at >= now + from - TimeDelta::milliseconds(500) && at <= now + to
+    }
+
+    #[test]
+    fn lease_is_bounded() {
+        for (lease_ms, valid) in [
+            (0, false),
+            (1, true),
+            (MAX_LEASE_MS, true),
+            (u64::MAX, false),
+        ] {
+            let opt = WebhookOptions {
+                lease_ms,
+                ..WebhookOptions::default()
+            };
+            let mut errors = ConfigErrors::default();
+            opt.validate("webhook.dispatcher", &mut errors);
+            assert_eq!(errors.is_empty(), valid, "{lease_ms}");
+        }
+    }
+
+    #[tokio::test]
+    async fn delivered() {
+        let dispatcher = dispatcher(
+            vec![SendOutcome::Delivered { status_code: 204 }],
+            WebhookOptions::default(),
+        );
+        let delivery = deliver_once(&dispatcher).await;
+        assert_eq!(delivery.status, DeliveryStatus::Delivered);
+        assert_eq!(delivery.attempts, 1);
+        assert_eq!(delivery.last_status_code, Some(204));
+        assert_eq!(delivery.last_error, None);
+        assert!(delivery.delivered_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn retryable_failure_is_rescheduled_with_backoff() {
+        let opt = WebhookOptions {
+            base_delay_ms: 60_000,
+            ..WebhookOptions::default()
+        };
+        let dispatcher = dispatcher(vec![failed(503, true)], opt);
+        let delivery = deliver_once(&dispatcher).await;
+        assert_eq!(delivery.status, DeliveryStatus::Pending);
+        assert_eq!(delivery.attempts, 1);
+        assert_eq!(delivery.last_status_code, Some(503));
+        assert_eq!(delivery.last_error.as_deref(), Some("返回 503"));
+        // 第一次重试的间隔在 base_delay_ms 的一半到全部之间
+        assert!(between(
+            delivery.next_attempt_at,
+            TimeDelta::seconds(30),
+            TimeDelta::seconds(60)
+        ));
+        // 还没有到期, 不会再次取出
+        assert_eq!(dispatcher.run_once().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn dead_after_max_attempts() {
+        let opt = WebhookOptions {
+            max_attempts: 3,
+            base_delay_ms: 0,
+            max_delay_ms: 0,
+            ..WebhookOptions::default()
+        };
+        let dispatcher = dispatcher(vec![failed(503, true)], opt);
+        let delivery = deliver_once(&dispatcher).await;
+        assert_eq!(delivery.status, DeliveryStatus::Pending);
+        assert_eq!(dispatcher.run_once().await.unwrap(), 1);
+        assert_eq!(dispatcher.run_once().await.unwrap(), 1);
+        let delivery = dispatcher.get(delivery.id).await.unwrap().unwrap();
+        assert_eq!(delivery.status, DeliveryStatus::Dead);
+        assert_eq!(delivery.attempts, 3);
+        assert_eq!(dispatcher.run_once().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn non_retryable_failure_is_dead_immediately() {
+        let dispatcher = dispatcher(vec![failed(400, false)], WebhookOptions::default());
+        let delivery = deliver_once(&dispatcher).await;
+        assert_eq!(delivery.status, DeliveryStatus::Dead);
+        assert_eq!(delivery.attempts, 1);
+        assert_eq!(delivery.last_status_code, Some(400));
+        let dead = dispatcher
+            .list(Some(DeliveryStatus::Dead), 10)
+            .await
+            .unwrap();
+        assert_eq!(dead, [delivery]);
+    }
+
+    #[tokio::test]
+    async fn requeue_only_dead_deliveries() {
+        let dispatcher = dispatcher(
+            vec![
+                failed(400, false),
+                SendOutcome::Delivered { status_code: 200 },
+            ],
+            WebhookOptions::default(),
+        );
+        let dead = deliver_once(&dispatcher).await;
+        let delivered = deliver_once(&dispatcher).await;
+        assert_eq!(delivered.status, DeliveryStatus::Delivered);
+
+        assert!(!dispatcher.requeue(delivered.id).await.unwrap());
+        assert!(!dispatcher.requeue(99).await.unwrap());
+        assert!(dispatcher.requeue(dead.id).await.unwrap());
+        let requeued = dispatcher.get(dead.id).await.unwrap().unwrap();
+        assert_eq!(requeued.status, DeliveryStatus::Pending);
+        assert_eq!(requeued.attempts, 0);
+
+        // 重新投递后成功
+        assert_eq!(dispatcher.run_once().await.unwrap(), 1);
+        let delivery = dispatcher.get(dead.id).await.unwrap().unwrap();
+        assert_eq!(delivery.status, DeliveryStatus::Delivered);
+        assert_eq!(delivery.attempts, 1);
+    }
+}
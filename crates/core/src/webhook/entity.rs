@@ -0,0 +1,76 @@
+//! webhook 投递记录
+
+use anyhow::bail;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// 投递状态.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    /// 等待投递, 包括等待重试.
+    Pending,
+    /// 已投递成功.
+    Delivered,
+    /// 不可重试的失败或次数用完, 不再自动投递.
+    Dead,
+}
+
+impl DeliveryStatus {
+    /// 持久化时使用的名称.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Delivered => "delivered",
+            Self::Dead => "dead",
+        }
+    }
+}
+
+impl fmt::Display for DeliveryStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DeliveryStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(Self::Pending),
+            "delivered" => Ok(Self::Delivered),
+            "dead" => Ok(Self::Dead),
+            v => bail!("未知的投递状态: {v}"),
+        }
+    }
+}
+
+/// 一次 webhook 投递.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct WebhookDelivery {
+    /// 唯一标识, 也作为接收方去重的依据.
+    pub id: u64,
+    /// 接收方名称, 对应配置中的 endpoint.
+    pub endpoint: String,
+    /// 事件类型, 如 `device.online`.
+    pub event: String,
+    /// 消息体 (JSON), 按原样发送.
+    pub payload: String,
+    /// 状态.
+    pub status: DeliveryStatus,
+    /// 已经尝试的次数.
+    pub attempts: u32,
+    /// 下一次投递的时间.
+    pub next_attempt_at: DateTime<Utc>,
+    /// 最后一次收到的状态码.
+    pub last_status_code: Option<u16>,
+    /// 最后一次失败的原因.
+    pub last_error: Option<String>,
+    /// 创建时间.
+    pub created_at: DateTime<Utc>,
+    /// 投递成功的时间.
+    pub delivered_at: Option<DateTime<Utc>>,
+}
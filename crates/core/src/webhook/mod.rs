@@ -0,0 +1,19 @@
+//! 向第三方投递 webhook.
+//!
+//! 业务代码通过 [WebhookDispatcher::enqueue] 登记要投递的事件, 记录先持久化 (见 [WebhookRepo]),
+//! 再由后台任务取出到期的记录, 通过 [WebhookSender] 发送:
+//!   - 成功: 标记为 `delivered`.
+//!   - 可以重试的失败 (网络错误、超时、429、5xx 等): 按指数间隔安排下一次投递, 重启后继续.
+//!   - 不可重试的失败, 或次数用完: 标记为 `dead` (死信), 需要人工处理后重新投递.
+//!
+//! 持久化和发送由 `ffi` 模块实现.
+
+mod app;
+mod entity;
+mod notifier;
+mod repo;
+
+pub use app::{WebhookDispatcher, WebhookOptions};
+pub use entity::{DeliveryStatus, WebhookDelivery};
+pub use notifier::{SendOutcome, WebhookSender};
+pub use repo::WebhookRepo;
@@ -0,0 +1,30 @@
+//! webhook 发送接口定义
+
+use super::WebhookDelivery;
+use async_trait::async_trait;
+
+/// 一次发送的结果.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SendOutcome {
+    /// 接收方返回 2xx.
+    Delivered {
+        /// 状态码.
+        status_code: u16,
+    },
+    /// 发送失败.
+    Failed {
+        /// 收到的状态码, 没有收到响应时为 `None`.
+        status_code: Option<u16>,
+        /// 失败原因.
+        error: String,
+        /// 是否可以重试. 如 4xx (408, 429 除外) 重试也不会成功.
+        retryable: bool,
+    },
+}
+
+/// 发送 webhook, 包括签名.
+#[async_trait]
+pub trait WebhookSender: Send + Sync {
+    /// 发送一次, 不在内部重试.
+    async fn send(&self, delivery: &WebhookDelivery) -> SendOutcome;
+}
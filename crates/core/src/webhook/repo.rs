@@ -0,0 +1,35 @@
+//! 投递记录的持久化接口定义
+
+use super::{DeliveryStatus, WebhookDelivery};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// 投递记录的持久化.
+#[async_trait]
+pub trait WebhookRepo: Send + Sync {
+    /// 新增一条待投递的记录, 返回它的 ID.
+    async fn insert(&self, endpoint: &str, event: &str, payload: &str) -> Result<u64>;
+
+    /// 取出最多 `limit` 条到期的 `pending` 记录, 并把它们的下一次投递时间推迟 `lease`.
+    ///
+    /// 推迟相当于加锁: 多个实例同时运行时不会取到同一条记录;
+    /// 进程在投递中途退出时, `lease` 过后记录会被重新取出.
+    async fn claim_due(&self, limit: usize, lease: Duration) -> Result<Vec<WebhookDelivery>>;
+
+    /// 保存一次投递后的状态、次数、下一次投递时间和结果.
+    async fn save_attempt(&self, delivery: &WebhookDelivery) -> Result<()>;
+
+    /// 按 ID 查询.
+    async fn get(&self, id: u64) -> Result<Option<WebhookDelivery>>;
+
+    /// 按 ID 倒序查询, `status` 为 `None` 时不限状态.
+    async fn list(
+        &self,
+        status: Option<DeliveryStatus>,
+        limit: usize,
+    ) -> Result<Vec<WebhookDelivery>>;
+
+    /// 将 `dead` 记录重置为 `pending`, 次数清零并立即投递. 记录不存在或不是 `dead` 时返回 `false`.
+    async fn requeue(&self, id: u64) -> Result<bool>;
+}
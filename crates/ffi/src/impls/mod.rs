@@ -1 +1,7 @@
 //! 实现 core 模块的 trait.
+
+mod webhook_repo;
+mod webhook_sender;
+
+pub use webhook_repo::MySqlWebhookRepo;
+pub use webhook_sender::{HttpWebhookSender, WebhookEndpoint};
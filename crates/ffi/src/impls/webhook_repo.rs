@@ -0,0 +1,180 @@
+//! 基于 `MySQL` 的 webhook 投递记录.
+//!
+//! 表结构见 `migrations/0001_create_webhook_deliveries.up.sql`, 需要 `MySQL` 8.0 以上
+//! (取出记录时使用 `FOR UPDATE SKIP LOCKED`). 时间都按 UTC 保存.
+
+use anyhow::{Context, Result, anyhow};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use internal_core::webhook::{DeliveryStatus, WebhookDelivery, WebhookRepo};
+use mysql_async::prelude::{FromValue, Queryable};
+use mysql_async::{Pool, Row, TxOpts, params};
+use std::time::Duration;
+
+/// 查询时的字段列表.
+const COLUMNS: &str = "id, endpoint, event, payload, status, attempts, next_attempt_at, \
+                       last_status_code, last_error, created_at, delivered_at";
+
+/// `last_error` 字段的长度上限 (字符).
+const ERROR_LEN: usize = 1024;
+
+/// 基于 `MySQL` 的投递记录.
+pub struct MySqlWebhookRepo {
+    pool: Pool,
+}
+
+impl MySqlWebhookRepo {
+    /// 使用连接池创建.
+    pub const fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl WebhookRepo for MySqlWebhookRepo {
+    async fn insert(&self, endpoint: &str, event: &str, payload: &str) -> Result<u64> {
+        let mut conn = self.pool.get_conn().await?;
+        conn.exec_drop(
+            "INSERT INTO webhook_deliveries (endpoint, event, payload, status, attempts, \
+             next_attempt_at, created_at) \
+             VALUES (:endpoint, :event, :payload, 'pending', 0, UTC_TIMESTAMP(3), UTC_TIMESTAMP(3))",
+            params! {"endpoint" => endpoint, "event" => event, "payload" => payload},
+        )
+        .await?;
+        conn.last_insert_id()
+            .ok_or_else(|| anyhow!("没有返回新增记录的 ID"))
+    }
+
+    async fn claim_due(&self, limit: usize, lease: Duration) -> Result<Vec<WebhookDelivery>> {
+        let mut tx = self.pool.start_transaction(TxOpts::default()).await?;
+        let ids: Vec<u64> = tx
+            .exec(
+                "SELECT id FROM webhook_deliveries \
+                 WHERE status = 'pending' AND next_attempt_at <= UTC_TIMESTAMP(3) \
+                 ORDER BY next_attempt_at LIMIT :limit FOR UPDATE SKIP LOCKED",
+                params! {"limit" => limit},
+            )
+            .await?;
+        if ids.is_empty() {
+            tx.commit().await?;
+            return Ok(Vec::new());
+        }
+
+        let placeholders = vec!["?"; ids.len()].join(", ");
+        let lease_ms = u64::try_from(lease.as_millis()).unwrap_or(u64::MAX);
+        let mut args = vec![lease_ms.saturating_mul(1000)];
+        args.extend(&ids);
+        tx.exec_drop(
+            format!(
+                "UPDATE webhook_deliveries \
+                 SET next_attempt_at = TIMESTAMPADD(MICROSECOND, ?, UTC_TIMESTAMP(3)) \
+                 WHERE id IN ({placeholders})"
+            ),
+            args,
+        )
+        .await?;
+        let rows: Vec<Row> = tx
+            .exec(
+                format!(
+                    "SELECT {COLUMNS} FROM webhook_deliveries WHERE id IN ({placeholders}) \
+                     ORDER BY next_attempt_at"
+                ),
+                ids,
+            )
+            .await?;
+        tx.commit().await?;
+        rows.into_iter().map(to_delivery).collect()
+    }
+
+    async fn save_attempt(&self, delivery: &WebhookDelivery) -> Result<()> {
+        let last_error = delivery
+            .last_error
+            .as_ref()
+            .map(|e| e.chars().take(ERROR_LEN).collect::<String>());
+        let mut conn = self.pool.get_conn().await?;
+        conn.exec_drop(
+            "UPDATE webhook_deliveries SET status = :status, attempts = :attempts, \
+             next_attempt_at = :next_attempt_at, last_status_code = :last_status_code, \
+             last_error = :last_error, delivered_at = :delivered_at WHERE id = :id",
+            params! {
+                "status" => delivery.status.as_str(),
+                "attempts" => delivery.attempts,
+                "next_attempt_at" => delivery.next_attempt_at.naive_utc(),
+                "last_status_code" => delivery.last_status_code,
+                "last_error" => last_error,
+                "delivered_at" => delivery.delivered_at.map(|t| t.naive_utc()),
+                "id" => delivery.id,
+            },
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get(&self, id: u64) -> Result<Option<WebhookDelivery>> {
+        let mut conn = self.pool.get_conn().await?;
+        let row: Option<Row> = conn
+            .exec_first(
+                format!("SELECT {COLUMNS} FROM webhook_deliveries WHERE id = :id"),
+                params! {"id" => id},
+            )
+            .await?;
+        row.map(to_delivery).transpose()
+    }
+
+    async fn list(
+        &self,
+        status: Option<DeliveryStatus>,
+        limit: usize,
+    ) -> Result<Vec<WebhookDelivery>> {
+        let mut conn = self.pool.get_conn().await?;
+        let rows: Vec<Row> = conn
+            .exec(
+                format!(
+                    "SELECT {COLUMNS} FROM webhook_deliveries \
+                     WHERE (:status IS NULL OR status = :status) ORDER BY id DESC LIMIT :limit"
+                ),
+                params! {"status" => status.map(DeliveryStatus::as_str), "limit" => limit},
+            )
+            .await?;
+        rows.into_iter().map(to_delivery).collect()
+    }
+
+    async fn requeue(&self, id: u64) -> Result<bool> {
+        let mut conn = self.pool.get_conn().await?;
+        conn.exec_drop(
+            "UPDATE webhook_deliveries SET status = 'pending', attempts = 0, \
+             next_attempt_at = UTC_TIMESTAMP(3) WHERE id = :id AND status = 'dead'",
+            params! {"id" => id},
+        )
+        .await?;
+        Ok(conn.affected_rows() > 0)
+    }
+}
+
+/// 将查询结果转换为投递记录.
+fn to_delivery(mut row: Row) -> Result<WebhookDelivery> {
+    Ok(WebhookDelivery {
+        id: column(&mut row, "id")?,
+        endpoint: column(&mut row, "endpoint")?,
+        event: column(&mut row, "event")?,
+        payload: column(&mut row, "payload")?,
+        status: column::<String>(&mut row, "status")?.parse()?,
+        attempts: column(&mut row, "attempts")?,
+        next_attempt_at: utc(column(&mut row, "next_attempt_at")?),
+        last_status_code: column(&mut row, "last_status_code")?,
+        last_error: column(&mut row, "last_error")?,
+        created_at: utc(column(&mut row, "created_at")?),
+        delivered_at: column::<Option<NaiveDateTime>>(&mut row, "delivered_at")?.map(utc),
+    })
+}
+
+/// 取出字段值.
+fn column<T: FromValue>(row: &mut Row, name: &str) -> Result<T> {
+    row.take_opt(name)
+        .ok_or_else(|| anyhow!("缺少字段: {name}"))?
+        .with_context(|| format!("解析 {name} 字段错误"))
+}
+
+fn utc(time: NaiveDateTime) -> DateTime<Utc> {
+    time.and_utc()
+}
@@ -3,29 +3,38 @@
 //! 调用外部系统 (FFI, gRPC, 数据库访问, HTTP 客户端等)
 
 pub mod impls;
+pub mod migrate;
 pub mod mqtt_client;
 mod mysql_client;
 mod redis_client;
 
-use crate::mqtt_client::{MQTTV5Client, MqttClientOptions};
-use crate::mysql_client::MySQLOptions;
-use crate::redis_client::RedisOptions;
+pub use crate::mysql_client::MySQLOptions;
+pub use crate::redis_client::RedisOptions;
+
+use crate::mqtt_client::{MQTTV5Client, MqttClientOptions, MqttSubscriptions};
 use anyhow::Result;
 use redis::Client;
 use rumqttc::v5::{AsyncClient, Event};
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 
-/// 初始化 Mqtt 客户端.
+/// 初始化 Mqtt 客户端, 返回值见 [MQTTV5Client::connect].
 ///
 /// # Arguments
 ///
-/// * `path` - 配置文件路径, 包含 MQTT 客户端的连接信息.
+/// * `opt` - MQTT 客户端的连接信息, 见 [MqttClientOptions::from_file].
 ///
 /// # Errors
 ///
-/// 如果读取配置文件失败, 或者 MQTT 客户端初始化失败, 会返回相应的错误.
-pub async fn init_mqtt_client(path: &str) -> Result<(AsyncClient, mpsc::Receiver<Event>)> {
-    let opt = MqttClientOptions::from_file(path)?;
+/// 如果 MQTT 客户端初始化失败, 会返回相应的错误.
+pub async fn init_mqtt_client(
+    opt: MqttClientOptions,
+) -> Result<(
+    AsyncClient,
+    mpsc::Receiver<Event>,
+    MqttSubscriptions,
+    JoinHandle<()>,
+)> {
     MQTTV5Client::connect(opt).await
 }
 
@@ -33,13 +42,12 @@ pub async fn init_mqtt_client(path: &str) -> Result<(AsyncClient, mpsc::Receiver
 ///
 /// # Arguments
 ///
-/// * `path` - 配置文件路径, 包含 Redis 连接信息.
+/// * `opt` - Redis 连接信息, 见 [RedisOptions::from_file].
 ///
 /// # Errors
 ///
-/// 如果读取配置文件失败, 或者创建连接池失败, 会返回相应的错误.
-pub fn init_redis(path: &str) -> Result<r2d2::Pool<Client>> {
-    let opt = RedisOptions::from_file(path)?;
+/// 如果创建连接池失败, 会返回相应的错误.
+pub fn init_redis(opt: RedisOptions) -> Result<r2d2::Pool<Client>> {
     redis_client::create_connection_pool(opt)
 }
 
@@ -47,12 +55,11 @@ pub fn init_redis(path: &str) -> Result<r2d2::Pool<Client>> {
 ///
 /// # Arguments
 ///
-/// * `path` - 配置文件路径, 包含 `MySQL` 连接信息.
+/// * `opt` - `MySQL` 连接信息, 见 [MySQLOptions::from_file].
 ///
 /// # Errors
 ///
-/// 如果读取配置文件失败, 或者创建连接池失败, 会返回相应的错误.
-pub fn init_mysql(path: &str) -> Result<mysql_async::Pool> {
-    let opt = MySQLOptions::from_file(path)?;
+/// 如果创建连接池失败, 会返回相应的错误.
+pub fn init_mysql(opt: MySQLOptions) -> Result<mysql_async::Pool> {
     mysql_client::create_connection_pool(opt)
 }
@@ -0,0 +1,354 @@
+//! 数据库迁移.
+//!
+//! 迁移文件放在同一个目录中, 文件名为 `{版本}_{名称}.up.sql` 和 `{版本}_{名称}.down.sql`,
+//! 如 `0001_create_webhook_deliveries.up.sql`. 版本是数字, 按从小到大执行, `down` 文件可以没有.
+//!
+//! 已执行的版本记录在 `schema_migrations` 表中. 执行期间持有 `MySQL` 的命名锁,
+//! 多个实例同时执行时只有一个生效.
+//!
+//! 注意 `MySQL` 的 DDL 不能回滚, 一个文件中的语句执行到一半失败时, 需要手动处理后再执行.
+
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use mysql_async::prelude::Queryable;
+use mysql_async::{Conn, Pool, params};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// 迁移锁的名称.
+const LOCK_NAME: &str = "schema_migrations";
+/// 等待迁移锁的秒数.
+const LOCK_TIMEOUT_SECS: u32 = 10;
+
+/// 一个迁移.
+#[derive(Debug, Clone)]
+pub struct Migration {
+    /// 版本.
+    pub version: u64,
+    /// 名称.
+    pub name: String,
+    /// 升级 SQL.
+    pub up: String,
+    /// 回滚 SQL, 没有 `down` 文件时为 `None`.
+    pub down: Option<String>,
+}
+
+/// 迁移的执行状态.
+#[derive(Debug, Clone)]
+pub struct MigrationStatus {
+    /// 版本.
+    pub version: u64,
+    /// 名称.
+    pub name: String,
+    /// 执行时间, 没有执行时为 `None`.
+    pub applied_at: Option<DateTime<Utc>>,
+    /// 数据库中有记录, 但是目录中没有对应的文件.
+    pub missing: bool,
+}
+
+/// 读取目录中的迁移文件, 按版本排序.
+///
+/// # Errors
+///
+/// 目录无法读取, 文件名格式错误, 版本重复或者只有 `down` 文件时返回错误.
+pub fn load_migrations(dir: &Path) -> Result<Vec<Migration>> {
+    let entries =
+        fs::read_dir(dir).with_context(|| format!("读取迁移目录 {} 失败", dir.display()))?;
+    let mut ups = BTreeMap::new();
+    let mut downs = BTreeMap::new();
+    for entry in entries {
+        let path = entry?.path();
+        let Some(file_name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let (stem, is_up) = if let Some(stem) = file_name.strip_suffix(".up.sql") {
+            (stem, true)
+        } else if let Some(stem) = file_name.strip_suffix(".down.sql") {
+            (stem, false)
+        } else {
+            continue;
+        };
+        let (version, name) = stem
+            .split_once('_')
+            .and_then(|(v, n)| Some((v.parse::<u64>().ok()?, n)))
+            .ok_or_else(|| anyhow!("迁移文件名 {file_name} 格式错误, 应为 NNNN_name.up.sql"))?;
+        let sql =
+            fs::read_to_string(&path).with_context(|| format!("读取 {} 失败", path.display()))?;
+        let files = if is_up { &mut ups } else { &mut downs };
+        if let Some((other, _)) = files.insert(version, (name.to_owned(), sql)) {
+            bail!("迁移版本 {version} 重复: {other} 和 {name}");
+        }
+    }
+
+    if let Some((version, (name, _))) = downs.iter().find(|(v, _)| !ups.contains_key(v)) {
+        bail!("迁移 {version:04}_{name} 只有 down 文件");
+    }
+    Ok(ups
+        .into_iter()
+        .map(|(version, (name, up))| Migration {
+            version,
+            down: downs.remove(&version).map(|(_, sql)| sql),
+            name,
+            up,
+        })
+        .collect())
+}
+
+/// 在 `MySQL` 中执行迁移.
+pub struct MySqlMigrator {
+    pool: Pool,
+}
+
+impl MySqlMigrator {
+    /// 使用连接池创建.
+    pub const fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+
+    /// 按顺序执行没有执行过的迁移, 返回执行的版本.
+    ///
+    /// # Arguments
+    ///
+    /// * `migrations` - 全部迁移, 见 [load_migrations].
+    /// * `steps` - 最多执行几个, `None` 表示全部.
+    ///
+    /// # Errors
+    ///
+    /// 连接失败, 没有拿到迁移锁或者执行 SQL 失败时返回错误, 之前执行成功的迁移仍然有效.
+    pub async fn up(&self, migrations: &[Migration], steps: Option<usize>) -> Result<Vec<u64>> {
+        let mut conn = self.locked_conn().await?;
+        let result = async {
+            let applied = applied(&mut conn).await?;
+            let mut done = Vec::new();
+            let pending = migrations
+                .iter()
+                .filter(|m| !applied.contains_key(&m.version))
+                .take(steps.unwrap_or(usize::MAX));
+            for migration in pending {
+                let label = format!("{:04}_{}", migration.version, migration.name);
+                conn.query_drop(&migration.up)
+                    .await
+                    .with_context(|| format!("执行迁移 {label} 失败"))?;
+                conn.exec_drop(
+                    "INSERT INTO schema_migrations (version, name, applied_at) \
+                     VALUES (:version, :name, UTC_TIMESTAMP(3))",
+                    params! {"version" => migration.version, "name" => &migration.name},
+                )
+                .await?;
+                log::info!("已执行迁移 {label}");
+                done.push(migration.version);
+            }
+            anyhow::Ok(done)
+        }
+        .await;
+        unlock(&mut conn).await;
+        result
+    }
+
+    /// 按执行的倒序回滚最近的 `steps` 个迁移, 返回回滚的版本.
+    ///
+    /// # Errors
+    ///
+    /// 连接失败, 没有拿到迁移锁, 迁移没有 `down` 文件或者执行 SQL 失败时返回错误,
+    /// 之前回滚成功的迁移仍然有效.
+    pub async fn down(&self, migrations: &[Migration], steps: usize) -> Result<Vec<u64>> {
+        let mut conn = self.locked_conn().await?;
+        let result = async {
+            let applied = applied(&mut conn).await?;
+            let mut done = Vec::new();
+            for (version, (name, _)) in applied.iter().rev().take(steps) {
+                let label = format!("{version:04}_{name}");
+                let down = migrations
+                    .iter()
+                    .find(|m| m.version == *version)
+                    .ok_or_else(|| anyhow!("迁移 {label} 的文件不存在"))?
+                    .down
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("迁移 {label} 没有 down 文件, 不能回滚"))?;
+                conn.query_drop(down)
+                    .await
+                    .with_context(|| format!("回滚迁移 {label} 失败"))?;
+                conn.exec_drop(
+                    "DELETE FROM schema_migrations WHERE version = :version",
+                    params! {"version" => version},
+                )
+                .await?;
+                log::info!("已回滚迁移 {label}");
+                done.push(*version);
+            }
+            anyhow::Ok(done)
+        }
+        .await;
+        unlock(&mut conn).await;
+        result
+    }
+
+    /// 所有迁移的执行状态, 按版本排序.
+    ///
+    /// # Errors
+    ///
+    /// 连接或查询失败时返回错误.
+    pub async fn status(&self, migrations: &[Migration]) -> Result<Vec<MigrationStatus>> {
+        let mut conn = self.pool.get_conn().await?;
+        ensure_table(&mut conn).await?;
+        let mut applied = applied(&mut conn).await?;
+        let mut status: Vec<_> = migrations
+            .iter()
+            .map(|m| MigrationStatus {
+                version: m.version,
+                name: m.name.clone(),
+                applied_at: applied.remove(&m.version).map(|(_, at)| at),
+                missing: false,
+            })
+            .collect();
+        status.extend(
+            applied
+                .into_iter()
+                .map(|(version, (name, at))| MigrationStatus {
+                    version,
+                    name,
+                    applied_at: Some(at),
+                    missing: true,
+                }),
+        );
+        status.sort_by_key(|s| s.version);
+        Ok(status)
+    }
+
+    /// 获取连接, 创建记录表并加锁.
+    async fn locked_conn(&self) -> Result<Conn> {
+        let mut conn = self.pool.get_conn().await?;
+        ensure_table(&mut conn).await?;
+        let locked: Option<Option<i64>> = conn
+            .exec_first(
+                "SELECT GET_LOCK(:name, :timeout)",
+                params! {"name" => LOCK_NAME, "timeout" => LOCK_TIMEOUT_SECS},
+            )
+            .await?;
+        if locked.flatten() != Some(1) {
+            bail!("{LOCK_TIMEOUT_SECS} 秒内没有拿到迁移锁, 可能有其他实例正在执行迁移");
+        }
+        Ok(conn)
+    }
+}
+
+/// 创建记录已执行版本的表.
+async fn ensure_table(conn: &mut Conn) -> Result<()> {
+    conn.query_drop(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version    BIGINT UNSIGNED NOT NULL,
+            name       VARCHAR(255)    NOT NULL,
+            applied_at DATETIME(3)     NOT NULL,
+            PRIMARY KEY (version)
+        ) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = '已执行的数据库迁移'",
+    )
+    .await?;
+    Ok(())
+}
+
+/// 已执行的版本, 值为名称和执行时间.
+async fn applied(conn: &mut Conn) -> Result<BTreeMap<u64, (String, DateTime<Utc>)>> {
+    let rows: Vec<(u64, String, NaiveDateTime)> = conn
+        .query("SELECT version, name, applied_at FROM schema_migrations")
+        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(version, name, at)| (version, (name, at.and_utc())))
+        .collect())
+}
+
+/// 释放迁移锁, 失败时只记录日志, 连接断开时锁也会释放.
+async fn unlock(conn: &mut Conn) {
+    if let Err(e) = conn
+        .exec_drop("DO RELEASE_LOCK(:name)", params! {"name" => LOCK_NAME})
+        .await
+    {
+        log::warn!("释放迁移锁失败: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// 在临时目录中创建迁移文件.
+    fn dir_with(test: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("migrate_{test}_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for name in files {
+            fs::write(dir.join(name), format!("-- {name}")).unwrap();
+        }
+        dir
+    }
+
+    fn load(test: &str, files: &[&str]) -> Result<Vec<Migration>> {
+        let dir = dir_with(test, files);
+        let result = load_migrations(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        result
+    }
+
+    #[test]
+    fn sorts_by_version_and_pairs_down_files() {
+        let migrations = load(
+            "sorts",
+            &[
+                "0010_add_index.up.sql",
+                "0002_create_devices.up.sql",
+                "0002_create_devices.down.sql",
+                "0001_create_webhooks.up.sql",
+                "README.md",
+            ],
+        )
+        .unwrap();
+        let versions: Vec<_> = migrations
+            .iter()
+            .map(|m| (m.version, m.name.as_str()))
+            .collect();
+        assert_eq!(
+            versions,
+            [
+                (1, "create_webhooks"),
+                (2, "create_devices"),
+                (10, "add_index")
+            ]
+        );
+        assert_eq!(migrations[1].up, "-- 0002_create_devices.up.sql");
+        assert_eq!(
+            migrations[1].down.as_deref(),
+            Some("-- 0002_create_devices.down.sql")
+        );
+        // 没有 down 文件也可以
+        assert!(migrations[0].down.is_none());
+        assert!(migrations[2].down.is_none());
+    }
+
+    #[test]
+    fn rejects_bad_file_names() {
+        for name in ["create.up.sql", "v1_create.up.sql", "0001.down.sql"] {
+            let err = load("bad_name", &[name]).unwrap_err();
+            assert!(err.to_string().contains("格式错误"), "{name}: {err}");
+        }
+    }
+
+    #[test]
+    fn rejects_duplicate_versions() {
+        let err = load("duplicate", &["0001_a.up.sql", "1_b.up.sql"]).unwrap_err();
+        assert!(err.to_string().contains("迁移版本 1 重复"), "{err}");
+    }
+
+    #[test]
+    fn rejects_down_only_files() {
+        let err = load("down_only", &["0001_a.up.sql", "0002_b.down.sql"]).unwrap_err();
+        assert_eq!(err.to_string(), "迁移 0002_b 只有 down 文件");
+    }
+
+    #[test]
+    fn missing_directory_is_an_error() {
+        let dir = std::env::temp_dir().join("migrate_missing_dir_does_not_exist");
+        assert!(load_migrations(&dir).is_err());
+    }
+}
@@ -1,21 +1,24 @@
 //! 用来创建 MQTT 客户端.
 
 use anyhow::Result;
-use internal_shared::yaml::from_yaml_file;
+use internal_shared::config::{ConfigErrors, Validate};
+use internal_shared::trace::TraceContext;
+use internal_shared::yaml::from_layered_yaml;
 use rumqttc::{
-    Error,
+    Error, Outgoing,
     v5::{
         Event,
-        mqttbytes::v5::Packet,
+        mqttbytes::v5::{Packet, PublishProperties},
         {AsyncClient, MqttOptions, mqttbytes::QoS},
     },
 };
 use serde::Deserialize;
+use std::sync::{Arc, Mutex, PoisonError};
 use std::time::Duration;
-use tokio::{sync::mpsc, time::sleep};
+use tokio::{sync::mpsc, task::JoinHandle, time::sleep};
 
 /// MQTT 客户端信息
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct MqttClientOptions {
     /// 客户端 ID
     pub id: String,
@@ -30,9 +33,14 @@ pub struct MqttClientOptions {
     /// 有界异步通道的容量
     pub channel_cap: usize,
     /// 要订阅的主题
-    ///
-    /// 订阅主题 QOS:0, 由程序控制是否接收消息
     pub subscribes: Vec<String>,
+    /// 订阅主题使用的 QoS, 只能是 0、1 或 2, 默认 1
+    #[serde(default = "default_qos")]
+    pub qos: u8,
+}
+
+const fn default_qos() -> u8 {
+    1
 }
 impl MqttClientOptions {
     /// 从 YAML 文件加载 MQTT 客户端配置
@@ -43,11 +51,65 @@ impl MqttClientOptions {
     /// # 返回值
     /// 解析后的 MQTT 客户端配置
     ///
+    /// 支持 `mqtt.{APP_ENV}.yaml` 覆盖和 `MQTT__*` 环境变量覆盖, 见 `from_layered_yaml`.
+    ///
     /// # Errors
     /// 当文件不存在、无法读取或 YAML 格式解析失败时，返回包含相应错误信息的 `anyhow::Error`
     #[allow(dead_code)]
     pub fn from_file(path: &str) -> Result<Self> {
-        from_yaml_file(path)
+        from_layered_yaml(path, "MQTT")
+    }
+}
+
+impl Validate for MqttClientOptions {
+    fn validate(&self, section: &str, errors: &mut ConfigErrors) {
+        errors.check(!self.id.trim().is_empty(), section, "id 不能为空");
+        errors.check(!self.host.trim().is_empty(), section, "host 不能为空");
+        errors.check(self.port != 0, section, "port 不能为 0");
+        errors.check(self.channel_cap > 0, section, "channel_cap 必须大于 0");
+        errors.check(
+            MQTTV5Client::qos(self.qos).is_ok(),
+            section,
+            format!("qos ({}) 只能是 0、1 或 2", self.qos),
+        );
+    }
+}
+
+/// 当前订阅的主题.
+///
+/// 断线重连后按这里记录的主题恢复订阅, 通过 [MqttSubscriptions::update] 修改时同步生效.
+#[derive(Clone)]
+pub struct MqttSubscriptions {
+    client: AsyncClient,
+    topics: Arc<Mutex<Vec<String>>>,
+    qos: QoS,
+}
+
+impl MqttSubscriptions {
+    /// 当前订阅的主题.
+    pub fn topics(&self) -> Vec<String> {
+        self.topics
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// 替换订阅列表: 订阅新增的主题, 取消已移除的主题.
+    ///
+    /// # Errors
+    /// 发送订阅或取消订阅请求失败时返回错误, 此时记录的主题已经更新, 重连后按新列表恢复.
+    pub async fn update(&self, topics: Vec<String>) -> Result<()> {
+        let old = std::mem::replace(
+            &mut *self.topics.lock().unwrap_or_else(PoisonError::into_inner),
+            topics.clone(),
+        );
+        for topic in old.iter().filter(|t| !topics.contains(t)) {
+            self.client.unsubscribe(topic).await?;
+        }
+        for topic in topics.iter().filter(|t| !old.contains(t)) {
+            self.client.subscribe(topic, self.qos).await?;
+        }
+        Ok(())
     }
 }
 
@@ -55,7 +117,10 @@ impl MqttClientOptions {
 pub struct MQTTV5Client;
 #[allow(dead_code)]
 impl MQTTV5Client {
-    /// 连接到 MQTT 服务器并返回异步客户端和事件接收器
+    /// 连接到 MQTT 服务器并返回异步客户端、事件接收器、订阅列表和事件循环任务
+    ///
+    /// 调用 `AsyncClient::disconnect` 后, 事件循环发出 DISCONNECT 报文并结束,
+    /// 等待事件循环任务即可确认已断开.
     ///
     /// # 参数
     /// * `client_info` - 包含客户端配置信息的 `MqttClientOptions` 结构体
@@ -66,7 +131,12 @@ impl MQTTV5Client {
     /// - 创建异步通道失败时返回错误
     pub async fn connect(
         client_info: MqttClientOptions,
-    ) -> Result<(AsyncClient, mpsc::Receiver<Event>)> {
+    ) -> Result<(
+        AsyncClient,
+        mpsc::Receiver<Event>,
+        MqttSubscriptions,
+        JoinHandle<()>,
+    )> {
         let mut options = MqttOptions::new(client_info.id, client_info.host, client_info.port);
         options.set_keep_alive(Duration::from_secs(10));
         options.set_clean_start(true);
@@ -74,23 +144,31 @@ impl MQTTV5Client {
         options.set_max_packet_size(Some(1_048_576)); // 1048576Byte = 1MB
         options.set_credentials(client_info.user_name, client_info.pass_word);
 
+        let qos = Self::qos(client_info.qos)?;
         let (client, mut event_loop) = AsyncClient::new(options, client_info.channel_cap);
-        let restore_subs = client_info.subscribes.clone();
-        let restore_client = client.clone();
-        for ele in client_info.subscribes {
-            client.subscribe(ele, QoS::AtLeastOnce).await?;
+        for ele in &client_info.subscribes {
+            client.subscribe(ele, qos).await?;
         }
+        let subscriptions = MqttSubscriptions {
+            client: client.clone(),
+            topics: Arc::new(Mutex::new(client_info.subscribes)),
+            qos,
+        };
+        let restore_subs = subscriptions.clone();
 
         let (tx, event_rx) = mpsc::channel::<Event>(client_info.channel_cap);
-        tokio::spawn(async move {
+        let event_loop = tokio::spawn(async move {
             loop {
                 match event_loop.poll().await {
+                    Ok(Event::Outgoing(Outgoing::Disconnect)) => {
+                        log::info!("MQTT 已断开连接.");
+                        break;
+                    }
                     Ok(event) => {
                         if let Event::Incoming(Packet::ConnAck(_ack)) = &event {
                             log::debug!("MQTT 已连接, 开始恢复订阅.");
-                            for t in &restore_subs {
-                                if let Err(e) = restore_client.subscribe(t, QoS::AtLeastOnce).await
-                                {
+                            for t in restore_subs.topics() {
+                                if let Err(e) = restore_subs.client.subscribe(&t, qos).await {
                                     log::error!("重连后订阅 {t} 失败: {e:?}");
                                 }
                             }
@@ -108,7 +186,44 @@ impl MQTTV5Client {
             }
         });
 
-        Ok((client, event_rx))
+        Ok((client, event_rx, subscriptions, event_loop))
+    }
+
+    /// 发布消息, 在追踪范围内时把 `x-request-id` 和 `traceparent` 放在用户属性中.
+    ///
+    /// # 参数
+    /// * `client` - MQTT 客户端
+    /// * `topic` - 主题
+    /// * `qos` - 发布使用的 QoS
+    /// * `retain` - 是否保留消息
+    /// * `payload` - 消息体
+    ///
+    /// # Errors
+    /// 请求无法放入发送队列时返回错误
+    pub async fn publish(
+        client: &AsyncClient,
+        topic: impl Into<String>,
+        qos: QoS,
+        retain: bool,
+        payload: impl Into<Vec<u8>>,
+    ) -> Result<()> {
+        let user_properties = TraceContext::current()
+            .map(|context| {
+                context
+                    .propagation()
+                    .into_iter()
+                    .map(|(k, v)| (k.to_owned(), v))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let properties = PublishProperties {
+            user_properties,
+            ..PublishProperties::default()
+        };
+        client
+            .publish_with_properties(topic, qos, retain, payload.into(), properties)
+            .await?;
+        Ok(())
     }
 
     /// 判断和返回 v5.0 的 qos
@@ -57,12 +57,13 @@
 //! ```
 
 use anyhow::{Result, anyhow};
-use internal_shared::yaml::from_yaml_file;
+use internal_shared::config::{ConfigErrors, Validate};
+use internal_shared::yaml::from_layered_yaml;
 use mysql_async::{Compression, Opts, OptsBuilder, Pool, PoolConstraints, PoolOpts};
 use serde::Deserialize;
 
 /// mysql 配置
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct MySQLOptions {
     /// 主机
     pub host: String,
@@ -83,8 +84,27 @@ pub struct MySQLOptions {
 }
 impl MySQLOptions {
     /// 从文件加载配置.
+    ///
+    /// 支持 `mysql.{APP_ENV}.yaml` 覆盖和 `MYSQL__*` 环境变量覆盖, 见 `from_layered_yaml`.
     pub fn from_file(path: &str) -> Result<Self> {
-        from_yaml_file(path)
+        from_layered_yaml(path, "MYSQL")
+    }
+}
+
+impl Validate for MySQLOptions {
+    fn validate(&self, section: &str, errors: &mut ConfigErrors) {
+        errors.check(!self.host.trim().is_empty(), section, "host 不能为空");
+        errors.check(self.port != 0, section, "port 不能为 0");
+        errors.check(!self.db_name.trim().is_empty(), section, "db_name 不能为空");
+        errors.check(self.pool_max > 0, section, "pool_max 必须大于 0");
+        errors.check(
+            self.pool_min <= self.pool_max,
+            section,
+            format!(
+                "pool_min ({}) 不能大于 pool_max ({})",
+                self.pool_min, self.pool_max
+            ),
+        );
     }
 }
 
@@ -15,13 +15,14 @@
 mod script;
 
 use anyhow::Result;
-use internal_shared::yaml::from_yaml_file;
+use internal_shared::config::{ConfigErrors, Validate};
+use internal_shared::yaml::from_layered_yaml;
 use r2d2::Pool;
 use redis::{Client, ConnectionAddr, ConnectionInfo, ProtocolVersion, RedisConnectionInfo};
 use serde::Deserialize;
 
 /// redis 配置
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct RedisOptions {
     /// 主机
     pub host: String,
@@ -38,8 +39,31 @@ pub struct RedisOptions {
 }
 impl RedisOptions {
     /// 从文件加载配置.
+    ///
+    /// 支持 `redis.{APP_ENV}.yaml` 覆盖和 `REDIS__*` 环境变量覆盖, 见 `from_layered_yaml`.
     pub fn from_file(path: &str) -> Result<Self> {
-        from_yaml_file(path)
+        from_layered_yaml(path, "REDIS")
+    }
+}
+
+impl Validate for RedisOptions {
+    fn validate(&self, section: &str, errors: &mut ConfigErrors) {
+        errors.check(!self.host.trim().is_empty(), section, "host 不能为空");
+        errors.check(self.port != 0, section, "port 不能为 0");
+        errors.check(
+            self.db >= 0,
+            section,
+            format!("db ({}) 不能为负数", self.db),
+        );
+        errors.check(self.pool_max > 0, section, "pool_max 必须大于 0");
+        errors.check(
+            self.pool_min <= self.pool_max,
+            section,
+            format!(
+                "pool_min ({}) 不能大于 pool_max ({})",
+                self.pool_min, self.pool_max
+            ),
+        );
     }
 }
 
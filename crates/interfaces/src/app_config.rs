@@ -0,0 +1,381 @@
+//! 应用程序配置.
+//!
+//! 所有配置在启动时一次性加载和校验, 有错误时统一报告, 不会建立任何连接.
+
+use anyhow::{Context, Result, anyhow};
+use internal_core::webhook::WebhookOptions;
+use internal_ffi::impls::WebhookEndpoint;
+use internal_ffi::mqtt_client::MqttClientOptions;
+use internal_ffi::{MySQLOptions, RedisOptions};
+use internal_shared::config::{ConfigErrors, Validate};
+use internal_shared::flexi_logger::LogOptions;
+use internal_shared::json::JsonSchema;
+use internal_shared::reqwest::HttpClientOptions;
+use internal_shared::yaml::from_layered_yaml;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::env;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+/// 默认配置目录.
+const DEFAULT_CONFIG_DIR: &str = "./config";
+/// 管理接口令牌的最短长度.
+const MIN_ADMIN_TOKEN_LEN: usize = 16;
+/// 线程栈大小的下限, 字节, 太小时线程启动后很快会栈溢出.
+const MIN_THREAD_STACK_SIZE: usize = 64 * 1024;
+
+/// 整个应用程序的配置.
+#[derive(Debug, Clone)]
+pub struct AppConfig {
+    /// HTTP 服务
+    pub http: HttpConfig,
+    /// Tokio 运行时
+    pub runtime: RuntimeConfig,
+    /// 日志
+    pub logging: LogOptions,
+    /// 调用上游接口的 HTTP 客户端, 键为客户端名称
+    pub http_clients: BTreeMap<String, HttpClientOptions>,
+    /// webhook 投递
+    pub webhook: WebhookConfig,
+    /// MQTT 消息体和 HTTP 请求体的 JSON Schema
+    pub schemas: SchemaConfig,
+    /// `MySQL`
+    pub mysql: MySQLOptions,
+    /// Redis
+    pub redis: RedisOptions,
+    /// MQTT
+    pub mqtt: MqttClientOptions,
+}
+
+/// `app.yaml` 的内容.
+#[derive(Debug, Deserialize)]
+struct AppFile {
+    http: HttpConfig,
+    #[serde(default)]
+    runtime: RuntimeConfig,
+    logging: LogOptions,
+    #[serde(default)]
+    http_clients: BTreeMap<String, HttpClientOptions>,
+    #[serde(default)]
+    webhook: WebhookConfig,
+    #[serde(default)]
+    schemas: SchemaConfig,
+}
+
+/// HTTP 服务配置
+#[derive(Debug, Clone, Deserialize)]
+pub struct HttpConfig {
+    /// 监听地址, 如 `0.0.0.0:3000`
+    pub bind: String,
+    /// 退出时等待处理中的请求完成的最长时间, 毫秒
+    #[serde(default = "default_shutdown_timeout_ms")]
+    pub shutdown_timeout_ms: u64,
+    /// 管理接口 (`/admin/*`, `/webhooks/*`) 的令牌, 请求时通过 `Authorization: Bearer {令牌}` 传递.
+    /// 没有配置时管理接口不可用
+    #[serde(default)]
+    pub admin_token: Option<String>,
+}
+
+const fn default_shutdown_timeout_ms() -> u64 {
+    30_000
+}
+
+/// Tokio 运行时配置, 修改后需要重启
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RuntimeConfig {
+    /// 调度方式, 默认 `multi_thread`
+    pub flavor: RuntimeFlavor,
+    /// 工作线程数, 默认为 CPU 核数, 只对 `multi_thread` 生效
+    pub worker_threads: Option<usize>,
+    /// 阻塞线程池 (`spawn_blocking`, 文件读写等) 的最大线程数
+    pub max_blocking_threads: usize,
+    /// 阻塞线程空闲多久后退出, 毫秒
+    pub thread_keep_alive_ms: u64,
+    /// 线程名称, 日志中的 `T[...]` 即为线程名称
+    pub thread_name: String,
+    /// 线程栈大小, 字节, 默认 2 MiB
+    pub thread_stack_size: Option<usize>,
+    /// 每次轮询最多处理的 IO 事件数
+    pub max_io_events_per_tick: usize,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self {
+            flavor: RuntimeFlavor::default(),
+            worker_threads: None,
+            max_blocking_threads: 512,
+            thread_keep_alive_ms: 60_000,
+            thread_name: "tokio-runtime-worker".to_owned(),
+            thread_stack_size: None,
+            max_io_events_per_tick: 2048,
+        }
+    }
+}
+
+/// Tokio 运行时的调度方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuntimeFlavor {
+    /// 多线程, 任务在多个工作线程间调度
+    #[default]
+    MultiThread,
+    /// 所有任务在一个线程中执行
+    CurrentThread,
+}
+
+/// webhook 投递配置
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    /// 是否启用, 启用前需要创建 `webhook_deliveries` 表
+    pub enabled: bool,
+    /// 投递和重试
+    pub dispatcher: WebhookOptions,
+    /// 发送使用的 HTTP 客户端, 其中的 `retry` 不生效
+    pub client: HttpClientOptions,
+    /// 接收方, 键为名称
+    pub endpoints: BTreeMap<String, WebhookEndpoint>,
+}
+
+/// JSON Schema 配置, 文件路径相对于配置目录, 修改后需要重启
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SchemaConfig {
+    /// 校验 MQTT 消息体, 键为主题过滤器, 支持 `+` 和 `#`. 一条消息匹配多个过滤器时,
+    /// 使用按字典序排在最前面的那个
+    pub mqtt: BTreeMap<String, PathBuf>,
+    /// 校验 HTTP 请求体, 键为请求体的名称, 会替换代码中注册的同名 Schema
+    pub http: BTreeMap<String, PathBuf>,
+}
+
+impl SchemaConfig {
+    /// 将相对路径转为相对于 `dir` 的路径.
+    fn relative_to(mut self, dir: &Path) -> Self {
+        for path in self.mqtt.values_mut().chain(self.http.values_mut()) {
+            *path = dir.join(&*path);
+        }
+        self
+    }
+}
+
+impl AppConfig {
+    /// 配置目录, 取自 `APP_CONFIG_DIR`, 默认 `./config`.
+    pub(crate) fn dir() -> PathBuf {
+        env::var("APP_CONFIG_DIR").map_or_else(|_| DEFAULT_CONFIG_DIR.into(), PathBuf::from)
+    }
+
+    /// 从配置目录加载所有配置并校验.
+    ///
+    /// 每个文件都会尝试加载, 加载失败和校验失败的错误会一起返回.
+    ///
+    /// # Errors
+    ///
+    /// 任意文件加载失败或者任意配置项校验失败时, 返回包含所有错误的 [ConfigErrors].
+    pub(crate) fn load(dir: &Path) -> Result<Self> {
+        let mut errors = ConfigErrors::default();
+        let app = load_file::<AppFile>(dir, "app.yaml", "APP", &mut errors).map(|mut app| {
+            app.schemas = app.schemas.relative_to(dir);
+            app
+        });
+        let mysql = load_file::<MySQLOptions>(dir, "mysql.yaml", "MYSQL", &mut errors);
+        let redis = load_file::<RedisOptions>(dir, "redis.yaml", "REDIS", &mut errors);
+        let mqtt = load_file::<MqttClientOptions>(dir, "mqtt.yaml", "MQTT", &mut errors);
+
+        // 其他文件加载失败时, 已加载的文件仍然校验, 所有错误一起报告
+        if let Some(app) = &app {
+            app.validate("", &mut errors);
+        }
+        if let Some(mysql) = &mysql {
+            mysql.validate("mysql", &mut errors);
+        }
+        if let Some(redis) = &redis {
+            redis.validate("redis", &mut errors);
+        }
+        if let Some(mqtt) = &mqtt {
+            mqtt.validate("mqtt", &mut errors);
+        }
+
+        let (Some(app), Some(mysql), Some(redis), Some(mqtt)) = (app, mysql, redis, mqtt) else {
+            return Err(anyhow!(errors));
+        };
+        errors.into_result().map_err(|e| anyhow!(e))?;
+        Ok(Self {
+            http: app.http,
+            runtime: app.runtime,
+            logging: app.logging,
+            http_clients: app.http_clients,
+            webhook: app.webhook,
+            schemas: app.schemas,
+            mysql,
+            redis,
+            mqtt,
+        })
+    }
+
+    /// 用命令行的 `--log-level` 覆盖 `logging.level`, 为 `None` 时不变.
+    pub(crate) fn with_log_level(mut self, level: Option<&str>) -> Self {
+        if let Some(level) = level {
+            level.clone_into(&mut self.logging.level);
+        }
+        self
+    }
+
+    /// 用命令行的 `--worker-threads` 覆盖 `runtime.worker_threads`, 为 `None` 时不变.
+    pub(crate) fn with_worker_threads(mut self, worker_threads: Option<usize>) -> Self {
+        if worker_threads.is_some() {
+            self.runtime.worker_threads = worker_threads;
+        }
+        self
+    }
+}
+
+impl Validate for AppFile {
+    fn validate(&self, _section: &str, errors: &mut ConfigErrors) {
+        self.http.validate("http", errors);
+        self.runtime.validate("runtime", errors);
+        self.logging.validate("logging", errors);
+        for (name, client) in &self.http_clients {
+            client.validate(&format!("http_clients.{name}"), errors);
+        }
+        if self.webhook.enabled {
+            self.webhook.validate("webhook", errors);
+        }
+        self.schemas.validate("schemas", errors);
+    }
+}
+
+impl Validate for HttpConfig {
+    fn validate(&self, section: &str, errors: &mut ConfigErrors) {
+        match self.bind.parse::<SocketAddr>() {
+            Ok(addr) => errors.check(addr.port() != 0, section, "bind 的端口不能为 0"),
+            Err(e) => errors.push(section, format!("bind `{}` 无效: {e}", self.bind)),
+        }
+        errors.check(
+            self.shutdown_timeout_ms > 0,
+            section,
+            "shutdown_timeout_ms 必须大于 0",
+        );
+        errors.check(
+            self.admin_token
+                .as_ref()
+                .is_none_or(|t| t.len() >= MIN_ADMIN_TOKEN_LEN),
+            section,
+            format!("admin_token 不能少于 {MIN_ADMIN_TOKEN_LEN} 个字符"),
+        );
+    }
+}
+
+impl Validate for WebhookConfig {
+    fn validate(&self, section: &str, errors: &mut ConfigErrors) {
+        self.dispatcher
+            .validate(&format!("{section}.dispatcher"), errors);
+        self.client.validate(&format!("{section}.client"), errors);
+        errors.check(
+            self.dispatcher.lease_ms > self.client.timeout_ms,
+            section,
+            format!(
+                "dispatcher.lease_ms ({}) 必须大于 client.timeout_ms ({})",
+                self.dispatcher.lease_ms, self.client.timeout_ms
+            ),
+        );
+        for (name, endpoint) in &self.endpoints {
+            endpoint.validate(&format!("{section}.endpoints.{name}"), errors);
+        }
+    }
+}
+
+impl Validate for SchemaConfig {
+    fn validate(&self, section: &str, errors: &mut ConfigErrors) {
+        for (kind, schemas) in [("mqtt", &self.mqtt), ("http", &self.http)] {
+            for (name, path) in schemas {
+                if let Err(e) = JsonSchema::from_file(path) {
+                    errors.push(
+                        &format!("{section}.{kind}"),
+                        format!("{name} 的 Schema 文件 {} 无效: {e:#}", path.display()),
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl Validate for RuntimeConfig {
+    fn validate(&self, section: &str, errors: &mut ConfigErrors) {
+        errors.check(
+            self.worker_threads != Some(0),
+            section,
+            "worker_threads 必须大于 0",
+        );
+        errors.check(
+            self.max_blocking_threads > 0,
+            section,
+            "max_blocking_threads 必须大于 0",
+        );
+        errors.check(
+            !self.thread_name.trim().is_empty(),
+            section,
+            "thread_name 不能为空",
+        );
+        errors.check(
+            self.thread_stack_size
+                .is_none_or(|size| size >= MIN_THREAD_STACK_SIZE),
+            section,
+            format!("thread_stack_size 不能小于 {MIN_THREAD_STACK_SIZE}"),
+        );
+        errors.check(
+            self.max_io_events_per_tick > 0,
+            section,
+            "max_io_events_per_tick 必须大于 0",
+        );
+    }
+}
+
+/// 加载单个文件, 失败时记录错误并返回 `None`.
+fn load_file<T: DeserializeOwned>(
+    dir: &Path,
+    name: &str,
+    env_prefix: &str,
+    errors: &mut ConfigErrors,
+) -> Option<T> {
+    let path = dir.join(name);
+    let result = path
+        .to_str()
+        .context("配置文件路径不是合法的 UTF-8")
+        .and_then(|p| from_layered_yaml(p, env_prefix));
+    match result {
+        Ok(v) => Some(v),
+        Err(e) => {
+            errors.push(name, format!("{e:#}"));
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn load_reports_errors_of_all_files() {
+        let source = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../config");
+        let dir = env::temp_dir().join(format!("app-config-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["app.yaml", "redis.yaml", "mqtt.yaml"] {
+            fs::copy(source.join(name), dir.join(name)).unwrap();
+        }
+        let app = fs::read_to_string(source.join("app.yaml")).unwrap();
+        let app = app.replacen("bind: \"0.0.0.0:3000\"", "bind: \"not an address\"", 1);
+        fs::write(dir.join("app.yaml"), app).unwrap();
+        fs::write(dir.join("mysql.yaml"), "host: [").unwrap();
+
+        let err = AppConfig::load(&dir).unwrap_err().to_string();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(err.contains("mysql.yaml: "), "{err}");
+        assert!(err.contains("http: bind `not an address` 无效"), "{err}");
+    }
+}
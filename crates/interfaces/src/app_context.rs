@@ -1,33 +1,207 @@
 //! 整个应用程序的上下文.
 
-use anyhow::Result;
-use internal_core::mqtt_event::MqttEventDispatchContext;
+use crate::app_config::{AppConfig, SchemaConfig, WebhookConfig};
+use crate::http::BodySchemas;
+use anyhow::{Context, Result};
+use internal_core::mqtt_event::{MqttEventDispatchContext, TopicSchemas, dispatch_mqtt_events};
+use internal_core::webhook::WebhookDispatcher;
+use internal_ffi::impls::{HttpWebhookSender, MySqlWebhookRepo};
+use internal_ffi::mqtt_client::MqttSubscriptions;
 use internal_ffi::{init_mqtt_client, init_mysql, init_redis};
+use internal_shared::flexi_logger::LogLevelControl;
+use internal_shared::json::JsonSchema;
+use internal_shared::reqwest::{HttpClient, HttpClients};
+use mysql_async::Pool;
+use redis::Client;
 use rumqttc::v5::AsyncClient;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+
+/// 退出时断开 MQTT 和关闭每个连接池的最长等待时间.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// 主要用来创建所有实例, 以及依赖注入.
 ///
 /// `AppContext` 中的所有实例, 在整个应用程序中共享.
 #[allow(dead_code)]
 pub struct AppContext {
+    /// 当前配置, 重新加载后自动更新. 需要响应配置变化的组件可以 `clone` 后监听.
+    pub config: watch::Receiver<Arc<AppConfig>>,
     pub mqtt_event_dispatch_context: Option<MqttEventDispatchContext>,
     pub mqtt_client: AsyncClient,
+    /// 调用上游接口的 HTTP 客户端, 按 `http_clients` 中的名称获取.
+    pub http_clients: HttpClients,
+    /// webhook 投递器, 没有启用时为 `None`.
+    pub webhooks: Option<Arc<WebhookDispatcher>>,
+    /// 运行中调整日志级别.
+    pub log_level: LogLevelControl,
+    /// 校验 HTTP 请求体的 Schema, 见 [ValidatedJson](crate::http::ValidatedJson).
+    pub body_schemas: BodySchemas,
+    /// `MySQL` 连接池.
+    pub mysql_pool: Pool,
+    /// Redis 连接池.
+    pub redis_pool: r2d2::Pool<Client>,
+    /// MQTT 事件循环任务, 退出时等待它发出 DISCONNECT 后结束.
+    mqtt_event_loop: Mutex<Option<JoinHandle<()>>>,
 }
 
 impl AppContext {
-    /// 创建 `AppContext`
-    pub(crate) async fn build() -> Result<Self> {
-        let _mysql_pool = init_mysql("./config/mysql.yaml")?;
-        let _redis_pool = init_redis("./config/redis.yaml")?;
-        let (client, event_loop) = init_mqtt_client("./config/mqtt.yaml").await?;
+    /// 创建 `AppContext`, `config` 中的配置应已通过 [AppConfig::load] 校验.
+    pub(crate) async fn build(
+        config: watch::Receiver<Arc<AppConfig>>,
+        log_level: LogLevelControl,
+    ) -> Result<Self> {
+        let current = config.borrow().clone();
+        let (topic_schemas, body_schemas) = build_schemas(&current.schemas)?;
+        let http_clients = HttpClients::new(&current.http_clients)?;
+        watch_http_limits(config.clone(), http_clients.clone());
+        let mysql_pool = init_mysql(current.mysql.clone())?;
+        let webhooks = build_webhooks(&current.webhook, MySqlWebhookRepo::new(mysql_pool.clone()))?;
+        let redis_pool = init_redis(current.redis.clone())?;
+        let (client, event_loop, subscriptions, mqtt_event_loop) =
+            init_mqtt_client(current.mqtt.clone()).await?;
+        watch_mqtt_subscriptions(config.clone(), subscriptions);
+
         let mqtt_event_dispatch_context = Some(MqttEventDispatchContext {
             client: client.clone(),
             event_loop,
+            schemas: topic_schemas,
         });
 
         Ok(Self {
+            config,
             mqtt_event_dispatch_context,
             mqtt_client: client,
+            http_clients,
+            webhooks,
+            log_level,
+            body_schemas,
+            mysql_pool,
+            redis_pool,
+            mqtt_event_loop: Mutex::new(Some(mqtt_event_loop)),
         })
     }
+
+    /// 启动后台任务: MQTT 事件分发和 webhook 投递.
+    ///
+    /// `shutdown` 取消后, 各任务处理完手上的工作后退出, 等待返回的任务即可.
+    pub(crate) fn spawn_background(&mut self, shutdown: &CancellationToken) -> Vec<JoinHandle<()>> {
+        let mut tasks = Vec::new();
+        if let Some(context) = self.mqtt_event_dispatch_context.take() {
+            tasks.push(dispatch_mqtt_events(context, shutdown.clone()));
+        }
+        if let Some(webhooks) = &self.webhooks {
+            tasks.push(webhooks.spawn(shutdown.clone()));
+        }
+        tasks
+    }
+
+    /// 断开 MQTT 连接, 关闭 `MySQL` 和 Redis 连接池.
+    ///
+    /// 应在 HTTP 服务和后台任务停止后调用, 每一步最多等待 [CLOSE_TIMEOUT].
+    pub(crate) async fn close(self: Arc<Self>) {
+        let event_loop = self
+            .mqtt_event_loop
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        let disconnect = async {
+            self.mqtt_client.disconnect().await?;
+            if let Some(event_loop) = event_loop {
+                event_loop.await?;
+            }
+            anyhow::Ok(())
+        };
+        match timeout(CLOSE_TIMEOUT, disconnect).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => log::warn!("断开 MQTT 连接失败: {e}"),
+            Err(_) => log::warn!("断开 MQTT 连接超时."),
+        }
+
+        match timeout(CLOSE_TIMEOUT, self.mysql_pool.clone().disconnect()).await {
+            Ok(Ok(())) => log::info!("MySQL 连接池已关闭."),
+            Ok(Err(e)) => log::warn!("关闭 MySQL 连接池失败: {e}"),
+            Err(_) => log::warn!("关闭 MySQL 连接池超时."),
+        }
+
+        // r2d2 连接池没有关闭方法, 释放最后一个引用时关闭所有连接
+        match Arc::try_unwrap(self) {
+            Ok(context) => {
+                drop(context);
+                log::info!("Redis 连接池已关闭.");
+            }
+            Err(_) => log::warn!("仍有未结束的请求, Redis 连接池在进程退出时关闭."),
+        }
+    }
+}
+
+/// 加载配置中的 Schema. 请求体先注册代码中的 Schema, 配置中的同名 Schema 会替换它.
+fn build_schemas(config: &SchemaConfig) -> Result<(TopicSchemas, BodySchemas)> {
+    let load = |path: &PathBuf| {
+        JsonSchema::from_file(path)
+            .with_context(|| format!("加载 Schema 文件 {} 失败", path.display()))
+    };
+    let mut topics = TopicSchemas::default();
+    for (filter, path) in &config.mqtt {
+        topics.insert(filter.clone(), load(path)?);
+    }
+    let mut bodies = BodySchemas::builtin();
+    for (name, path) in &config.http {
+        bodies.insert(name.clone(), load(path)?);
+    }
+    Ok((topics, bodies))
+}
+
+/// 创建 webhook 投递器, 由 [AppContext::spawn_background] 启动.
+fn build_webhooks(
+    config: &WebhookConfig,
+    repo: MySqlWebhookRepo,
+) -> Result<Option<Arc<WebhookDispatcher>>> {
+    if !config.enabled {
+        return Ok(None);
+    }
+    let client = HttpClient::new("webhook", &config.client)?;
+    let dispatcher = Arc::new(WebhookDispatcher::new(
+        Arc::new(repo),
+        Arc::new(HttpWebhookSender::new(client, config.endpoints.clone())),
+        config.dispatcher.clone(),
+    ));
+    log::info!("webhook 接收方: {:?}", config.endpoints.keys());
+    Ok(Some(dispatcher))
+}
+
+/// 配置变化时, 更新 HTTP 客户端的熔断和限流.
+fn watch_http_limits(mut config: watch::Receiver<Arc<AppConfig>>, clients: HttpClients) {
+    tokio::spawn(async move {
+        let mut previous = config.borrow().clone();
+        while config.changed().await.is_ok() {
+            let current = config.borrow_and_update().clone();
+            clients.reload(&previous.http_clients, &current.http_clients);
+            previous = current;
+        }
+    });
+}
+
+/// 配置中的订阅列表变化时, 更新 MQTT 订阅.
+fn watch_mqtt_subscriptions(
+    mut config: watch::Receiver<Arc<AppConfig>>,
+    subscriptions: MqttSubscriptions,
+) {
+    tokio::spawn(async move {
+        while config.changed().await.is_ok() {
+            let topics = config.borrow_and_update().mqtt.subscribes.clone();
+            if topics == subscriptions.topics() {
+                continue;
+            }
+            match subscriptions.update(topics).await {
+                Ok(()) => log::info!("MQTT 订阅已更新: {:?}", subscriptions.topics()),
+                Err(e) => log::error!("更新 MQTT 订阅失败: {e:?}"),
+            }
+        }
+    });
 }
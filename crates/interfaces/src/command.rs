@@ -0,0 +1,415 @@
+//! 命令行参数和运维命令.
+//!
+//! 不带子命令时等同于 `serve`. 其他命令执行后直接退出, 不启动服务:
+//! - `check-config`: 加载并校验所有配置文件.
+//! - `migrate up|down|status`: 执行、回滚数据库迁移或查看迁移状态.
+//! - `mqtt publish <TOPIC> <PAYLOAD>`: 发布一条 MQTT 消息.
+//! - `encrypt-secret [VALUE]`: 用 `APP_SECRET_KEY` 或 `APP_SECRET_KEY_FILE` 加密 `VALUE`,
+//!   不传时从标准输入读取一行, 避免明文留在 shell 历史中.
+//! - `gen-secret-key`: 生成新的配置加密密钥.
+//!
+//! 全局参数 `--env`, `--config-dir` 和 `--log-level` 分别覆盖 `APP_ENV`, `APP_CONFIG_DIR`
+//! 和配置中的 `logging.level`, 对所有命令有效.
+
+use crate::app_config::AppConfig;
+use anyhow::{Context, Result, bail};
+use clap::builder::RangedU64ValueParser;
+use clap::{Args, Parser, Subcommand};
+use flexi_logger::{LogSpecification, Logger, LoggerHandle};
+use internal_ffi::init_mqtt_client;
+use internal_ffi::migrate::{MySqlMigrator, load_migrations};
+use internal_ffi::mqtt_client::{MQTTV5Client, MqttClientOptions};
+use internal_shared::secret::SecretKey;
+use internal_shared::trace::TraceContext;
+use rumqttc::Outgoing;
+use rumqttc::v5::Event;
+use rumqttc::v5::mqttbytes::QoS;
+use rumqttc::v5::mqttbytes::v5::{Packet, PubAckReason, PubCompReason};
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::Duration;
+use tokio::time::timeout;
+
+/// 发布 MQTT 消息后等待服务器确认的最长时间.
+const PUBLISH_TIMEOUT: Duration = Duration::from_secs(10);
+/// 断开 MQTT 连接的最长等待时间.
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 命令行参数.
+#[derive(Debug, Parser)]
+#[command(version, about = "应用程序和运维命令")]
+pub(crate) struct Cli {
+    /// 运行环境, 覆盖 `APP_ENV`, 决定加载的 `.env.{ENV}` 和 `*.{ENV}.yaml`
+    #[arg(long, global = true, value_name = "ENV")]
+    pub(crate) env: Option<String>,
+    /// 配置目录, 覆盖 `APP_CONFIG_DIR`
+    #[arg(long, global = true, value_name = "DIR")]
+    config_dir: Option<PathBuf>,
+    /// 日志级别, 覆盖配置中的 `logging.level`, 如 `info,internal_ffi=debug`
+    #[arg(long, global = true, value_name = "SPEC", value_parser = parse_log_level)]
+    pub(crate) log_level: Option<String>,
+    /// 要执行的命令, 默认 `serve`
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+}
+
+impl Cli {
+    /// 配置目录, 没有传 `--config-dir` 时见 [AppConfig::dir].
+    pub(crate) fn config_dir(&self) -> PathBuf {
+        self.config_dir.clone().unwrap_or_else(AppConfig::dir)
+    }
+}
+
+/// 子命令.
+#[derive(Debug, Subcommand)]
+pub(crate) enum Command {
+    /// 启动服务
+    Serve(ServeArgs),
+    /// 加载并校验所有配置文件, 不建立任何连接
+    CheckConfig,
+    /// 数据库迁移
+    Migrate(MigrateArgs),
+    /// MQTT 工具
+    #[command(subcommand)]
+    Mqtt(MqttCommand),
+    /// 加密配置中的敏感值
+    EncryptSecret {
+        /// 要加密的值, 不传时从标准输入读取一行
+        value: Option<String>,
+    },
+    /// 生成新的配置加密密钥
+    GenSecretKey,
+}
+
+/// `serve` 的参数.
+#[derive(Debug, Default, Args)]
+pub(crate) struct ServeArgs {
+    /// 工作线程数, 覆盖配置中的 `runtime.worker_threads`
+    #[arg(long, value_name = "N", value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    pub(crate) worker_threads: Option<usize>,
+}
+
+/// `migrate` 的参数.
+#[derive(Debug, Args)]
+pub(crate) struct MigrateArgs {
+    /// 迁移文件目录
+    #[arg(
+        long,
+        global = true,
+        value_name = "DIR",
+        default_value = "./migrations"
+    )]
+    dir: PathBuf,
+    #[command(subcommand)]
+    action: MigrateAction,
+}
+
+/// `migrate` 的子命令.
+#[derive(Debug, Subcommand)]
+enum MigrateAction {
+    /// 按版本顺序执行没有执行过的迁移
+    Up {
+        /// 最多执行几个, 默认全部
+        #[arg(long)]
+        steps: Option<usize>,
+    },
+    /// 回滚最近执行的迁移
+    Down {
+        /// 回滚几个
+        #[arg(long, default_value_t = 1)]
+        steps: usize,
+    },
+    /// 查看每个迁移是否已执行
+    Status,
+}
+
+/// `mqtt` 的子命令.
+#[derive(Debug, Subcommand)]
+pub(crate) enum MqttCommand {
+    /// 发布一条消息, 等待服务器确认后退出
+    Publish {
+        /// 主题
+        topic: String,
+        /// 消息体
+        payload: String,
+        /// QoS
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(0..=2))]
+        qos: u8,
+        /// 是否保留消息
+        #[arg(long)]
+        retain: bool,
+    },
+}
+
+/// 执行 `serve` 以外的命令.
+///
+/// # Arguments
+///
+/// * `command` - 要执行的命令, 不能是 [Command::Serve].
+/// * `cli` - 命令行参数, 用于读取全局参数.
+///
+/// # Returns
+///
+/// 进程退出码.
+pub(crate) fn run(command: Command, cli: &Cli) -> i32 {
+    let result = match command {
+        Command::Serve(_) => unreachable!("serve 由 main 处理"),
+        Command::CheckConfig => check_config(&cli.config_dir()),
+        Command::Migrate(args) => with_runtime(cli, |config| migrate(config, args)),
+        Command::Mqtt(command) => with_runtime(cli, |config| mqtt(config, command)),
+        Command::EncryptSecret { value } => encrypt_secret(value),
+        Command::GenSecretKey => {
+            println!("{}", SecretKey::generate());
+            Ok(())
+        }
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{e:#}");
+            1
+        }
+    }
+}
+
+/// 校验 `--log-level`.
+fn parse_log_level(spec: &str) -> Result<String> {
+    LogSpecification::parse(spec).with_context(|| format!("日志级别 `{spec}` 无效"))?;
+    Ok(spec.to_owned())
+}
+
+fn check_config(dir: &Path) -> Result<()> {
+    AppConfig::load(dir)?;
+    println!("配置检查通过: {}", dir.display());
+    Ok(())
+}
+
+/// 加载配置, 初始化输出到标准错误的日志, 在单线程运行时中执行 `f`.
+fn with_runtime<F: Future<Output = Result<()>>>(
+    cli: &Cli,
+    f: impl FnOnce(AppConfig) -> F,
+) -> Result<()> {
+    let config = AppConfig::load(&cli.config_dir())?;
+    let _logger = init_stderr_logger(cli.log_level.as_deref().unwrap_or("info"))?;
+    let runtime = crate::new_current_thread()?;
+    let result = runtime.block_on(f(config));
+    // 不等待连接池等遗留的后台任务
+    runtime.shutdown_timeout(Duration::ZERO);
+    result
+}
+
+/// 运维命令的日志只输出到标准错误, 不写入服务的日志文件.
+fn init_stderr_logger(spec: &str) -> Result<LoggerHandle> {
+    Ok(Logger::try_with_str(spec)?.log_to_stderr().start()?)
+}
+
+async fn migrate(config: AppConfig, args: MigrateArgs) -> Result<()> {
+    let migrations = load_migrations(&args.dir)?;
+    let pool = internal_ffi::init_mysql(config.mysql)?;
+    let migrator = MySqlMigrator::new(pool.clone());
+    let result = match args.action {
+        MigrateAction::Up { steps } => migrator.up(&migrations, steps).await.map(|done| match done
+            .len()
+        {
+            0 => println!("没有需要执行的迁移."),
+            n => println!("已执行 {n} 个迁移."),
+        }),
+        MigrateAction::Down { steps } => {
+            migrator
+                .down(&migrations, steps)
+                .await
+                .map(|done| match done.len() {
+                    0 => println!("没有可以回滚的迁移."),
+                    n => println!("已回滚 {n} 个迁移."),
+                })
+        }
+        MigrateAction::Status => migrator.status(&migrations).await.map(|status| {
+            for s in status {
+                let state = match (s.applied_at, s.missing) {
+                    (Some(at), false) => format!("已执行 {}", at.format("%Y-%m-%d %H:%M:%S")),
+                    (Some(at), true) => {
+                        format!("已执行 {} (文件不存在)", at.format("%Y-%m-%d %H:%M:%S"))
+                    }
+                    (None, _) => "未执行".to_owned(),
+                };
+                println!("{:04}_{}\t{state}", s.version, s.name);
+            }
+        }),
+    };
+    if let Err(e) = pool.disconnect().await {
+        log::warn!("关闭 MySQL 连接池失败: {e}");
+    }
+    result
+}
+
+async fn mqtt(config: AppConfig, command: MqttCommand) -> Result<()> {
+    match command {
+        MqttCommand::Publish {
+            topic,
+            payload,
+            qos,
+            retain,
+        } => mqtt_publish(config.mqtt, &topic, payload, qos, retain).await,
+    }
+}
+
+/// 发布一条消息, 等待服务器确认 (QoS 0 时等待发出) 后断开连接.
+async fn mqtt_publish(
+    mut opt: MqttClientOptions,
+    topic: &str,
+    payload: String,
+    qos: u8,
+    retain: bool,
+) -> Result<()> {
+    // 不订阅主题, 并使用不同的客户端 ID, 避免把运行中的服务挤下线
+    opt.subscribes.clear();
+    opt.id = format!("{}-cli-{}", opt.id, process::id());
+    let (client, mut events, _subscriptions, event_loop) = init_mqtt_client(opt).await?;
+
+    let qos = match qos {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        _ => QoS::ExactlyOnce,
+    };
+    let trace = TraceContext::generate();
+    let publish = async {
+        trace
+            .clone()
+            .scope(MQTTV5Client::publish(&client, topic, qos, retain, payload))
+            .await?;
+        while let Some(event) = events.recv().await {
+            match event {
+                Event::Outgoing(Outgoing::Publish(_)) if qos == QoS::AtMostOnce => return Ok(()),
+                Event::Incoming(Packet::PubAck(ack)) => {
+                    return match ack.reason {
+                        PubAckReason::Success | PubAckReason::NoMatchingSubscribers => Ok(()),
+                        reason => bail!("服务器拒绝了消息: {reason:?}"),
+                    };
+                }
+                Event::Incoming(Packet::PubComp(comp)) => {
+                    return match comp.reason {
+                        PubCompReason::Success => Ok(()),
+                        reason => bail!("服务器拒绝了消息: {reason:?}"),
+                    };
+                }
+                _ => {}
+            }
+        }
+        bail!("MQTT 事件循环已结束")
+    };
+    let result = timeout(PUBLISH_TIMEOUT, publish)
+        .await
+        .unwrap_or_else(|_| Err(anyhow::anyhow!("等待服务器确认超时")));
+
+    // 继续接收事件直到事件循环结束, 否则事件循环会阻塞在发送事件上
+    let disconnect = async {
+        client.disconnect().await?;
+        while events.recv().await.is_some() {}
+        event_loop.await?;
+        anyhow::Ok(())
+    };
+    match timeout(DISCONNECT_TIMEOUT, disconnect).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => log::warn!("断开 MQTT 连接失败: {e}"),
+        Err(_) => log::warn!("断开 MQTT 连接超时."),
+    }
+
+    result.with_context(|| format!("发布到 {topic} 失败"))?;
+    println!("已发布到 {topic}, trace_id: {trace}");
+    Ok(())
+}
+
+fn encrypt_secret(value: Option<String>) -> Result<()> {
+    let key = SecretKey::from_env()?;
+    let value = match value {
+        Some(v) => v,
+        None => {
+            let mut line = String::new();
+            io::stdin()
+                .lock()
+                .read_line(&mut line)
+                .context("读取标准输入失败")?;
+            line.trim_end_matches(['\r', '\n']).to_owned()
+        }
+    };
+    if value.is_empty() {
+        bail!("要加密的值不能为空");
+    }
+    println!("{}", key.encrypt(&value)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_to_serve() {
+        let cli = Cli::try_parse_from(["app"]).unwrap();
+        assert!(cli.command.is_none());
+        assert!(cli.env.is_none() && cli.log_level.is_none());
+
+        let cli = Cli::try_parse_from(["app", "serve", "--worker-threads", "4"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Serve(ServeArgs {
+                worker_threads: Some(4)
+            }))
+        ));
+        assert!(Cli::try_parse_from(["app", "serve", "--worker-threads", "0"]).is_err());
+    }
+
+    #[test]
+    fn parses_migrate_down_steps() {
+        let cli = Cli::try_parse_from(["app", "migrate", "down", "--steps", "3"]).unwrap();
+        let Some(Command::Migrate(args)) = cli.command else {
+            panic!("应为 migrate");
+        };
+        assert_eq!(args.dir, Path::new("./migrations"));
+        assert!(matches!(args.action, MigrateAction::Down { steps: 3 }));
+
+        // --dir 是 migrate 的全局参数, 可以写在子命令后面
+        let cli = Cli::try_parse_from(["app", "migrate", "down", "--dir", "sql"]).unwrap();
+        let Some(Command::Migrate(args)) = cli.command else {
+            panic!("应为 migrate");
+        };
+        assert_eq!(args.dir, Path::new("sql"));
+        assert!(matches!(args.action, MigrateAction::Down { steps: 1 }));
+
+        let cli = Cli::try_parse_from(["app", "migrate", "up"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Command::Migrate(MigrateArgs {
+                action: MigrateAction::Up { steps: None },
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn global_flags_after_subcommand() {
+        let cli = Cli::try_parse_from([
+            "app",
+            "check-config",
+            "--env",
+            "prod",
+            "--config-dir",
+            "/etc/app",
+            "--log-level",
+            "info,internal_ffi=debug",
+        ])
+        .unwrap();
+        assert!(matches!(cli.command, Some(Command::CheckConfig)));
+        assert_eq!(cli.env.as_deref(), Some("prod"));
+        assert_eq!(cli.config_dir(), Path::new("/etc/app"));
+        assert_eq!(cli.log_level.as_deref(), Some("info,internal_ffi=debug"));
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        assert!(Cli::try_parse_from(["app", "--log-level", "info,=x=y"]).is_err());
+        assert!(Cli::try_parse_from(["app", "mqtt", "publish", "t", "p", "--qos", "3"]).is_err());
+        assert!(Cli::try_parse_from(["app", "migrate"]).is_err());
+    }
+}
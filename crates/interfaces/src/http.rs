@@ -2,24 +2,563 @@
 
 use crate::app_context::AppContext;
 use axum::Router;
-use axum::response::Json;
-use axum::routing::get;
+use axum::extract::{FromRequest, Path, Query, Request, State};
+use axum::http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use axum::http::{HeaderValue, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Json, Response};
+use axum::routing::{get, post};
+use internal_core::webhook::{DeliveryStatus, WebhookDelivery, WebhookDispatcher};
+use internal_shared::flexi_logger::{LogLevelStatus, MAX_LOG_LEVEL_TTL};
+use internal_shared::json::{JsonError, JsonSchema, ValidationError};
+use internal_shared::reqwest::HttpError;
+use internal_shared::trace::{self, TraceContext};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
 use serde_json::{Value, json};
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+
+/// 接口错误, 会转换为对应的 HTTP 响应.
+///
+/// 处理函数返回 `Result<_, ApiError>` 时, 可以直接用 `?` 传播 [JsonError], [ValidationError],
+/// [HttpError] 和 [anyhow::Error]. 需要按 JSON Schema 校验的请求体使用 [ValidatedJson] 提取.
+#[derive(Debug)]
+pub enum ApiError {
+    /// 请求数据错误, 返回 400 和字段详情.
+    BadRequest(JsonError),
+    /// 请求体不符合 JSON Schema, 返回 400 和所有违反约束的位置.
+    Validation(ValidationError),
+    /// 请求体不是 JSON 或者与接口定义不符, 返回对应的状态码 (400, 415, 422 等) 和原因.
+    InvalidBody(StatusCode, String),
+    /// 没有提供或提供了错误的管理令牌, 返回 401.
+    Unauthorized,
+    /// 不允许访问, 返回 403 和原因.
+    Forbidden(String),
+    /// 资源不存在, 返回 404 和原因.
+    NotFound(String),
+    /// 调用上游接口失败, 返回 502, 详情只写日志.
+    Upstream(HttpError),
+    /// 内部错误, 返回 500, 详情只写日志.
+    Internal(anyhow::Error),
+}
+
+impl From<JsonError> for ApiError {
+    fn from(e: JsonError) -> Self {
+        Self::BadRequest(e)
+    }
+}
+
+impl From<ValidationError> for ApiError {
+    fn from(e: ValidationError) -> Self {
+        Self::Validation(e)
+    }
+}
+
+impl From<HttpError> for ApiError {
+    fn from(e: HttpError) -> Self {
+        Self::Upstream(e)
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        Self::Internal(e)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::BadRequest(e) => {
+                let body = json!({"error": e.to_string(), "detail": e});
+                (StatusCode::BAD_REQUEST, Json(body)).into_response()
+            }
+            Self::Validation(e) => {
+                let body = json!({"error": "请求数据校验失败.", "violations": e.violations});
+                (StatusCode::BAD_REQUEST, Json(body)).into_response()
+            }
+            Self::InvalidBody(status, reason) => {
+                (status, Json(json!({"error": reason}))).into_response()
+            }
+            Self::Unauthorized => {
+                let body = json!({"error": "缺少或错误的管理令牌."});
+                let headers = [(WWW_AUTHENTICATE, "Bearer")];
+                (StatusCode::UNAUTHORIZED, headers, Json(body)).into_response()
+            }
+            Self::Forbidden(reason) => {
+                (StatusCode::FORBIDDEN, Json(json!({"error": reason}))).into_response()
+            }
+            Self::NotFound(reason) => {
+                (StatusCode::NOT_FOUND, Json(json!({"error": reason}))).into_response()
+            }
+            Self::Upstream(e) => {
+                log::error!("调用上游接口错误: {e}");
+                let body = json!({"error": "上游服务错误."});
+                (StatusCode::BAD_GATEWAY, Json(body)).into_response()
+            }
+            Self::Internal(e) => {
+                log::error!("处理请求错误: {e:?}");
+                let body = json!({"error": "服务器内部错误."});
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response()
+            }
+        }
+    }
+}
+
+/// 请求体在 [BodySchemas] 中的名称.
+pub trait RequestSchema {
+    /// 名称, 也是配置中 `schemas.http` 的键.
+    const NAME: &'static str;
+}
+
+/// 按名称注册的请求体 Schema, 见 [ValidatedJson].
+#[derive(Debug, Default)]
+pub struct BodySchemas {
+    schemas: HashMap<String, JsonSchema>,
+}
+
+impl BodySchemas {
+    /// 代码中注册的 Schema.
+    pub fn builtin() -> Self {
+        let mut schemas = Self::default();
+        schemas.insert(
+            SetLogLevel::NAME,
+            JsonSchema::compile(&json!({
+                "type": "object",
+                "required": ["spec"],
+                "properties": {
+                    "spec": {"type": "string", "minLength": 1},
+                    "ttl_secs": {"type": "integer", "minimum": 0, "maximum": MAX_LOG_LEVEL_TTL.as_secs()}
+                },
+                "additionalProperties": false
+            }))
+            .expect("内置的 Schema 应合法"),
+        );
+        schemas
+    }
+
+    /// 注册 Schema, 替换同名的 Schema.
+    pub fn insert(&mut self, name: impl Into<String>, schema: JsonSchema) {
+        self.schemas.insert(name.into(), schema);
+    }
+
+    /// 查找 Schema.
+    pub fn get(&self, name: &str) -> Option<&JsonSchema> {
+        self.schemas.get(name)
+    }
+}
+
+/// 可以提供 [BodySchemas] 的状态, 使用 [ValidatedJson] 的路由需要.
+pub trait HasBodySchemas {
+    /// 请求体的 Schema.
+    fn body_schemas(&self) -> &BodySchemas;
+}
+
+impl HasBodySchemas for Arc<AppContext> {
+    fn body_schemas(&self) -> &BodySchemas {
+        &self.body_schemas
+    }
+}
+
+/// 按 JSON Schema 校验后再反序列化的请求体.
+///
+/// 按 [RequestSchema::NAME] 查找 Schema, 不符合时返回 [ApiError::Validation] 和所有违反约束的位置;
+/// 没有注册 Schema 时只反序列化.
+#[derive(Debug)]
+pub struct ValidatedJson<T>(pub T);
+
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned + RequestSchema,
+    S: HasBodySchemas + Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<Value>::from_request(request, state)
+            .await
+            .map_err(|e| ApiError::InvalidBody(e.status(), e.body_text()))?;
+        if let Some(schema) = state.body_schemas().get(T::NAME) {
+            schema.validate(&value)?;
+        }
+        serde_json::from_value(value)
+            .map(Self)
+            .map_err(|e| ApiError::InvalidBody(StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))
+    }
+}
 
 /// 返回系统信息
 pub async fn system_info() -> Json<Value> {
     Json(json!({"version": "1.0.0"}))
 }
 
-/// 启动 HTTP 服务.
-pub async fn start_http(app_context: AppContext) -> anyhow::Result<()> {
-    let http_shared = Arc::new(app_context);
+/// 返回各 HTTP 客户端的熔断和限流状态
+pub async fn http_clients_stats(State(app_context): State<Arc<AppContext>>) -> Json<Value> {
+    Json(json!(app_context.http_clients.stats()))
+}
+
+/// 返回 Tokio 运行时的状态
+///
+/// - `global_queue_depth`: 全局队列中等待调度的任务数, 持续增长说明工作线程处理不过来.
+/// - `busy_workers`: 正在执行任务的工作线程数, 包括处理这个请求的线程.
+/// - `workers`: 每个工作线程是否忙碌, 累计忙碌时间 (毫秒) 和休眠次数.
+///
+/// Tokio 没有直接提供线程是否忙碌的指标. `worker_park_unpark_count` 从 0 开始,
+/// 线程休眠和被唤醒时各加 1, 所以为奇数时线程正在休眠, 为偶数时线程醒着 (执行任务或者查找任务).
+/// 这是读取时的瞬时值, 各线程不是同时读取的, 只能作为大致参考.
+pub async fn runtime_stats() -> Json<Value> {
+    let handle = Handle::current();
+    let metrics = handle.metrics();
+    let workers: Vec<_> = (0..metrics.num_workers())
+        .map(|i| {
+            json!({
+                // 见函数文档, 休眠和唤醒的次数为偶数时线程醒着
+                "busy": metrics.worker_park_unpark_count(i).is_multiple_of(2),
+                "busy_ms": metrics.worker_total_busy_duration(i).as_millis(),
+                "park_count": metrics.worker_park_count(i),
+            })
+        })
+        .collect();
+    let flavor = match handle.runtime_flavor() {
+        RuntimeFlavor::MultiThread => "multi_thread",
+        RuntimeFlavor::CurrentThread => "current_thread",
+        _ => "unknown",
+    };
+    Json(json!({
+        "flavor": flavor,
+        "num_workers": metrics.num_workers(),
+        "busy_workers": workers.iter().filter(|w| w["busy"] == true).count(),
+        "alive_tasks": metrics.num_alive_tasks(),
+        "global_queue_depth": metrics.global_queue_depth(),
+        "workers": workers,
+    }))
+}
+
+/// 返回当前的日志级别
+pub async fn get_log_level(State(app_context): State<Arc<AppContext>>) -> Json<LogLevelStatus> {
+    Json(app_context.log_level.status())
+}
+
+/// 临时修改日志级别的请求体.
+#[derive(Debug, Deserialize)]
+pub struct SetLogLevel {
+    /// 日志级别, 如 `info,internal_ffi=debug`.
+    spec: String,
+    /// 多少秒后恢复为配置中的级别, 不设置时一直生效.
+    ttl_secs: Option<u64>,
+}
+
+impl RequestSchema for SetLogLevel {
+    const NAME: &'static str = "set_log_level";
+}
+
+/// 临时修改日志级别, 请求体如 `{"spec": "info,internal_ffi=debug", "ttl_secs": 600}`.
+///
+/// 设置了 `ttl_secs` 时, 到期后恢复为配置中的级别, 最长 7 天.
+pub async fn put_log_level(
+    State(app_context): State<Arc<AppContext>>,
+    ValidatedJson(body): ValidatedJson<SetLogLevel>,
+) -> Result<Json<LogLevelStatus>, ApiError> {
+    let spec = body.spec.as_str();
+    let ttl = body.ttl_secs.map(Duration::from_secs);
+    if let Some(ttl) = ttl
+        && ttl > MAX_LOG_LEVEL_TTL
+    {
+        return Err(ApiError::BadRequest(JsonError::InvalidFormat {
+            path: "ttl_secs".to_owned(),
+            expected: "不超过 7 天 (604800) 的秒数",
+            value: ttl.as_secs().to_string(),
+        }));
+    }
+    app_context.log_level.set(spec, ttl).map_err(|_| {
+        ApiError::BadRequest(JsonError::InvalidFormat {
+            path: "spec".to_owned(),
+            expected: "日志级别, 如 info,internal_ffi=debug",
+            value: spec.to_owned(),
+        })
+    })?;
+    Ok(Json(app_context.log_level.status()))
+}
+
+/// 取消临时级别, 恢复为配置中的级别
+pub async fn delete_log_level(State(app_context): State<Arc<AppContext>>) -> Json<LogLevelStatus> {
+    app_context.log_level.reset();
+    Json(app_context.log_level.status())
+}
+
+/// 查询 webhook 投递记录的参数.
+#[derive(Deserialize)]
+pub struct DeliveryQuery {
+    /// 只查询该状态的记录.
+    status: Option<DeliveryStatus>,
+    /// 最多返回的条数, 默认 50, 最大 500.
+    limit: Option<usize>,
+}
+
+/// 按 ID 倒序返回 webhook 投递记录, 如 `?status=dead` 查询死信
+pub async fn list_webhook_deliveries(
+    State(app_context): State<Arc<AppContext>>,
+    Query(query): Query<DeliveryQuery>,
+) -> Result<Json<Vec<WebhookDelivery>>, ApiError> {
+    let webhooks = webhooks(&app_context)?;
+    let limit = query.limit.unwrap_or(50).min(500);
+    Ok(Json(webhooks.list(query.status, limit).await?))
+}
+
+/// 返回单条 webhook 投递记录
+pub async fn get_webhook_delivery(
+    State(app_context): State<Arc<AppContext>>,
+    Path(id): Path<u64>,
+) -> Result<Json<WebhookDelivery>, ApiError> {
+    let webhooks = webhooks(&app_context)?;
+    match webhooks.get(id).await? {
+        Some(delivery) => Ok(Json(delivery)),
+        None => Err(ApiError::NotFound(format!("没有找到 webhook #{id}."))),
+    }
+}
+
+/// 重新投递死信
+pub async fn retry_webhook_delivery(
+    State(app_context): State<Arc<AppContext>>,
+    Path(id): Path<u64>,
+) -> Result<Json<Value>, ApiError> {
+    let webhooks = webhooks(&app_context)?;
+    if !webhooks.requeue(id).await? {
+        return Err(ApiError::NotFound(format!("没有找到死信 webhook #{id}.")));
+    }
+    Ok(Json(json!({"id": id, "status": DeliveryStatus::Pending})))
+}
+
+fn webhooks(app_context: &AppContext) -> Result<&WebhookDispatcher, ApiError> {
+    app_context
+        .webhooks
+        .as_deref()
+        .ok_or_else(|| ApiError::NotFound("没有启用 webhook.".to_owned()))
+}
+
+/// 在请求的追踪范围内处理请求, 并通过 `X-Request-Id` 响应头返回 trace ID.
+///
+/// trace ID 取自请求头 `traceparent` 或 `X-Request-Id`, 都没有时生成新的.
+async fn trace_request(request: Request, next: Next) -> Response {
+    let context = TraceContext::from_headers(request.headers());
+    let trace_id = HeaderValue::from_str(context.trace_id());
+    let mut response = context.scope(next.run(request)).await;
+    if let Ok(trace_id) = trace_id {
+        response.headers_mut().insert(trace::REQUEST_ID, trace_id);
+    }
+    response
+}
+
+/// 校验管理接口和 webhook 投递记录接口的令牌 (`Authorization: Bearer {http.admin_token}`).
+///
+/// 没有配置 `http.admin_token` 时拒绝所有请求. 令牌取自当前配置, 重新加载后立即生效.
+async fn require_admin(
+    State(app_context): State<Arc<AppContext>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let expected = app_context.config.borrow().http.admin_token.clone();
+    let Some(expected) = expected else {
+        return Err(ApiError::Forbidden(
+            "没有配置 http.admin_token, 管理接口不可用.".to_owned(),
+        ));
+    };
+    let token = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    match token {
+        Some(token) if token_eq(token.trim().as_bytes(), expected.as_bytes()) => {
+            Ok(next.run(request).await)
+        }
+        _ => Err(ApiError::Unauthorized),
+    }
+}
+
+/// 比较令牌, 耗时与第一个不同字节的位置无关.
+fn token_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// 启动 HTTP 服务, `shutdown` 取消后停止.
+///
+/// 停止时不再接收新的连接, 等待处理中的请求完成, 最多等待 `http.shutdown_timeout_ms`,
+/// 超时后不再等待, 直接返回.
+pub async fn start_http(
+    app_context: Arc<AppContext>,
+    shutdown: CancellationToken,
+) -> anyhow::Result<()> {
+    let (bind, drain) = {
+        let config = app_context.config.borrow();
+        let drain = Duration::from_millis(config.http.shutdown_timeout_ms);
+        (config.http.bind.clone(), drain)
+    };
+    // 管理接口和 webhook 投递记录需要令牌, 见 [require_admin]
+    let admin = Router::new()
+        .route("/admin/http_clients", get(http_clients_stats))
+        .route("/admin/runtime", get(runtime_stats))
+        .route(
+            "/admin/log",
+            get(get_log_level)
+                .put(put_log_level)
+                .delete(delete_log_level),
+        )
+        .route("/webhooks/deliveries", get(list_webhook_deliveries))
+        .route("/webhooks/deliveries/{id}", get(get_webhook_delivery))
+        .route(
+            "/webhooks/deliveries/{id}/retry",
+            post(retry_webhook_delivery),
+        )
+        .route_layer(middleware::from_fn_with_state(
+            app_context.clone(),
+            require_admin,
+        ));
     let app = Router::new()
         .route("/system_info", get(system_info))
-        .with_state(http_shared);
+        .merge(admin)
+        .layer(middleware::from_fn(trace_request))
+        .with_state(app_context);
+
+    let listener = tokio::net::TcpListener::bind(&bind).await?;
+    log::info!("HTTP 服务已启动, 监听 {bind}.");
+    let server = tokio::spawn(
+        axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown.clone().cancelled_owned())
+            .into_future(),
+    );
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
-    axum::serve(listener, app).await?;
+    let abort = server.abort_handle();
+    tokio::spawn(async move {
+        shutdown.cancelled().await;
+        log::info!("HTTP 服务停止接收新的请求, 等待处理中的请求完成.");
+        sleep(drain).await;
+        if !abort.is_finished() {
+            log::warn!("等待请求完成超时 ({drain:?}), 不再等待.");
+            abort.abort();
+        }
+    });
+
+    match server.await {
+        Ok(result) => result?,
+        Err(e) if e.is_cancelled() => {}
+        Err(e) => return Err(e.into()),
+    }
+    log::info!("HTTP 服务已停止.");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::header::CONTENT_TYPE;
+
+    impl HasBodySchemas for BodySchemas {
+        fn body_schemas(&self) -> &BodySchemas {
+            self
+        }
+    }
+
+    /// 没有注册 Schema 的请求体.
+    #[derive(Debug, Deserialize)]
+    struct Unchecked {
+        id: u64,
+    }
+
+    impl RequestSchema for Unchecked {
+        const NAME: &'static str = "unchecked";
+    }
+
+    async fn extract<T: DeserializeOwned + RequestSchema>(
+        content_type: &str,
+        body: &str,
+    ) -> Result<T, ApiError> {
+        let request = Request::builder()
+            .method("PUT")
+            .uri("/")
+            .header(CONTENT_TYPE, content_type)
+            .body(Body::from(body.to_owned()))
+            .unwrap();
+        let ValidatedJson(value) =
+            ValidatedJson::<T>::from_request(request, &BodySchemas::builtin()).await?;
+        Ok(value)
+    }
+
+    #[tokio::test]
+    async fn validated_json_accepts_valid_body() {
+        let body =
+            extract::<SetLogLevel>("application/json", r#"{"spec": "debug", "ttl_secs": 60}"#)
+                .await
+                .unwrap();
+        assert_eq!(body.spec, "debug");
+        assert_eq!(body.ttl_secs, Some(60));
+    }
+
+    #[tokio::test]
+    async fn validated_json_reports_all_violations() {
+        let err = extract::<SetLogLevel>(
+            "application/json",
+            r#"{"spec": "", "ttl_secs": 604801, "extra": 1}"#,
+        )
+        .await
+        .unwrap_err();
+        let ApiError::Validation(e) = err else {
+            panic!("{err:?}");
+        };
+        let mut paths: Vec<_> = e.violations.iter().map(|v| v.path.as_str()).collect();
+        paths.sort_unstable();
+        assert_eq!(paths, ["/extra", "/spec", "/ttl_secs"]);
+    }
+
+    #[tokio::test]
+    async fn validated_json_rejects_invalid_json() {
+        let err = extract::<SetLogLevel>("text/plain", r#"{"spec": "debug"}"#)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                ApiError::InvalidBody(StatusCode::UNSUPPORTED_MEDIA_TYPE, _)
+            ),
+            "{err:?}"
+        );
+        let err = extract::<SetLogLevel>("application/json", "{")
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err, ApiError::InvalidBody(StatusCode::BAD_REQUEST, _)),
+            "{err:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn validated_json_without_schema_only_deserializes() {
+        let err = extract::<Unchecked>("application/json", r#"{"id": "x"}"#)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(
+                err,
+                ApiError::InvalidBody(StatusCode::UNPROCESSABLE_ENTITY, _)
+            ),
+            "{err:?}"
+        );
+        let body = extract::<Unchecked>("application/json", r#"{"id": 1, "x": 2}"#)
+            .await
+            .unwrap();
+        assert_eq!(body.id, 1);
+    }
+
+    #[test]
+    fn token_eq_compares_whole_token() {
+        assert!(token_eq(b"0123456789abcdef", b"0123456789abcdef"));
+        assert!(!token_eq(b"0123456789abcdef", b"0123456789abcdeF"));
+        assert!(!token_eq(b"0123456789abcde", b"0123456789abcdef"));
+    }
+}
@@ -7,78 +7,247 @@
 //!   - 配置依赖关系
 //!   - 启动服务等
 
+mod app_config;
 mod app_context;
+mod command;
 mod http;
 
+use crate::app_config::{AppConfig, RuntimeConfig, RuntimeFlavor};
 use crate::app_context::AppContext;
+use crate::command::{Cli, Command, ServeArgs};
+use clap::Parser;
 use dotenvy::from_filename;
+use flexi_logger::LoggerHandle;
 use http::start_http;
-use internal_core::mqtt_event::dispatch_mqtt_events;
-use internal_shared::flexi_logger::init_flexi_logger;
-use std::env;
+use internal_shared::config::ConfigWatcher;
+use internal_shared::flexi_logger::{LogLevelControl, init_flexi_logger};
+use internal_shared::yaml::app_env;
 use std::io::Result;
+use std::path::PathBuf;
 use std::process::exit;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::runtime::{Builder, Runtime};
 use tokio::signal;
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
 
 fn main() {
+    let mut cli = Cli::parse();
+    if let Some(env) = &cli.env {
+        // SAFETY: 此时还没有启动其他线程
+        unsafe { std::env::set_var("APP_ENV", env) };
+    }
     // 决定环境 (默认 development)
-    let env = env::var("APP_ENV").unwrap_or_else(|_| "development".into());
+    let env = app_env();
     let env_file = format!(".env.{env}");
     from_filename(&env_file).ok();
 
-    let logger = init_flexi_logger().unwrap();
+    // 除 serve 以外的命令执行后直接退出
+    let serve = match cli.command.take() {
+        None => ServeArgs::default(),
+        Some(Command::Serve(args)) => args,
+        Some(command) => exit(command::run(command, &cli)),
+    };
 
-    let runtime = new_multi_thread().unwrap();
-    runtime.block_on(async_main());
-    runtime.block_on(async move {
-        signal::ctrl_c().await.unwrap();
-        logger.flush();
-        logger.shutdown();
-    });
+    // 加载并校验所有配置, 有错误时在建立任何连接之前退出
+    let dir = cli.config_dir();
+    let load = {
+        let dir = dir.clone();
+        move || {
+            AppConfig::load(&dir).map(|c| {
+                c.with_log_level(cli.log_level.as_deref())
+                    .with_worker_threads(serve.worker_threads)
+            })
+        }
+    };
+    let config = match load() {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{e:#}");
+            exit(1);
+        }
+    };
+
+    let logger = init_flexi_logger(&config.logging).unwrap();
+
+    let runtime = new_runtime(&config.runtime).unwrap();
+    let code = runtime.block_on(async_main(config, dir, load, logger.clone()));
+    // 不等待超时后仍未结束的请求
+    runtime.shutdown_background();
+    logger.flush();
+    logger.shutdown();
+    exit(code);
 }
 
-/// 异步执行入口
-async fn async_main() {
-    let app_context = AppContext::build().await;
+/// 异步执行入口, 返回退出码.
+///
+/// 收到 SIGINT 或 SIGTERM 后按顺序退出: 停止 HTTP 服务并等待处理中的请求,
+/// 停止 MQTT 事件分发等后台任务, 断开 MQTT, 关闭连接池. 最后由 `main` 刷新并关闭日志.
+async fn async_main(
+    config: AppConfig,
+    dir: PathBuf,
+    load: impl Fn() -> anyhow::Result<AppConfig> + Send + Sync + 'static,
+    logger: LoggerHandle,
+) -> i32 {
+    let shutdown = CancellationToken::new();
+    listen_for_shutdown(&shutdown);
+
+    // 配置文件变化或收到 SIGHUP 时重新加载, 校验失败时保留旧配置
+    let log_level = LogLevelControl::new(logger, &config.logging.level);
+    let config = ConfigWatcher::new(dir).spawn(config, load);
+    watch_log_level(config.clone(), log_level.clone());
+
+    let app_context = AppContext::build(config, log_level).await;
     let mut app_context = match app_context {
         Ok(v) => v,
         Err(e) => {
             log::error!("build app context error: {e}");
-            exit(1);
+            return 1;
         }
     };
 
-    if let Some(context) = app_context.mqtt_event_dispatch_context {
-        dispatch_mqtt_events(context);
-        app_context.mqtt_event_dispatch_context = None;
-    }
+    // 后台任务在 HTTP 服务停止后再停止, 处理中的请求仍可以使用它们
+    let background = CancellationToken::new();
+    let tasks = app_context.spawn_background(&background);
+    let app_context = Arc::new(app_context);
+
+    let code = match start_http(app_context.clone(), shutdown).await {
+        Ok(()) => 0,
+        Err(e) => {
+            log::error!("start http service error: {e}");
+            1
+        }
+    };
 
-    if let Err(e) = start_http(app_context).await {
-        log::error!("start http service error: {e}");
-        exit(1);
+    background.cancel();
+    for task in tasks {
+        if let Err(e) = task.await {
+            log::error!("后台任务异常退出: {e}");
+        }
     }
+    app_context.close().await;
+    log::info!("已退出.");
+    code
 }
 
-/// 新建多线程运行时
-#[allow(dead_code)]
-fn new_multi_thread() -> Result<Runtime> {
-    builder(Builder::new_multi_thread().worker_threads(5))
+/// 收到 SIGINT 或 SIGTERM 时取消 `shutdown`.
+fn listen_for_shutdown(shutdown: &CancellationToken) {
+    let interrupt = shutdown.clone();
+    tokio::spawn(async move {
+        match signal::ctrl_c().await {
+            Ok(()) => {
+                log::info!("收到 SIGINT, 开始退出.");
+                interrupt.cancel();
+            }
+            Err(e) => log::error!("监听 SIGINT 失败: {e}"),
+        }
+    });
+    listen_for_terminate(shutdown.clone());
 }
 
-/// 使用当前线程新建运行时
-#[allow(dead_code)]
-fn new_current_thread() -> Result<Runtime> {
-    builder(&mut Builder::new_current_thread())
+/// 收到 SIGTERM 时取消 `shutdown`.
+#[cfg(unix)]
+fn listen_for_terminate(shutdown: CancellationToken) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("监听 SIGTERM 失败: {e}");
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        if terminate.recv().await.is_some() {
+            log::info!("收到 SIGTERM, 开始退出.");
+            shutdown.cancel();
+        }
+    });
 }
 
-/// 配置 Builder
-fn builder(builder: &mut Builder) -> Result<Runtime> {
+#[cfg(not(unix))]
+fn listen_for_terminate(_shutdown: CancellationToken) {}
+
+/// 配置中的日志级别变化时, 更新日志级别.
+fn watch_log_level(mut config: watch::Receiver<Arc<AppConfig>>, log_level: LogLevelControl) {
+    tokio::spawn(async move {
+        while config.changed().await.is_ok() {
+            let level = config.borrow_and_update().logging.level.clone();
+            if let Err(e) = log_level.set_configured(&level) {
+                log::error!("{e:#}");
+            }
+        }
+    });
+}
+
+/// 按配置新建运行时
+fn new_runtime(config: &RuntimeConfig) -> Result<Runtime> {
+    let mut builder = match config.flavor {
+        RuntimeFlavor::MultiThread => {
+            let mut builder = Builder::new_multi_thread();
+            if let Some(worker_threads) = config.worker_threads {
+                builder.worker_threads(worker_threads);
+            }
+            builder
+        }
+        RuntimeFlavor::CurrentThread => Builder::new_current_thread(),
+    };
     builder
         .enable_all()
-        .max_io_events_per_tick(2048)
-        .max_blocking_threads(512)
-        .thread_keep_alive(Duration::from_secs(60))
-        .build()
+        .thread_name(&config.thread_name)
+        .max_io_events_per_tick(config.max_io_events_per_tick)
+        .max_blocking_threads(config.max_blocking_threads)
+        .thread_keep_alive(Duration::from_millis(config.thread_keep_alive_ms));
+    if let Some(size) = config.thread_stack_size {
+        builder.thread_stack_size(size);
+    }
+    builder.build()
+}
+
+/// 使用当前线程新建运行时, 用于运维命令
+fn new_current_thread() -> Result<Runtime> {
+    new_runtime(&RuntimeConfig {
+        flavor: RuntimeFlavor::CurrentThread,
+        ..RuntimeConfig::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 在按配置创建的运行时中读取运行时状态.
+    fn stats(config: &RuntimeConfig) -> serde_json::Value {
+        let runtime = new_runtime(config).unwrap();
+        let axum::Json(stats) = runtime.block_on(http::runtime_stats());
+        stats
+    }
+
+    #[test]
+    fn builds_multi_thread_runtime_from_config() {
+        let stats = stats(&RuntimeConfig {
+            worker_threads: Some(2),
+            thread_name: "test-worker".to_owned(),
+            thread_stack_size: Some(4 * 1024 * 1024),
+            ..RuntimeConfig::default()
+        });
+        assert_eq!(stats["flavor"], "multi_thread");
+        assert_eq!(stats["num_workers"], 2);
+        assert_eq!(stats["workers"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn builds_current_thread_runtime_from_config() {
+        // worker_threads 对 current_thread 无效
+        let stats = stats(&RuntimeConfig {
+            flavor: RuntimeFlavor::CurrentThread,
+            worker_threads: Some(4),
+            ..RuntimeConfig::default()
+        });
+        assert_eq!(stats["flavor"], "current_thread");
+        assert_eq!(stats["num_workers"], 1);
+        // 读取状态的就是唯一的工作线程, 它一定醒着
+        assert_eq!(stats["busy_workers"], 1);
+    }
 }
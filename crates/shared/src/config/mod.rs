@@ -0,0 +1,72 @@
+//! 配置校验与热加载
+//!
+//! - 各配置类型实现 [Validate], 启动时一次性收集所有错误, 在建立任何连接之前统一报告.
+//! - [ConfigWatcher] 在配置文件变化或收到 `SIGHUP` 时重新加载, 通过 `watch` 通道发布新配置.
+
+mod watcher;
+
+pub use watcher::ConfigWatcher;
+
+use std::fmt::{self, Display};
+
+/// 可以校验的配置.
+pub trait Validate {
+    /// 校验配置, 将发现的所有问题写入 `errors`.
+    ///
+    /// # Arguments
+    ///
+    /// * `section` - 配置所在的段落名, 用于错误信息, 如 `mysql`.
+    /// * `errors` - 错误收集器.
+    fn validate(&self, section: &str, errors: &mut ConfigErrors);
+}
+
+/// 配置错误的收集器.
+#[derive(Debug, Default)]
+pub struct ConfigErrors {
+    errors: Vec<String>,
+}
+
+impl ConfigErrors {
+    /// 记录一个错误.
+    pub fn push(&mut self, section: &str, message: impl Display) {
+        self.errors.push(format!("{section}: {message}"));
+    }
+
+    /// 条件不成立时记录错误.
+    pub fn check(&mut self, ok: bool, section: &str, message: impl Display) {
+        if !ok {
+            self.push(section, message);
+        }
+    }
+
+    /// 是否没有错误.
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// 所有错误信息.
+    pub fn messages(&self) -> &[String] {
+        &self.errors
+    }
+
+    /// 没有错误时返回 `Ok(())`, 否则返回自身.
+    ///
+    /// # Errors
+    ///
+    /// 收集到任意错误时返回 [ConfigErrors].
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.is_empty() { Ok(()) } else { Err(self) }
+    }
+}
+
+impl Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "配置校验失败, 共 {} 处错误:", self.errors.len())?;
+        for error in &self.errors {
+            write!(f, "\n  - {error}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
@@ -0,0 +1,216 @@
+//! 配置热加载
+//!
+//! 使用例子:
+//!
+//! ```ignore
+//! use internal_shared::config::ConfigWatcher;
+//!
+//! let dir = AppConfig::dir();
+//! let mut rx = ConfigWatcher::new(&dir).spawn(config, move || AppConfig::load(&dir));
+//! while rx.changed().await.is_ok() {
+//!     let config = rx.borrow_and_update().clone();
+//!     // 使用新配置
+//! }
+//! ```
+
+use anyhow::{Result, anyhow};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::fs;
+use tokio::sync::{mpsc, watch};
+use tokio::time::{MissedTickBehavior, interval};
+
+/// 默认检查文件变化的间隔.
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// 监听配置目录, 在文件变化或收到 `SIGHUP` 时重新加载配置.
+///
+/// 重新加载失败 (文件格式错误、校验不通过等) 时只记录日志, 继续使用上一次成功加载的配置.
+pub struct ConfigWatcher {
+    dir: PathBuf,
+    interval: Duration,
+}
+
+/// 触发重新加载的原因.
+#[derive(Debug, Clone, Copy)]
+enum Trigger {
+    /// 目录中的 yaml 文件发生了变化.
+    Modified,
+    /// 收到 `SIGHUP`.
+    Hangup,
+}
+
+impl ConfigWatcher {
+    /// 监听 `dir` 目录中的 `.yaml` 和 `.yml` 文件.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            interval: DEFAULT_INTERVAL,
+        }
+    }
+
+    /// 设置检查文件变化的间隔, 默认 2 秒.
+    #[must_use]
+    pub const fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// 启动后台任务, 返回配置的接收端. 必须在 Tokio 运行时中调用.
+    ///
+    /// # Arguments
+    ///
+    /// * `initial` - 启动时已经加载的配置.
+    /// * `load` - 重新加载配置, 应包含校验, 返回错误时保留旧配置. 在阻塞线程池中执行.
+    pub fn spawn<T, F>(self, initial: T, load: F) -> watch::Receiver<Arc<T>>
+    where
+        T: Send + Sync + 'static,
+        F: Fn() -> Result<T> + Send + Sync + 'static,
+    {
+        let (tx, rx) = watch::channel(Arc::new(initial));
+        let (trigger_tx, mut trigger_rx) = mpsc::channel(1);
+        let load = Arc::new(load);
+
+        spawn_poll(self.dir, self.interval, trigger_tx.clone());
+        spawn_hangup(trigger_tx);
+
+        tokio::spawn(async move {
+            while let Some(trigger) = trigger_rx.recv().await {
+                let load = Arc::clone(&load);
+                let loaded = tokio::task::spawn_blocking(move || load())
+                    .await
+                    .unwrap_or_else(|e| Err(anyhow!("加载配置的任务异常退出: {e}")));
+                match loaded {
+                    Ok(config) => {
+                        log::info!("配置已重新加载 ({trigger:?}).");
+                        if tx.send(Arc::new(config)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        log::error!("重新加载配置失败 ({trigger:?}), 继续使用上一次的配置: {e:#}");
+                    }
+                }
+            }
+        });
+        rx
+    }
+}
+
+/// 定期比较目录中文件的修改时间和大小.
+fn spawn_poll(dir: PathBuf, period: Duration, trigger: mpsc::Sender<Trigger>) {
+    tokio::spawn(async move {
+        let mut ticker = interval(period);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let mut last = snapshot(&dir).await;
+        loop {
+            ticker.tick().await;
+            let current = snapshot(&dir).await;
+            if current == last {
+                continue;
+            }
+            last = current;
+            if trigger.send(Trigger::Modified).await.is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// 收到 `SIGHUP` 时触发重新加载.
+#[cfg(unix)]
+fn spawn_hangup(trigger: mpsc::Sender<Trigger>) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("监听 SIGHUP 失败: {e}");
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            if trigger.send(Trigger::Hangup).await.is_err() {
+                break;
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+fn spawn_hangup(_trigger: mpsc::Sender<Trigger>) {}
+
+/// 目录中所有 yaml 文件的路径、修改时间和大小, 按路径排序.
+async fn snapshot(dir: &Path) -> Vec<(PathBuf, Option<SystemTime>, u64)> {
+    let Ok(mut entries) = fs::read_dir(dir).await else {
+        return Vec::new();
+    };
+    let mut files = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        if !path
+            .extension()
+            .is_some_and(|ext| ext == "yaml" || ext == "yml")
+        {
+            continue;
+        }
+        let meta = fs::metadata(&path).await.ok();
+        let modified = meta.as_ref().and_then(|m| m.modified().ok());
+        let len = meta.map_or(0, |m| m.len());
+        files.push((path, modified, len));
+    }
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reloads_when_file_changes() {
+        let dir = std::env::temp_dir().join(format!("config_watcher_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("app.yaml");
+        std::fs::write(&file, "1").unwrap();
+
+        let load = {
+            let file = file.clone();
+            move || Ok(std::fs::read_to_string(&file)?)
+        };
+        let mut rx = ConfigWatcher::new(&dir)
+            .with_interval(Duration::from_millis(50))
+            .spawn(String::from("1"), load);
+
+        // 等第一次快照完成再修改, 长度也变化, 不依赖修改时间的精度
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        std::fs::write(&file, "22").unwrap();
+        tokio::time::timeout(Duration::from_secs(5), rx.changed())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(rx.borrow_and_update().as_str(), "22");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn snapshot_lists_only_yaml_files() {
+        let dir = std::env::temp_dir().join(format!("config_snapshot_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["b.yml", "a.yaml", "c.txt"] {
+            std::fs::write(dir.join(name), name).unwrap();
+        }
+        let names: Vec<_> = snapshot(&dir)
+            .await
+            .into_iter()
+            .map(|(path, _, len)| (path.file_name().unwrap().to_owned(), len))
+            .collect();
+        assert_eq!(names, [("a.yaml".into(), 6), ("b.yml".into(), 5)]);
+        assert!(snapshot(&dir.join("missing")).await.is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
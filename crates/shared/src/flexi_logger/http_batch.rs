@@ -0,0 +1,311 @@
+//! 批量发送日志到 HTTP 日志收集服务.
+//!
+//! 以 `POST` 发送, 请求体为按日志格式化后的多行日志, 每行一条: `json` 格式时为 NDJSON
+//! (`application/x-ndjson`), `text` 格式时为 `text/plain`. 非 2xx 响应视为失败, 这一批日志被丢弃,
+//! 不重试.
+//!
+//! 发送时使用独立的阻塞客户端, 不经过 [crate::reqwest]. 为了避免发送过程中产生的日志再被发送,
+//! `reqwest`, `hyper` 等 HTTP 相关模块的日志不会发到收集服务, 只写入文件.
+
+use super::queue::{Batching, Queue, Sink, default_queue_size};
+use super::{LogFormat, json_format, text_format};
+use crate::config::{ConfigErrors, Validate};
+use flexi_logger::writers::LogWriter;
+use flexi_logger::{DeferredNow, FormatFunction};
+use log::Record;
+use reqwest::Url;
+use reqwest::blocking::Client;
+use reqwest::header::{CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::io;
+use std::time::Duration;
+
+/// 不发送到收集服务的日志模块前缀.
+const IGNORED_TARGETS: &[&str] = &["reqwest", "hyper", "hyper_util", "h2", "rustls", "want"];
+
+/// HTTP 日志收集服务配置
+#[derive(Debug, Clone, Deserialize)]
+pub struct HttpLogOptions {
+    /// 接收地址
+    pub url: String,
+    /// 额外的请求头, 如 `Authorization`
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// 每批最多条数
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    /// 收到第一条日志后最多等待多久发送, 毫秒
+    #[serde(default = "default_batch_interval_ms")]
+    pub batch_interval_ms: u64,
+    /// 请求超时, 毫秒
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    /// 队列容量 (条), 队列满时丢弃新的日志
+    #[serde(default = "default_queue_size")]
+    pub queue_size: usize,
+}
+
+const fn default_batch_size() -> usize {
+    500
+}
+
+const fn default_batch_interval_ms() -> u64 {
+    1_000
+}
+
+const fn default_timeout_ms() -> u64 {
+    5_000
+}
+
+impl Validate for HttpLogOptions {
+    fn validate(&self, section: &str, errors: &mut ConfigErrors) {
+        if let Err(e) = Url::parse(&self.url) {
+            errors.push(section, format!("url `{}` 无效: {e}", self.url));
+        }
+        for (name, value) in &self.headers {
+            errors.check(
+                HeaderName::try_from(name).is_ok() && HeaderValue::try_from(value).is_ok(),
+                section,
+                format!("请求头 {name} 无效"),
+            );
+        }
+        errors.check(self.batch_size > 0, section, "batch_size 必须大于 0");
+        errors.check(self.timeout_ms > 0, section, "timeout_ms 必须大于 0");
+        errors.check(self.queue_size > 0, section, "queue_size 必须大于 0");
+    }
+}
+
+/// 批量发送日志到 HTTP 日志收集服务的 [LogWriter].
+///
+/// 日志先放入队列, 由单独的线程按批发送, 不会阻塞写日志的线程.
+pub struct HttpLogWriter {
+    queue: Queue,
+    format: FormatFunction,
+}
+
+impl HttpLogWriter {
+    /// 创建并启动发送线程.
+    ///
+    /// # Arguments
+    ///
+    /// * `opt` - 收集服务配置.
+    /// * `format` - 日志格式, 决定请求的 `Content-Type`.
+    ///
+    /// # Errors
+    ///
+    /// 启动发送线程失败时返回错误.
+    pub fn new(opt: &HttpLogOptions, format: LogFormat) -> io::Result<Self> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &opt.headers {
+            let name = HeaderName::try_from(name).map_err(io::Error::other)?;
+            let value = HeaderValue::try_from(value).map_err(io::Error::other)?;
+            headers.insert(name, value);
+        }
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static(match format {
+                LogFormat::Text => "text/plain; charset=utf-8",
+                LogFormat::Json => "application/x-ndjson",
+            }),
+        );
+        let url = opt.url.clone();
+        let timeout = Duration::from_millis(opt.timeout_ms);
+        let queue = Queue::start(
+            "http-log-writer",
+            opt.queue_size,
+            Batching {
+                max_lines: opt.batch_size,
+                linger: Duration::from_millis(opt.batch_interval_ms),
+            },
+            // 阻塞客户端要在发送线程中创建, 不能在 tokio 运行时中创建或销毁
+            move || {
+                let client = Client::builder()
+                    .timeout(timeout)
+                    .default_headers(headers)
+                    .build()
+                    .map_err(io::Error::other)?;
+                Ok(Box::new(HttpSink {
+                    client,
+                    url,
+                    format,
+                }) as Box<dyn Sink>)
+            },
+        )?;
+        Ok(Self {
+            queue,
+            format: match format {
+                LogFormat::Text => text_format,
+                LogFormat::Json => json_format,
+            },
+        })
+    }
+}
+
+impl LogWriter for HttpLogWriter {
+    fn write(&self, now: &mut DeferredNow, record: &Record) -> io::Result<()> {
+        let target = record.target();
+        let ignored = IGNORED_TARGETS.iter().any(|t| {
+            target
+                .strip_prefix(t)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+        });
+        if ignored {
+            return Ok(());
+        }
+        let mut line = Vec::new();
+        (self.format)(&mut line, now, record)?;
+        self.queue
+            .push(String::from_utf8_lossy(&line).trim_end().to_owned());
+        Ok(())
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.queue.flush();
+        Ok(())
+    }
+
+    fn format(&mut self, format: FormatFunction) {
+        self.format = format;
+    }
+
+    fn shutdown(&self) {
+        self.queue.drain();
+    }
+}
+
+struct HttpSink {
+    client: Client,
+    url: String,
+    format: LogFormat,
+}
+
+impl Sink for HttpSink {
+    fn send(&mut self, lines: &[String]) -> io::Result<()> {
+        let response = self
+            .client
+            .post(&self.url)
+            .body(lines.join("\n"))
+            .send()
+            .map_err(io::Error::other)?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!("返回 {}", response.status())))
+        }
+    }
+
+    fn dropped_notice(&self, count: u64) -> String {
+        let message = format!("日志队列已满或发送失败, 丢弃了 {count} 条日志");
+        let mut now = DeferredNow::new();
+        match self.format {
+            LogFormat::Text => format!(
+                "[{}] T[{}] WARN [{}] {message}",
+                now.format("%Y-%m-%d %H:%M:%S%.6f"),
+                std::thread::current().name().unwrap_or("<unnamed>"),
+                module_path!(),
+            ),
+            LogFormat::Json => json!({
+                "timestamp": now.format_rfc3339(),
+                "level": "WARN",
+                "thread": std::thread::current().name().unwrap_or("<unnamed>"),
+                "module": module_path!(),
+                "message": message,
+            })
+            .to_string(),
+        }
+    }
+
+    fn describe(&self) -> String {
+        self.url.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+    use serde_json::Value;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+
+    /// 接收一个请求, 返回请求头 (小写) 和请求体.
+    fn serve_once(listener: TcpListener, tx: mpsc::Sender<(Vec<String>, String)>) {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut headers = Vec::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            headers.push(line.to_lowercase());
+        }
+        let len = headers
+            .iter()
+            .find_map(|h| h.strip_prefix("content-length: "))
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0);
+        let mut body = vec![0; len];
+        reader.read_exact(&mut body).unwrap();
+        reader
+            .get_mut()
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        tx.send((headers, String::from_utf8(body).unwrap()))
+            .unwrap();
+    }
+
+    fn write(writer: &HttpLogWriter, target: &str, msg: &str) {
+        writer
+            .write(
+                &mut DeferredNow::new(),
+                &Record::builder()
+                    .args(format_args!("{msg}"))
+                    .level(Level::Info)
+                    .target(target)
+                    .module_path(Some(target))
+                    .build(),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn posts_ndjson_batch() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let opt = HttpLogOptions {
+            url: format!("http://{}/logs", listener.local_addr().unwrap()),
+            headers: BTreeMap::from([("X-Token".to_owned(), "abc".to_owned())]),
+            batch_size: 10,
+            batch_interval_ms: 200,
+            timeout_ms: 5_000,
+            queue_size: 10,
+        };
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || serve_once(listener, tx));
+
+        let writer = HttpLogWriter::new(&opt, LogFormat::Json).unwrap();
+        write(&writer, "app::device", "设备上线");
+        write(&writer, "reqwest::connect", "不应发送");
+        write(&writer, "app::order", "下单 \"A\"");
+        writer.shutdown();
+
+        let (headers, body) = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(headers.iter().any(|h| h.starts_with("post /logs ")));
+        assert!(headers.contains(&"content-type: application/x-ndjson".to_owned()));
+        assert!(headers.contains(&"x-token: abc".to_owned()));
+        let lines: Vec<Value> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["message"], "设备上线");
+        assert_eq!(lines[0]["module"], "app::device");
+        assert_eq!(lines[1]["message"], "下单 \"A\"");
+    }
+}
@@ -0,0 +1,820 @@
+//! 初始化 `flexi_logger` 日志.
+//!
+//! 日志写入本地文件, 也可以同时发送到 syslog ([SyslogWriter]) 和 HTTP 日志收集服务 ([HttpLogWriter]).
+
+mod http_batch;
+mod queue;
+mod syslog;
+
+pub use http_batch::{HttpLogOptions, HttpLogWriter};
+pub use syslog::{SyslogFacility, SyslogOptions, SyslogTransport, SyslogWriter};
+
+use crate::config::{ConfigErrors, Validate};
+use crate::json::Redactor;
+use crate::trace;
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, TimeDelta, Utc};
+use flexi_logger::writers::LogWriter;
+use flexi_logger::{
+    Age, Cleanup, Criterion, DeferredNow, Duplicate, FileSpec, FormatFunction, LogSpecification,
+    Logger, LoggerHandle, Naming, WriteMode,
+};
+use log::Record;
+use log::kv::{self, Key, VisitSource};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::{thread, time::Duration};
+
+/// 日志格式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// 文本, 便于阅读
+    #[default]
+    Text,
+    /// 每行一个 JSON 对象, 便于日志系统采集
+    Json,
+}
+
+/// 复制到标准输出的日志级别
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StdoutLevel {
+    /// 不复制
+    Off,
+    /// `error`
+    Error,
+    /// `warn` 及以上
+    Warn,
+    /// `info` 及以上
+    Info,
+    /// `debug` 及以上
+    Debug,
+    /// `trace` 及以上
+    Trace,
+    /// 所有日志
+    All,
+}
+
+impl From<StdoutLevel> for Duplicate {
+    fn from(level: StdoutLevel) -> Self {
+        match level {
+            StdoutLevel::Off => Self::None,
+            StdoutLevel::Error => Self::Error,
+            StdoutLevel::Warn => Self::Warn,
+            StdoutLevel::Info => Self::Info,
+            StdoutLevel::Debug => Self::Debug,
+            StdoutLevel::Trace => Self::Trace,
+            StdoutLevel::All => Self::All,
+        }
+    }
+}
+
+/// 按时间切分的周期
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RotateAge {
+    /// 每天
+    Day,
+    /// 每小时
+    Hour,
+}
+
+/// 日志文件切分和清理
+///
+/// `max_size_mb` 和 `age` 都设置时, 满足任意一个就切分; 都为 `null` 时不切分.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LogRotation {
+    /// 文件达到多少 MB 时切分
+    pub max_size_mb: Option<u64>,
+    /// 按时间切分
+    pub age: Option<RotateAge>,
+    /// 保留的文件数, 不包括当前文件
+    pub keep_files: Option<usize>,
+    /// 保留的天数, 不能与 `keep_files` 同时设置
+    pub keep_days: Option<usize>,
+    /// 用 gzip 压缩切分出的文件, 需要设置 `keep_files`
+    pub compress: bool,
+}
+
+impl Default for LogRotation {
+    /// 每天或达到 10MB 时切分, 保留 30 个文件, 不压缩.
+    fn default() -> Self {
+        Self {
+            max_size_mb: Some(10),
+            age: Some(RotateAge::Day),
+            keep_files: Some(30),
+            keep_days: None,
+            compress: false,
+        }
+    }
+}
+
+impl LogRotation {
+    fn criterion(&self) -> Option<Criterion> {
+        let age = self.age.map(|age| match age {
+            RotateAge::Day => Age::Day,
+            RotateAge::Hour => Age::Hour,
+        });
+        let size = self.max_size_mb.map(|mb| mb * 1_000_000);
+        match (age, size) {
+            (Some(age), Some(size)) => Some(Criterion::AgeOrSize(age, size)),
+            (Some(age), None) => Some(Criterion::Age(age)),
+            (None, Some(size)) => Some(Criterion::Size(size)),
+            (None, None) => None,
+        }
+    }
+
+    fn cleanup(&self) -> Cleanup {
+        match (self.keep_files, self.keep_days) {
+            (Some(n), _) if self.compress => Cleanup::KeepCompressedFiles(n),
+            (Some(n), _) => Cleanup::KeepLogFiles(n),
+            (None, Some(days)) => Cleanup::KeepForDays(days),
+            (None, None) => Cleanup::Never,
+        }
+    }
+}
+
+/// 日志配置
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogOptions {
+    /// 日志名称, 用作文件名和默认的日志目录名
+    pub name: String,
+    /// 日志级别, 如 `info` 或 `info,internal_ffi=debug`
+    pub level: String,
+    /// 日志格式, 默认 `text`
+    #[serde(default)]
+    pub format: LogFormat,
+    /// 在默认字段之外, 额外需要脱敏的字段名
+    #[serde(default)]
+    pub redact_keys: Vec<String>,
+    /// 需要脱敏的路径, 点号路径或 JSON Pointer, `*` 匹配任意一段
+    #[serde(default)]
+    pub redact_paths: Vec<String>,
+    /// 日志目录, 默认 debug 构建为 `log/{name}`, release 构建为 `/var/log/{name}`
+    #[serde(default)]
+    pub directory: Option<String>,
+    /// 文件切分和清理
+    #[serde(default)]
+    pub rotation: LogRotation,
+    /// 复制到标准输出的级别, 默认 debug 构建为 `all`, release 构建为 `error`
+    #[serde(default)]
+    pub duplicate_to_stdout: Option<StdoutLevel>,
+    /// 写缓冲区大小, 字节, 为 0 时不缓冲
+    #[serde(default = "default_buffer_size")]
+    pub buffer_size: usize,
+    /// 缓冲区的刷新间隔, 毫秒
+    #[serde(default = "default_flush_interval_ms")]
+    pub flush_interval_ms: u64,
+    /// 同时发送到 syslog, 不配置时不发送
+    #[serde(default)]
+    pub syslog: Option<SyslogOptions>,
+    /// 同时批量发送到 HTTP 日志收集服务, 不配置时不发送
+    #[serde(default)]
+    pub http: Option<HttpLogOptions>,
+}
+
+const fn default_buffer_size() -> usize {
+    64_000
+}
+
+const fn default_flush_interval_ms() -> u64 {
+    5_000
+}
+
+impl LogOptions {
+    /// 日志目录, 没有配置时按构建类型使用默认目录.
+    pub fn directory(&self) -> String {
+        if let Some(directory) = &self.directory {
+            return directory.clone();
+        }
+        if cfg!(debug_assertions) {
+            format!("log/{}", self.name)
+        } else {
+            format!("/var/log/{}", self.name)
+        }
+    }
+}
+
+impl Validate for LogOptions {
+    fn validate(&self, section: &str, errors: &mut ConfigErrors) {
+        errors.check(!self.name.trim().is_empty(), section, "name 不能为空");
+        if let Err(e) = LogSpecification::parse(&self.level) {
+            errors.push(section, format!("level `{}` 无效: {e}", self.level));
+        }
+        errors.check(
+            self.directory.as_ref().is_none_or(|d| !d.trim().is_empty()),
+            section,
+            "directory 不能为空",
+        );
+        errors.check(
+            self.flush_interval_ms > 0,
+            section,
+            "flush_interval_ms 必须大于 0",
+        );
+        let rotation = &self.rotation;
+        errors.check(
+            rotation.max_size_mb != Some(0),
+            section,
+            "rotation.max_size_mb 必须大于 0",
+        );
+        errors.check(
+            rotation.keep_files != Some(0) && rotation.keep_days != Some(0),
+            section,
+            "rotation.keep_files 和 rotation.keep_days 必须大于 0",
+        );
+        errors.check(
+            rotation.keep_files.is_none() || rotation.keep_days.is_none(),
+            section,
+            "rotation.keep_files 和 rotation.keep_days 只能设置一个",
+        );
+        errors.check(
+            !rotation.compress || rotation.keep_files.is_some(),
+            section,
+            "rotation.compress 需要设置 rotation.keep_files",
+        );
+        if let Some(syslog) = &self.syslog {
+            syslog.validate(&format!("{section}.syslog"), errors);
+        }
+        if let Some(http) = &self.http {
+            http.validate(&format!("{section}.http"), errors);
+        }
+    }
+}
+
+/// 初始化 `flexi_logger`, 并按配置设置全局脱敏规则.
+///
+/// # Errors
+///
+/// 如果日志初始化失败，会返回 `flexi_logger::FlexiLoggerError`。
+pub fn init_flexi_logger(opt: &LogOptions) -> Result<LoggerHandle> {
+    let log_name = opt.name.as_str();
+    let log_level = opt.level.as_str();
+
+    let redactor = Redactor::default()
+        .with_keys(&opt.redact_keys)
+        .with_paths(&opt.redact_paths);
+    let _ = Redactor::set_global(redactor);
+
+    let file_spec = FileSpec::default()
+        .directory(opt.directory())
+        .basename(log_name)
+        .suppress_timestamp();
+
+    let mut remote: Vec<Box<dyn LogWriter>> = Vec::new();
+    if let Some(syslog) = &opt.syslog {
+        remote.push(Box::new(
+            SyslogWriter::new(syslog, log_name).context("创建 syslog 日志失败")?,
+        ));
+    }
+    if let Some(http) = &opt.http {
+        remote.push(Box::new(
+            HttpLogWriter::new(http, opt.format).context("创建 HTTP 日志失败")?,
+        ));
+    }
+
+    let mut logger = Logger::try_with_str(log_level)?;
+    logger = if remote.is_empty() {
+        logger.log_to_file(file_spec)
+    } else {
+        logger.log_to_file_and_writer(file_spec, Box::new(Writers(remote)))
+    };
+    logger = logger.format(match opt.format {
+        LogFormat::Text => text_format,
+        LogFormat::Json => json_format,
+    });
+
+    let duplicate = opt
+        .duplicate_to_stdout
+        .unwrap_or(if cfg!(debug_assertions) {
+            StdoutLevel::All
+        } else {
+            StdoutLevel::Error
+        });
+    logger = logger.duplicate_to_stdout(duplicate.into());
+
+    logger = logger.write_mode(if opt.buffer_size == 0 {
+        WriteMode::Direct
+    } else {
+        WriteMode::BufferAndFlushWith(
+            opt.buffer_size,
+            Duration::from_millis(opt.flush_interval_ms),
+        )
+    });
+
+    if let Some(criterion) = opt.rotation.criterion() {
+        logger = logger.rotate(criterion, Naming::Timestamps, opt.rotation.cleanup());
+    }
+    logger = logger.append();
+    Ok(logger.start()?)
+}
+
+/// 依次写入多个 [LogWriter].
+struct Writers(Vec<Box<dyn LogWriter>>);
+
+impl LogWriter for Writers {
+    fn write(&self, now: &mut DeferredNow, record: &Record) -> std::io::Result<()> {
+        self.0.iter().try_for_each(|w| w.write(now, record))
+    }
+
+    fn flush(&self) -> std::io::Result<()> {
+        self.0.iter().try_for_each(|w| w.flush())
+    }
+
+    fn format(&mut self, format: FormatFunction) {
+        for w in &mut self.0 {
+            w.format(format);
+        }
+    }
+
+    fn shutdown(&self) {
+        for w in &self.0 {
+            w.shutdown();
+        }
+    }
+}
+
+/// 临时日志级别的最长有效期, 7 天.
+pub const MAX_LOG_LEVEL_TTL: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// 运行中调整日志级别.
+///
+/// 配置中的级别是基础级别, 可以临时改为其他级别并设置有效期, 到期后自动恢复.
+/// 有临时级别时, 配置变化只更新基础级别, 等临时级别结束后生效.
+#[derive(Clone)]
+pub struct LogLevelControl {
+    handle: LoggerHandle,
+    state: Arc<Mutex<LevelState>>,
+}
+
+struct LevelState {
+    configured: String,
+    temporary: Option<(String, Option<DateTime<Utc>>)>,
+    /// 每次修改加 1, 用于判断到期的是不是当前的临时级别
+    generation: u64,
+}
+
+/// 当前的日志级别.
+#[derive(Debug, Clone, Serialize)]
+pub struct LogLevelStatus {
+    /// 生效中的级别.
+    pub spec: String,
+    /// 配置中的级别.
+    pub configured: String,
+    /// 是否为临时级别.
+    pub temporary: bool,
+    /// 临时级别的到期时间, 不会到期时为 `None`.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl LogLevelControl {
+    /// 创建, `configured` 为当前配置中的级别.
+    pub fn new(handle: LoggerHandle, configured: &str) -> Self {
+        Self {
+            handle,
+            state: Arc::new(Mutex::new(LevelState {
+                configured: configured.to_owned(),
+                temporary: None,
+                generation: 0,
+            })),
+        }
+    }
+
+    /// 当前的日志级别.
+    pub fn status(&self) -> LogLevelStatus {
+        let state = self.lock();
+        match &state.temporary {
+            Some((spec, expires_at)) => LogLevelStatus {
+                spec: spec.clone(),
+                configured: state.configured.clone(),
+                temporary: true,
+                expires_at: *expires_at,
+            },
+            None => LogLevelStatus {
+                spec: state.configured.clone(),
+                configured: state.configured.clone(),
+                temporary: false,
+                expires_at: None,
+            },
+        }
+    }
+
+    /// 临时修改日志级别, `ttl` 过后恢复为配置中的级别. 设置了 `ttl` 时需要在 tokio 运行时中调用.
+    ///
+    /// # Errors
+    ///
+    /// `spec` 格式错误或者 `ttl` 超过 [MAX_LOG_LEVEL_TTL] 时返回错误.
+    pub fn set(&self, spec: &str, ttl: Option<Duration>) -> Result<()> {
+        let log_spec =
+            LogSpecification::parse(spec).with_context(|| format!("日志级别 `{spec}` 无效"))?;
+        let expires_at = match ttl {
+            Some(ttl) if ttl > MAX_LOG_LEVEL_TTL => {
+                bail!("有效期不能超过 {} 秒", MAX_LOG_LEVEL_TTL.as_secs())
+            }
+            Some(ttl) => TimeDelta::from_std(ttl)
+                .ok()
+                .and_then(|ttl| Utc::now().checked_add_signed(ttl)),
+            None => None,
+        };
+        let generation = {
+            let mut state = self.lock();
+            state.temporary = Some((spec.to_owned(), expires_at));
+            state.generation += 1;
+            self.handle.set_new_spec(log_spec);
+            state.generation
+        };
+        log::info!("日志级别临时修改为 `{spec}`, 有效期: {ttl:?}");
+
+        if let Some(ttl) = ttl {
+            let this = self.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(ttl).await;
+                this.reset_if(generation);
+            });
+        }
+        Ok(())
+    }
+
+    /// 取消临时级别, 恢复为配置中的级别.
+    pub fn reset(&self) {
+        self.revert(&mut self.lock());
+    }
+
+    /// 临时级别到期时调用, 期间没有再修改过 (`generation` 没有变化) 时才恢复.
+    ///
+    /// 检查和恢复在同一次加锁中完成, 以免恢复掉检查之后新设置的临时级别.
+    fn reset_if(&self, generation: u64) {
+        let mut state = self.lock();
+        if state.generation == generation {
+            self.revert(&mut state);
+        }
+    }
+
+    fn revert(&self, state: &mut LevelState) {
+        if state.temporary.take().is_none() {
+            return;
+        }
+        state.generation += 1;
+        self.apply(&state.configured);
+        log::info!("日志级别已恢复为 `{}`", state.configured);
+    }
+
+    /// 配置中的级别变化时调用, 没有临时级别时立即生效.
+    ///
+    /// # Errors
+    ///
+    /// `spec` 格式错误时返回错误.
+    pub fn set_configured(&self, spec: &str) -> Result<()> {
+        let log_spec =
+            LogSpecification::parse(spec).with_context(|| format!("日志级别 `{spec}` 无效"))?;
+        let mut state = self.lock();
+        if state.configured == spec {
+            return Ok(());
+        }
+        log::info!("配置中的日志级别已更新: {} -> {spec}", state.configured);
+        state.configured = spec.to_owned();
+        if state.temporary.is_none() {
+            self.handle.set_new_spec(log_spec);
+        }
+        Ok(())
+    }
+
+    fn apply(&self, spec: &str) {
+        match LogSpecification::parse(spec) {
+            Ok(log_spec) => self.handle.set_new_spec(log_spec),
+            Err(e) => log::error!("日志级别 `{spec}` 无效: {e}"),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LevelState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// 文本格式, 如 `[时间] T[线程] INFO [模块:行号] [trace ID] {"device":"d1"} 消息`.
+///
+/// 不在追踪范围内时没有 `[trace ID]`.
+fn text_format(
+    w: &mut dyn std::io::Write,
+    now: &mut DeferredNow,
+    record: &Record,
+) -> Result<(), std::io::Error> {
+    write!(
+        w,
+        "[{}] T[{}] {} [{}:{}] ",
+        now.format("%Y-%m-%d %H:%M:%S%.6f"),
+        thread::current().name().unwrap_or("<unnamed>"),
+        record.level(),
+        record.module_path().unwrap_or("<unnamed>"),
+        record.line().unwrap_or(0),
+    )?;
+
+    if let Some(trace_id) = trace::current_id() {
+        write!(w, "[{trace_id}] ")?;
+    }
+
+    let fields = key_values(record);
+    if !fields.is_empty() {
+        write!(w, "{} ", Value::Object(fields))?;
+    }
+
+    write!(w, "{}", &record.args())
+}
+
+/// JSON 格式, 每行一个对象.
+///
+/// 包含 `timestamp`, `level`, `thread`, `module`, `line` 和 `message`, 在追踪范围内时有 `trace_id`,
+/// 通过 `log::info!(device = id; "...")` 传入的键值对放在 `fields` 中.
+fn json_format(
+    w: &mut dyn std::io::Write,
+    now: &mut DeferredNow,
+    record: &Record,
+) -> Result<(), std::io::Error> {
+    write!(
+        w,
+        "{{\"timestamp\":{},\"level\":{},\"thread\":{},\"module\":{},\"line\":{},\"message\":{}",
+        Value::from(now.format_rfc3339()),
+        Value::from(record.level().as_str()),
+        Value::from(thread::current().name().unwrap_or("<unnamed>")),
+        Value::from(record.module_path().unwrap_or("<unnamed>")),
+        record.line().unwrap_or(0),
+        Value::from(record.args().to_string()),
+    )?;
+
+    if let Some(trace_id) = trace::current_id() {
+        write!(w, ",\"trace_id\":{}", Value::from(&*trace_id))?;
+    }
+
+    let fields = key_values(record);
+    if !fields.is_empty() {
+        write!(w, ",\"fields\":{}", Value::Object(fields))?;
+    }
+
+    write!(w, "}}")
+}
+
+/// 取出日志中的键值对, 按全局规则脱敏.
+fn key_values(record: &Record) -> Map<String, Value> {
+    struct Collect(Map<String, Value>);
+
+    impl<'kvs> VisitSource<'kvs> for Collect {
+        fn visit_pair(&mut self, key: Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+            let value = serde_json::to_value(&value).unwrap_or_else(|_| value.to_string().into());
+            self.0.insert(key.to_string(), value);
+            Ok(())
+        }
+    }
+
+    if record.key_values().count() == 0 {
+        return Map::new();
+    }
+    let mut collect = Collect(Map::new());
+    let _ = record.key_values().visit(&mut collect);
+    let mut fields = Value::Object(collect.0);
+    Redactor::global().redact_in_place(&mut fields);
+    match fields {
+        Value::Object(map) => map,
+        _ => Map::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 不安装为全局日志的 handle, 只用来检查生效的级别.
+    fn control(configured: &str) -> (LogLevelControl, LoggerHandle) {
+        let (_, handle) = Logger::try_with_str(configured).unwrap().build().unwrap();
+        (LogLevelControl::new(handle.clone(), configured), handle)
+    }
+
+    fn current(handle: &LoggerHandle) -> String {
+        handle.current_log_spec().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn set_and_reset() {
+        let (control, handle) = control("info");
+        control.set("debug", None).unwrap();
+        let status = control.status();
+        assert!(status.temporary);
+        assert_eq!(status.spec, "debug");
+        assert_eq!(status.configured, "info");
+        assert_eq!(status.expires_at, None);
+        assert_eq!(current(&handle), "debug");
+
+        control.reset();
+        assert!(!control.status().temporary);
+        assert_eq!(current(&handle), "info");
+
+        assert!(control.set("debug,x=nope", None).is_err());
+        assert!(control.set("debug", Some(MAX_LOG_LEVEL_TTL * 2)).is_err());
+        assert!(!control.status().temporary);
+    }
+
+    #[tokio::test]
+    async fn set_configured_waits_for_temporary_level() {
+        let (control, handle) = control("info");
+        control.set_configured("warn").unwrap();
+        assert_eq!(current(&handle), "warn");
+
+        control.set("debug", None).unwrap();
+        control.set_configured("error").unwrap();
+        assert_eq!(current(&handle), "debug");
+        assert_eq!(control.status().configured, "error");
+        control.reset();
+        assert_eq!(current(&handle), "error");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn temporary_level_expires() {
+        let (control, handle) = control("info");
+        control.set("debug", Some(Duration::from_secs(60))).unwrap();
+        assert!(control.status().expires_at.is_some());
+
+        tokio::time::sleep(Duration::from_secs(59)).await;
+        assert_eq!(current(&handle), "debug");
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(current(&handle), "info");
+        assert!(!control.status().temporary);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn newer_level_is_not_reverted_by_old_ttl() {
+        let (control, handle) = control("info");
+        control.set("debug", Some(Duration::from_secs(10))).unwrap();
+        control.set("trace", Some(Duration::from_secs(60))).unwrap();
+        tokio::time::sleep(Duration::from_secs(11)).await;
+        assert_eq!(current(&handle), "trace");
+
+        // 旧的到期任务在新的修改之后执行, 也不会恢复
+        let generation = control.lock().generation;
+        control.set("debug", None).unwrap();
+        control.reset_if(generation);
+        assert_eq!(current(&handle), "debug");
+    }
+
+    /// 按 `format` 输出一条带键值对的日志.
+    fn render(format: FormatFunction) -> String {
+        let fields: &[(&str, &str)] = &[("device", "d1"), ("password", "p")];
+        let record = Record::builder()
+            .args(format_args!("设备上线"))
+            .level(log::Level::Warn)
+            .module_path(Some("internal_core::device"))
+            .line(Some(42))
+            .key_values(&fields)
+            .build();
+        let mut out = Vec::new();
+        format(&mut out, &mut DeferredNow::new(), &record).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[tokio::test]
+    async fn json_format_renders_required_keys_and_redacts_fields() {
+        let line = trace::TraceContext::with_id("trace-1")
+            .scope(async { render(json_format) })
+            .await;
+        let value: Value = serde_json::from_str(&line).unwrap();
+        let object = value.as_object().unwrap();
+        for key in ["timestamp", "level", "thread", "module", "line", "message"] {
+            assert!(object.contains_key(key), "{key}: {line}");
+        }
+        assert!(DateTime::parse_from_rfc3339(value["timestamp"].as_str().unwrap()).is_ok());
+        assert_eq!(value["level"], "WARN");
+        assert_eq!(value["module"], "internal_core::device");
+        assert_eq!(value["line"], 42);
+        assert_eq!(value["message"], "设备上线");
+        assert_eq!(value["trace_id"], "trace-1");
+        assert_eq!(
+            value["fields"],
+            serde_json::json!({"device": "d1", "password": "******"})
+        );
+
+        // 不在追踪范围内时没有 trace_id
+        let value: Value = serde_json::from_str(&render(json_format)).unwrap();
+        assert!(value.get("trace_id").is_none());
+    }
+
+    #[test]
+    fn text_format_puts_fields_before_message() {
+        let line = render(text_format);
+        assert!(
+            line.ends_with(
+                r#" WARN [internal_core::device:42] {"device":"d1","password":"******"} 设备上线"#
+            ),
+            "{line}"
+        );
+    }
+
+    fn rotation(
+        max_size_mb: Option<u64>,
+        age: Option<RotateAge>,
+        keep_files: Option<usize>,
+        keep_days: Option<usize>,
+        compress: bool,
+    ) -> LogRotation {
+        LogRotation {
+            max_size_mb,
+            age,
+            keep_files,
+            keep_days,
+            compress,
+        }
+    }
+
+    #[test]
+    fn rotation_criterion() {
+        for (max_size_mb, age, expected) in [
+            (
+                Some(10),
+                Some(RotateAge::Day),
+                "Some(AgeOrSize(Day, 10000000))",
+            ),
+            (None, Some(RotateAge::Hour), "Some(Age(Hour))"),
+            (Some(1), None, "Some(Size(1000000))"),
+            (None, None, "None"),
+        ] {
+            let criterion = rotation(max_size_mb, age, None, None, false).criterion();
+            assert_eq!(format!("{criterion:?}"), expected);
+        }
+    }
+
+    #[test]
+    fn rotation_cleanup() {
+        for (keep_files, keep_days, compress, expected) in [
+            (Some(5), None, false, "KeepLogFiles(5)"),
+            (Some(5), None, true, "KeepCompressedFiles(5)"),
+            (None, Some(7), false, "KeepForDays(7)"),
+            (None, None, false, "Never"),
+        ] {
+            let cleanup = rotation(None, None, keep_files, keep_days, compress).cleanup();
+            assert_eq!(format!("{cleanup:?}"), expected);
+        }
+    }
+
+    fn options() -> LogOptions {
+        serde_json::from_value(serde_json::json!({"name": "app", "level": "info"})).unwrap()
+    }
+
+    fn errors(opt: &LogOptions) -> Vec<String> {
+        let mut errors = ConfigErrors::default();
+        opt.validate("logging", &mut errors);
+        errors.messages().to_vec()
+    }
+
+    #[test]
+    fn validate_accepts_defaults() {
+        assert_eq!(errors(&options()), Vec::<String>::new());
+    }
+
+    type Modify = fn(&mut LogOptions);
+
+    #[test]
+    fn validate_rejects_invalid_options() {
+        let cases: [(Modify, &str); 9] = [
+            (|o| o.name = " ".to_owned(), "name 不能为空"),
+            (
+                |o| o.level = "info,a=nope".to_owned(),
+                "level `info,a=nope` 无效",
+            ),
+            (|o| o.directory = Some(String::new()), "directory 不能为空"),
+            (|o| o.flush_interval_ms = 0, "flush_interval_ms 必须大于 0"),
+            (
+                |o| o.rotation.max_size_mb = Some(0),
+                "rotation.max_size_mb 必须大于 0",
+            ),
+            (
+                |o| o.rotation.keep_files = Some(0),
+                "rotation.keep_files 和 rotation.keep_days 必须大于 0",
+            ),
+            (
+                |o| o.rotation.keep_days = Some(0),
+                "rotation.keep_files 和 rotation.keep_days 必须大于 0",
+            ),
+            (
+                |o| o.rotation.keep_days = Some(7),
+                "rotation.keep_files 和 rotation.keep_days 只能设置一个",
+            ),
+            (
+                |o| {
+                    o.rotation.keep_files = None;
+                    o.rotation.compress = true;
+                },
+                "rotation.compress 需要设置 rotation.keep_files",
+            ),
+        ];
+        for (modify, expected) in cases {
+            let mut opt = options();
+            modify(&mut opt);
+            let errors = errors(&opt);
+            assert!(
+                errors
+                    .iter()
+                    .any(|e| e.starts_with(&format!("logging: {expected}"))),
+                "{expected}: {errors:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,268 @@
+//! 远程日志的发送队列
+//!
+//! 写日志时只把格式化好的行放入有界队列, 由单独的线程发送, 队列满时直接丢弃,
+//! 接收方变慢或不可用时不会阻塞业务线程. 丢弃的条数会在下一次发送时以一条警告日志补发.
+
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// 关闭时等待队列发送完的最长时间.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 队列的默认容量 (条).
+pub(super) const fn default_queue_size() -> usize {
+    10_000
+}
+
+/// 日志的接收方.
+pub(super) trait Sink: Send + 'static {
+    /// 发送一批日志.
+    fn send(&mut self, lines: &[String]) -> io::Result<()>;
+
+    /// 生成一条 "丢弃了多少条日志" 的警告日志.
+    fn dropped_notice(&self, count: u64) -> String;
+
+    /// 接收方的描述, 用于错误信息.
+    fn describe(&self) -> String;
+}
+
+/// 发送方式.
+pub(super) struct Batching {
+    /// 每批最多条数.
+    pub(super) max_lines: usize,
+    /// 收到第一条后最多等待多久再发送, 为 0 时只取队列中已有的日志.
+    pub(super) linger: Duration,
+}
+
+enum Message {
+    Line(String),
+    /// 立即发送已收到的日志, 发送后通过 `Some` 中的通道通知.
+    Flush(Option<SyncSender<()>>),
+}
+
+/// 有界发送队列.
+pub(super) struct Queue {
+    tx: SyncSender<Message>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Queue {
+    /// 创建队列, 并启动名为 `name` 的发送线程.
+    pub(super) fn start(
+        name: &str,
+        capacity: usize,
+        batching: Batching,
+        sink: impl FnOnce() -> io::Result<Box<dyn Sink>> + Send + 'static,
+    ) -> io::Result<Self> {
+        let (tx, rx) = mpsc::sync_channel(capacity);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let worker_dropped = dropped.clone();
+        thread::Builder::new()
+            .name(name.to_owned())
+            .spawn(move || match sink() {
+                Ok(sink) => run(&rx, &worker_dropped, sink, &batching),
+                Err(e) => eprintln!("创建日志发送线程失败: {e}"),
+            })?;
+        Ok(Self { tx, dropped })
+    }
+
+    /// 放入一行日志, 队列已满时丢弃.
+    pub(super) fn push(&self, line: String) {
+        if self.tx.try_send(Message::Line(line)).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// 通知发送线程立即发送, 不等待结果.
+    pub(super) fn flush(&self) {
+        let _ = self.tx.try_send(Message::Flush(None));
+    }
+
+    /// 等待队列中的日志发送完, 最多等待 [SHUTDOWN_TIMEOUT].
+    pub(super) fn drain(&self) {
+        let (ack_tx, ack_rx) = mpsc::sync_channel(1);
+        let message = Message::Flush(Some(ack_tx));
+        match self.tx.try_send(message) {
+            Ok(()) => {}
+            // 队列已满时等待空位, 同样计入超时
+            Err(TrySendError::Full(message)) => {
+                let tx = self.tx.clone();
+                thread::spawn(move || tx.send(message));
+            }
+            Err(TrySendError::Disconnected(_)) => return,
+        }
+        let _ = ack_rx.recv_timeout(SHUTDOWN_TIMEOUT);
+    }
+}
+
+/// 发送线程: 按批取出日志并发送, 发送失败的日志计入丢弃条数.
+fn run(rx: &Receiver<Message>, dropped: &AtomicU64, mut sink: Box<dyn Sink>, batching: &Batching) {
+    let mut lines = Vec::with_capacity(batching.max_lines);
+    let mut acks = Vec::new();
+    let mut failing = false;
+    while let Ok(first) = rx.recv() {
+        let deadline = Instant::now() + batching.linger;
+        let mut message = Some(first);
+        while let Some(m) = message.take() {
+            match m {
+                Message::Line(line) => lines.push(line),
+                Message::Flush(ack) => {
+                    acks.extend(ack);
+                    break;
+                }
+            }
+            if lines.len() >= batching.max_lines {
+                break;
+            }
+            message = match deadline.checked_duration_since(Instant::now()) {
+                Some(timeout) if !timeout.is_zero() => rx.recv_timeout(timeout).ok(),
+                _ => rx.try_recv().ok(),
+            };
+        }
+
+        let count = dropped.swap(0, Ordering::Relaxed);
+        if count > 0 {
+            lines.push(sink.dropped_notice(count));
+        }
+        if !lines.is_empty() {
+            match sink.send(&lines) {
+                Ok(()) if failing => {
+                    failing = false;
+                    eprintln!("已恢复发送日志到 {}", sink.describe());
+                }
+                Ok(()) => {}
+                Err(e) => {
+                    // 日志发不出去时只能写到标准错误, 连续失败只提示一次
+                    if !failing {
+                        failing = true;
+                        eprintln!("发送日志到 {} 失败: {e}", sink.describe());
+                    }
+                    let lost = lines.len() as u64 - u64::from(count > 0);
+                    dropped.fetch_add(lost + count, Ordering::Relaxed);
+                }
+            }
+            lines.clear();
+        }
+        for ack in acks.drain(..) {
+            let _ = ack.send(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// 记录收到的日志, 每次发送前等待 `gate` 放行.
+    struct TestSink {
+        sent: Arc<Mutex<Vec<String>>>,
+        entered: SyncSender<()>,
+        gate: Receiver<()>,
+    }
+
+    impl Sink for TestSink {
+        fn send(&mut self, lines: &[String]) -> io::Result<()> {
+            let _ = self.entered.try_send(());
+            let _ = self.gate.recv();
+            self.sent.lock().unwrap().extend_from_slice(lines);
+            Ok(())
+        }
+
+        fn dropped_notice(&self, count: u64) -> String {
+            format!("dropped {count}")
+        }
+
+        fn describe(&self) -> String {
+            "test".to_owned()
+        }
+    }
+
+    struct Harness {
+        queue: Queue,
+        sent: Arc<Mutex<Vec<String>>>,
+        entered: Receiver<()>,
+        gate: SyncSender<()>,
+    }
+
+    fn start(capacity: usize) -> Harness {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let (entered_tx, entered) = mpsc::sync_channel(16);
+        let (gate, gate_rx) = mpsc::sync_channel(16);
+        let sink_sent = sent.clone();
+        let queue = Queue::start(
+            "test-log-writer",
+            capacity,
+            Batching {
+                max_lines: 100,
+                linger: Duration::ZERO,
+            },
+            move || {
+                Ok(Box::new(TestSink {
+                    sent: sink_sent,
+                    entered: entered_tx,
+                    gate: gate_rx,
+                }) as Box<dyn Sink>)
+            },
+        )
+        .unwrap();
+        Harness {
+            queue,
+            sent,
+            entered,
+            gate,
+        }
+    }
+
+    #[test]
+    fn drops_lines_when_full_and_reports_count() {
+        let h = start(2);
+        h.queue.push("1".to_owned());
+        // 发送线程取走第一条后阻塞在发送中, 队列只剩两个空位
+        h.entered.recv_timeout(Duration::from_secs(5)).unwrap();
+        for line in ["2", "3", "4", "5"] {
+            h.queue.push(line.to_owned());
+        }
+        assert_eq!(h.queue.dropped.load(Ordering::Relaxed), 2);
+
+        for _ in 0..2 {
+            h.gate.send(()).unwrap();
+        }
+        h.queue.drain();
+        assert_eq!(*h.sent.lock().unwrap(), ["1", "2", "3", "dropped 2"]);
+    }
+
+    #[test]
+    fn drain_delivers_pending_lines() {
+        let h = start(100);
+        // 每次发送都放行, 最多分 10 批
+        for _ in 0..10 {
+            h.gate.send(()).unwrap();
+        }
+        for i in 0..10 {
+            h.queue.push(i.to_string());
+        }
+        h.queue.drain();
+        assert_eq!(h.sent.lock().unwrap().len(), 10);
+    }
+
+    #[test]
+    fn drain_gives_up_after_timeout() {
+        let h = start(10);
+        h.queue.push("stuck".to_owned());
+        h.entered.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        let started = Instant::now();
+        h.queue.drain();
+        let elapsed = started.elapsed();
+        assert!(elapsed >= SHUTDOWN_TIMEOUT, "{elapsed:?}");
+        assert!(
+            elapsed < SHUTDOWN_TIMEOUT + Duration::from_secs(2),
+            "{elapsed:?}"
+        );
+    }
+}
@@ -0,0 +1,501 @@
+//! 以 RFC 5424 格式发送日志到 syslog.
+//!
+//! 每条日志为 `<PRI>1 时间 主机名 应用名 进程ID - - 消息`, 消息是按日志格式 (`text` 或 `json`)
+//! 格式化后的内容. 支持以下传输方式:
+//! - `udp`: 每条日志一个数据报 (RFC 5426), 超过 [UDP_MAX_LEN] 字节的部分会被截断.
+//! - `tcp`: 按 RFC 6587 的 octet-counting 分帧 (`长度 空格 日志`), 断开后自动重连.
+//! - `unix`: Unix 数据报套接字, 如 `/dev/log`.
+
+use super::queue::{Batching, Queue, Sink, default_queue_size};
+use super::text_format;
+use crate::config::{ConfigErrors, Validate};
+use flexi_logger::writers::LogWriter;
+use flexi_logger::{DeferredNow, FormatFunction};
+use log::{Level, Record};
+use serde::Deserialize;
+use std::io::{self, Write};
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+use std::{env, fs, process};
+
+/// UDP 数据报的长度上限, 字节.
+const UDP_MAX_LEN: usize = 8192;
+/// TCP 连接和写入的超时.
+const TCP_TIMEOUT: Duration = Duration::from_secs(5);
+/// 每次最多发送的条数.
+const MAX_LINES: usize = 100;
+
+/// syslog 的传输方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyslogTransport {
+    /// UDP
+    #[default]
+    Udp,
+    /// TCP
+    Tcp,
+    /// Unix 数据报套接字
+    Unix,
+}
+
+/// syslog 的 facility
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyslogFacility {
+    /// `kern`, 内核
+    Kern,
+    /// `user`, 用户程序
+    #[default]
+    User,
+    /// `mail`, 邮件
+    Mail,
+    /// `daemon`, 系统守护进程
+    Daemon,
+    /// `auth`, 安全和认证
+    Auth,
+    /// `syslog`, syslog 自身
+    Syslog,
+    /// `lpr`, 打印
+    Lpr,
+    /// `news`, 新闻组
+    News,
+    /// `uucp`, UUCP
+    Uucp,
+    /// `cron`, 定时任务
+    Cron,
+    /// `authpriv`, 私有的安全和认证
+    Authpriv,
+    /// `ftp`, FTP
+    Ftp,
+    /// `local0`, 本地使用 0
+    Local0,
+    /// `local1`, 本地使用 1
+    Local1,
+    /// `local2`, 本地使用 2
+    Local2,
+    /// `local3`, 本地使用 3
+    Local3,
+    /// `local4`, 本地使用 4
+    Local4,
+    /// `local5`, 本地使用 5
+    Local5,
+    /// `local6`, 本地使用 6
+    Local6,
+    /// `local7`, 本地使用 7
+    Local7,
+}
+
+impl SyslogFacility {
+    const fn code(self) -> u8 {
+        match self {
+            Self::Kern => 0,
+            Self::User => 1,
+            Self::Mail => 2,
+            Self::Daemon => 3,
+            Self::Auth => 4,
+            Self::Syslog => 5,
+            Self::Lpr => 6,
+            Self::News => 7,
+            Self::Uucp => 8,
+            Self::Cron => 9,
+            Self::Authpriv => 10,
+            Self::Ftp => 11,
+            Self::Local0 => 16,
+            Self::Local1 => 17,
+            Self::Local2 => 18,
+            Self::Local3 => 19,
+            Self::Local4 => 20,
+            Self::Local5 => 21,
+            Self::Local6 => 22,
+            Self::Local7 => 23,
+        }
+    }
+}
+
+/// syslog 配置
+#[derive(Debug, Clone, Deserialize)]
+pub struct SyslogOptions {
+    /// 传输方式, 默认 `udp`
+    #[serde(default)]
+    pub transport: SyslogTransport,
+    /// 地址, `udp` 和 `tcp` 为 `host:port`, `unix` 为套接字路径
+    pub address: String,
+    /// facility, 默认 `user`
+    #[serde(default)]
+    pub facility: SyslogFacility,
+    /// 应用名, 默认为日志名称
+    #[serde(default)]
+    pub app_name: Option<String>,
+    /// 主机名, 默认读取系统主机名
+    #[serde(default)]
+    pub hostname: Option<String>,
+    /// 队列容量 (条), 队列满时丢弃新的日志
+    #[serde(default = "default_queue_size")]
+    pub queue_size: usize,
+}
+
+impl Validate for SyslogOptions {
+    fn validate(&self, section: &str, errors: &mut ConfigErrors) {
+        errors.check(!self.address.trim().is_empty(), section, "address 不能为空");
+        errors.check(
+            cfg!(unix) || self.transport != SyslogTransport::Unix,
+            section,
+            "当前系统不支持 unix 传输方式",
+        );
+        errors.check(self.queue_size > 0, section, "queue_size 必须大于 0");
+    }
+}
+
+/// 发送日志到 syslog 的 [LogWriter].
+///
+/// 日志先放入队列, 由单独的线程发送, 不会阻塞写日志的线程.
+pub struct SyslogWriter {
+    queue: Queue,
+    header: Header,
+    format: FormatFunction,
+}
+
+/// 每条日志相同的头部字段.
+#[derive(Clone)]
+struct Header {
+    facility: u8,
+    hostname: String,
+    app_name: String,
+    proc_id: u32,
+}
+
+impl Header {
+    /// 生成一条完整的 syslog 消息.
+    fn message(&self, level: Level, timestamp: &str, msg: &str) -> String {
+        let severity = match level {
+            Level::Error => 3,
+            Level::Warn => 4,
+            Level::Info => 6,
+            Level::Debug | Level::Trace => 7,
+        };
+        // 消息是 UTF-8, 按 RFC 5424 以 BOM 开头
+        format!(
+            "<{}>1 {timestamp} {} {} {} - - \u{feff}{msg}",
+            self.facility * 8 + severity,
+            self.hostname,
+            self.app_name,
+            self.proc_id,
+        )
+    }
+}
+
+impl SyslogWriter {
+    /// 创建并启动发送线程.
+    ///
+    /// # Arguments
+    ///
+    /// * `opt` - syslog 配置.
+    /// * `name` - 日志名称, 没有配置 `app_name` 时作为应用名.
+    ///
+    /// # Errors
+    ///
+    /// 启动发送线程失败时返回错误. 连接失败不会返回错误, 发送时会重试.
+    pub fn new(opt: &SyslogOptions, name: &str) -> io::Result<Self> {
+        let header = Header {
+            facility: opt.facility.code(),
+            hostname: header_field(&opt.hostname.clone().unwrap_or_else(hostname), 255),
+            app_name: header_field(opt.app_name.as_deref().unwrap_or(name), 48),
+            proc_id: process::id(),
+        };
+        let notice = header.clone();
+        let transport = opt.transport;
+        let address = opt.address.clone();
+        let queue = Queue::start(
+            "syslog-writer",
+            opt.queue_size,
+            Batching {
+                max_lines: MAX_LINES,
+                linger: Duration::ZERO,
+            },
+            move || {
+                Ok(Box::new(SyslogSink {
+                    transport,
+                    address,
+                    connection: None,
+                    header: notice,
+                }) as Box<dyn Sink>)
+            },
+        )?;
+        Ok(Self {
+            queue,
+            header,
+            format: text_format,
+        })
+    }
+}
+
+impl LogWriter for SyslogWriter {
+    fn write(&self, now: &mut DeferredNow, record: &Record) -> io::Result<()> {
+        let mut msg = Vec::new();
+        (self.format)(&mut msg, now, record)?;
+        let message = self.header.message(
+            record.level(),
+            &now.format_rfc3339(),
+            String::from_utf8_lossy(&msg).trim_end(),
+        );
+        self.queue.push(message);
+        Ok(())
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.queue.flush();
+        Ok(())
+    }
+
+    fn format(&mut self, format: FormatFunction) {
+        self.format = format;
+    }
+
+    fn shutdown(&self) {
+        self.queue.drain();
+    }
+}
+
+/// 发送线程中的连接.
+enum Connection {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixDatagram),
+}
+
+struct SyslogSink {
+    transport: SyslogTransport,
+    address: String,
+    connection: Option<Connection>,
+    header: Header,
+}
+
+impl SyslogSink {
+    fn connect(&self) -> io::Result<Connection> {
+        match self.transport {
+            SyslogTransport::Udp => {
+                let addr = self.resolve()?;
+                let local = if addr.is_ipv4() {
+                    "0.0.0.0:0"
+                } else {
+                    "[::]:0"
+                };
+                let socket = UdpSocket::bind(local)?;
+                socket.connect(addr)?;
+                Ok(Connection::Udp(socket))
+            }
+            SyslogTransport::Tcp => {
+                let stream = TcpStream::connect_timeout(&self.resolve()?, TCP_TIMEOUT)?;
+                stream.set_write_timeout(Some(TCP_TIMEOUT))?;
+                Ok(Connection::Tcp(stream))
+            }
+            #[cfg(unix)]
+            SyslogTransport::Unix => {
+                let socket = std::os::unix::net::UnixDatagram::unbound()?;
+                socket.connect(&self.address)?;
+                Ok(Connection::Unix(socket))
+            }
+            #[cfg(not(unix))]
+            SyslogTransport::Unix => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "当前系统不支持 unix 传输方式",
+            )),
+        }
+    }
+
+    fn resolve(&self) -> io::Result<std::net::SocketAddr> {
+        self.address.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("无法解析地址 {}", self.address),
+            )
+        })
+    }
+}
+
+impl Sink for SyslogSink {
+    fn send(&mut self, lines: &[String]) -> io::Result<()> {
+        let connection = match &mut self.connection {
+            Some(c) => c,
+            None => self.connection.insert(self.connect()?),
+        };
+        let result = lines.iter().try_for_each(|line| match connection {
+            Connection::Udp(socket) => socket.send(truncate(line).as_bytes()).map(drop),
+            Connection::Tcp(stream) => write!(stream, "{} {line}", line.len()),
+            #[cfg(unix)]
+            Connection::Unix(socket) => socket.send(truncate(line).as_bytes()).map(drop),
+        });
+        if result.is_err() {
+            // 下次发送时重新连接
+            self.connection = None;
+        }
+        result
+    }
+
+    fn dropped_notice(&self, count: u64) -> String {
+        let timestamp = DeferredNow::new().format_rfc3339();
+        let msg = format!("日志队列已满或发送失败, 丢弃了 {count} 条日志");
+        self.header.message(Level::Warn, &timestamp, &msg)
+    }
+
+    fn describe(&self) -> String {
+        format!("syslog ({:?} {})", self.transport, self.address)
+    }
+}
+
+/// 按 UTF-8 字符边界截断到 [UDP_MAX_LEN] 字节.
+fn truncate(line: &str) -> &str {
+    &line[..line.floor_char_boundary(UDP_MAX_LEN)]
+}
+
+/// 系统主机名.
+fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .map(|h| h.trim().to_owned())
+        .filter(|h| !h.is_empty())
+        .or_else(|| env::var("HOSTNAME").ok())
+        .unwrap_or_default()
+}
+
+/// 头部字段只能是可打印的 ASCII 字符, 替换其他字符并截断, 为空时用 `-`.
+fn header_field(value: &str, max_len: usize) -> String {
+    let value: String = value
+        .chars()
+        .map(|c| if c.is_ascii_graphic() { c } else { '_' })
+        .take(max_len)
+        .collect();
+    if value.is_empty() {
+        "-".to_owned()
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    fn options(transport: SyslogTransport, address: String) -> SyslogOptions {
+        SyslogOptions {
+            transport,
+            address,
+            facility: SyslogFacility::Local0,
+            app_name: Some("test app".to_owned()),
+            hostname: Some("host-1".to_owned()),
+            queue_size: 10,
+        }
+    }
+
+    fn write_warn(writer: &SyslogWriter, msg: &str) {
+        writer
+            .write(
+                &mut DeferredNow::new(),
+                &Record::builder()
+                    .args(format_args!("{msg}"))
+                    .level(Level::Warn)
+                    .module_path(Some("app::device"))
+                    .build(),
+            )
+            .unwrap();
+        writer.shutdown();
+    }
+
+    fn assert_frame(frame: &str, msg: &str) {
+        // local0 (16) * 8 + warning (4)
+        assert!(frame.starts_with("<132>1 "), "{frame}");
+        let fields: Vec<&str> = frame.splitn(8, ' ').collect();
+        assert_eq!(fields[2], "host-1");
+        assert_eq!(fields[3], "test_app");
+        assert_eq!(fields[4], process::id().to_string());
+        assert_eq!(&fields[5..7], ["-", "-"]);
+        assert!(fields[7].starts_with('\u{feff}'), "{frame}");
+        assert!(fields[7].contains("WARN [app::device"), "{frame}");
+        assert!(fields[7].ends_with(msg), "{frame}");
+    }
+
+    #[test]
+    fn sends_udp_datagrams() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let opt = options(
+            SyslogTransport::Udp,
+            socket.local_addr().unwrap().to_string(),
+        );
+        let writer = SyslogWriter::new(&opt, "app").unwrap();
+        write_warn(&writer, "设备离线");
+
+        let mut buf = [0; UDP_MAX_LEN];
+        let n = socket.recv(&mut buf).unwrap();
+        assert_frame(std::str::from_utf8(&buf[..n]).unwrap(), "设备离线");
+    }
+
+    #[test]
+    fn sends_octet_counted_tcp_frames() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let opt = options(
+            SyslogTransport::Tcp,
+            listener.local_addr().unwrap().to_string(),
+        );
+        let writer = SyslogWriter::new(&opt, "app").unwrap();
+        write_warn(&writer, "first");
+        write_warn(&writer, "second");
+        drop(writer);
+
+        let (mut stream, _) = listener.accept().unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut data = Vec::new();
+        stream.read_to_end(&mut data).unwrap();
+        let mut rest = std::str::from_utf8(&data).unwrap();
+        for msg in ["first", "second"] {
+            let (len, tail) = rest.split_once(' ').unwrap();
+            let len: usize = len.parse().unwrap();
+            assert_frame(&tail[..len], msg);
+            rest = &tail[len..];
+        }
+        assert!(rest.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sends_unix_datagrams() {
+        use std::os::unix::net::UnixDatagram;
+
+        let path = env::temp_dir().join(format!("syslog-test-{}.sock", process::id()));
+        let _ = fs::remove_file(&path);
+        let socket = UnixDatagram::bind(&path).unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let opt = options(SyslogTransport::Unix, path.to_string_lossy().into_owned());
+        let writer = SyslogWriter::new(&opt, "app").unwrap();
+        write_warn(&writer, "unix");
+
+        let mut buf = [0; UDP_MAX_LEN];
+        let n = socket.recv(&mut buf).unwrap();
+        assert_frame(std::str::from_utf8(&buf[..n]).unwrap(), "unix");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn truncates_on_char_boundary() {
+        let line = "日".repeat(UDP_MAX_LEN);
+        let truncated = truncate(&line);
+        assert!(truncated.len() <= UDP_MAX_LEN);
+        assert!(truncated.len() > UDP_MAX_LEN - 3);
+    }
+
+    #[test]
+    fn header_field_is_printable_ascii() {
+        assert_eq!(header_field("my app\n", 48), "my_app_");
+        assert_eq!(header_field("", 48), "-");
+        assert_eq!(header_field("abcdef", 3), "abc");
+    }
+}
@@ -0,0 +1,200 @@
+//! JSON 工具库的错误类型.
+
+use super::path::type_name;
+use serde::Serialize;
+use serde_json::Value;
+use thiserror::Error;
+
+/// JSON 工具库的返回值类型.
+pub type JsonResult<T> = Result<T, JsonError>;
+
+/// 转换或提取 JSON 字段时产生的错误.
+///
+/// 实现了 [Serialize], 序列化后带有 `kind` 字段, 可以直接作为接口错误详情返回.
+/// `path` 为出错位置, 写法与调用时传入的路径一致; 对值本身转换出错时为空字符串.
+#[derive(Debug, Clone, PartialEq, Eq, Error, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JsonError {
+    /// 缺失必要字段.
+    #[error("缺失必要字段: {}.", display_path(path))]
+    MissingField {
+        /// 缺失的字段路径.
+        path: String,
+    },
+
+    /// 字段类型错误.
+    #[error("字段类型错误, {} 应为{expected}, 实际为{found}.", display_path(path))]
+    TypeMismatch {
+        /// 字段路径.
+        path: String,
+        /// 期望的类型.
+        expected: &'static str,
+        /// 实际的类型.
+        found: &'static str,
+    },
+
+    /// 数值超出目标类型的范围.
+    #[error(
+        "数值超出范围, {} 的值 {value} 无法转换为 {target}.",
+        display_path(path)
+    )]
+    OutOfRange {
+        /// 字段路径.
+        path: String,
+        /// 目标类型.
+        target: &'static str,
+        /// 原始值.
+        value: String,
+    },
+
+    /// 字符串格式错误, 如无法解析的时间或十进制数.
+    #[error("格式错误, {} 应为{expected}, 实际值为 {value:?}.", display_path(path))]
+    InvalidFormat {
+        /// 字段路径.
+        path: String,
+        /// 期望的格式.
+        expected: &'static str,
+        /// 原始值.
+        value: String,
+    },
+
+    /// JSON Schema 不合法.
+    #[error("无效的 JSON Schema, {}: {reason}.", display_path(path))]
+    InvalidSchema {
+        /// 出错关键字在 Schema 中的 JSON Pointer.
+        path: String,
+        /// 错误原因.
+        reason: String,
+    },
+
+    /// JSON Patch 中的 `test` 操作不通过.
+    #[error("补丁测试不通过: {} 的值与期望不一致.", display_path(path))]
+    TestFailed {
+        /// `test` 操作的路径.
+        path: String,
+    },
+
+    /// JSON Patch 格式错误.
+    #[error("无效的 JSON Patch: {reason}.")]
+    InvalidPatch {
+        /// 错误原因.
+        reason: String,
+    },
+
+    /// 路径格式错误.
+    #[error("无效的路径: {path}, {reason}.")]
+    InvalidPath {
+        /// 传入的路径.
+        path: String,
+        /// 错误原因.
+        reason: &'static str,
+    },
+}
+
+impl JsonError {
+    /// 创建 [`JsonError::MissingField`].
+    pub fn missing(path: impl Into<String>) -> Self {
+        Self::MissingField { path: path.into() }
+    }
+
+    /// 根据实际的值创建 [`JsonError::TypeMismatch`].
+    pub fn mismatch(path: impl Into<String>, expected: &'static str, found: &Value) -> Self {
+        Self::TypeMismatch {
+            path: path.into(),
+            expected,
+            found: type_name(found),
+        }
+    }
+
+    /// 出错位置的路径, 补丁格式错误时为空字符串.
+    pub fn path(&self) -> &str {
+        match self {
+            Self::MissingField { path }
+            | Self::TypeMismatch { path, .. }
+            | Self::OutOfRange { path, .. }
+            | Self::InvalidFormat { path, .. }
+            | Self::InvalidSchema { path, .. }
+            | Self::TestFailed { path }
+            | Self::InvalidPath { path, .. } => path,
+            Self::InvalidPatch { .. } => "",
+        }
+    }
+}
+
+/// 根节点用 `$` 表示.
+fn display_path(path: &str) -> &str {
+    if path.is_empty() { "$" } else { path }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// 检查错误信息和序列化结果.
+    fn check(err: &JsonError, display: &str, serialized: &Value) {
+        assert_eq!(err.to_string(), display);
+        assert_eq!(&serde_json::to_value(err).unwrap(), serialized);
+    }
+
+    #[test]
+    fn missing_field() {
+        check(
+            &JsonError::missing("device.id"),
+            "缺失必要字段: device.id.",
+            &json!({"kind": "missing_field", "path": "device.id"}),
+        );
+    }
+
+    #[test]
+    fn type_mismatch() {
+        check(
+            &JsonError::mismatch("/sensors/0", "数字", &json!("1")),
+            "字段类型错误, /sensors/0 应为数字, 实际为字符串.",
+            &json!({
+                "kind": "type_mismatch",
+                "path": "/sensors/0",
+                "expected": "数字",
+                "found": "字符串",
+            }),
+        );
+    }
+
+    #[test]
+    fn out_of_range_at_root() {
+        check(
+            &JsonError::OutOfRange {
+                path: String::new(),
+                target: "u8",
+                value: "256".to_owned(),
+            },
+            "数值超出范围, $ 的值 256 无法转换为 u8.",
+            &json!({"kind": "out_of_range", "path": "", "target": "u8", "value": "256"}),
+        );
+    }
+
+    #[test]
+    fn invalid_format_and_patch() {
+        check(
+            &JsonError::InvalidFormat {
+                path: "at".to_owned(),
+                expected: "RFC3339 时间",
+                value: "yesterday".to_owned(),
+            },
+            "格式错误, at 应为RFC3339 时间, 实际值为 \"yesterday\".",
+            &json!({
+                "kind": "invalid_format",
+                "path": "at",
+                "expected": "RFC3339 时间",
+                "value": "yesterday",
+            }),
+        );
+        check(
+            &JsonError::InvalidPatch {
+                reason: "缺少 op".to_owned(),
+            },
+            "无效的 JSON Patch: 缺少 op.",
+            &json!({"kind": "invalid_patch", "reason": "缺少 op"}),
+        );
+    }
+}
@@ -0,0 +1,941 @@
+//! JSON 工具库
+//!
+//! 提供用于处理 JSON 数据的转换和提取功能,
+//! 帮助开发者更方便地操作 [Value] 类型.
+//!
+//! [Extract] 中以 `_at` 结尾的方法按路径提取字段, 路径写法见 [JsonPath].
+//!
+//! 以 `opt_` 开头的方法用于可选字段, 字段不存在或为 `null` 时返回 `Ok(None)`;
+//! 以 `_or` 结尾的方法在字段不存在或为 `null` 时返回默认值. 字段类型错误时都会返回错误.
+//!
+//! 所有方法出错时返回 [JsonError], 可以区分缺失字段和类型错误, 并带有出错位置.
+//!
+//! 校验整个 JSON 文档时使用 [JsonSchema], 局部更新和比较时使用 [Patch],
+//! 记录日志前使用 [Redactor] 隐藏敏感字段.
+
+mod error;
+mod patch;
+mod path;
+mod redact;
+mod schema;
+mod typed;
+
+pub use error::{JsonError, JsonResult};
+pub use patch::{Change, ChangeKind, Patch, PatchOp};
+pub use path::{JsonPath, Segment};
+pub use redact::{Redacted, Redactor};
+pub use schema::{JsonSchema, ValidationError, Violation};
+
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde_json::{Map, Value};
+use typed::FromJson;
+
+/// 提供将 [Value] 类型转换为目标类型的方法.
+pub struct Convert;
+
+/// 提供从 [Value] 类型中提取特定字段并转换为目标类型的方法.
+pub struct Extract;
+
+impl Convert {
+    /// 尝试将 JSON 值转换为数组 (`Vec<Value>`).
+    ///
+    /// # 参数
+    /// - `value`: 要转换的 JSON 值.
+    ///
+    /// # 返回
+    /// 如果 `value` 是数组类型, 返回该数组的引用; 否则返回错误.
+    pub fn try_into_array(value: &Value) -> JsonResult<&Vec<Value>> {
+        FromJson::from_json(value, "")
+    }
+
+    /// 尝试将 JSON 值转换为对象 (`Map<String, Value>`).
+    ///
+    /// # 参数
+    /// - `value`: 要转换的 JSON 值.
+    ///
+    /// # 返回
+    /// 如果 `value` 是一个对象, 返回对该对象的引用; 否则返回错误.
+    pub fn try_into_map(value: &Value) -> JsonResult<&Map<String, Value>> {
+        FromJson::from_json(value, "")
+    }
+
+    /// 尝试将 JSON 值转换为字符串引用.
+    ///
+    /// # 参数
+    /// - `value`: 要转换的 JSON 值.
+    ///
+    /// # 返回
+    /// 如果 `value` 是字符串, 返回其引用; 否则返回错误.
+    pub fn try_into_str(value: &Value) -> JsonResult<&str> {
+        FromJson::from_json(value, "")
+    }
+
+    /// 尝试将 JSON 值转换为 `f64` 浮点数.
+    ///
+    /// # 参数
+    /// - `value`: 要转换的 JSON 值.
+    ///
+    /// # 返回
+    /// 如果 `value` 是数字类型, 返回其 `f64` 表示; 否则返回错误.
+    pub fn try_into_f64(value: &Value) -> JsonResult<f64> {
+        FromJson::from_json(value, "")
+    }
+
+    /// 尝试将 JSON 值转换为 `u64` 整数.
+    ///
+    /// # 参数
+    /// - `value`: 要转换的 JSON 值.
+    ///
+    /// # 返回
+    /// 如果 `value` 是数字类型, 返回其 `u64` 表示; 否则返回错误.
+    pub fn try_into_u64(value: &Value) -> JsonResult<u64> {
+        FromJson::from_json(value, "")
+    }
+
+    /// 尝试将 JSON 值转换为 `i64` 整数.
+    ///
+    /// # 参数
+    /// - `value`: 要转换的 JSON 值.
+    ///
+    /// # 返回
+    /// 如果 `value` 是数字类型, 返回其 `i64` 表示; 否则返回错误.
+    pub fn try_into_i64(value: &Value) -> JsonResult<i64> {
+        FromJson::from_json(value, "")
+    }
+
+    /// 尝试将 JSON 值转换为布尔值.
+    ///
+    /// # 参数
+    /// - `value`: 要转换的 JSON 值.
+    ///
+    /// # 返回
+    /// 如果 `value` 是布尔类型, 返回其值; 否则返回错误.
+    pub fn try_into_bool(value: &Value) -> JsonResult<bool> {
+        FromJson::from_json(value, "")
+    }
+
+    /// 尝试将 JSON 值转换为 `u8`.
+    ///
+    /// # 参数
+    /// - `value`: 要转换的 JSON 值.
+    ///
+    /// # 返回
+    /// 如果 `value` 为整数且在 `u8` 范围内, 返回转换后的值; 否则返回错误.
+    pub fn try_into_u8(value: &Value) -> JsonResult<u8> {
+        FromJson::from_json(value, "")
+    }
+
+    /// 尝试将 JSON 值转换为 `u16`.
+    ///
+    /// # 参数
+    /// - `value`: 要转换的 JSON 值.
+    ///
+    /// # 返回
+    /// 如果 `value` 为整数且在 `u16` 范围内, 返回转换后的值; 否则返回错误.
+    pub fn try_into_u16(value: &Value) -> JsonResult<u16> {
+        FromJson::from_json(value, "")
+    }
+
+    /// 尝试将 JSON 值转换为 `u32`.
+    ///
+    /// # 参数
+    /// - `value`: 要转换的 JSON 值.
+    ///
+    /// # 返回
+    /// 如果 `value` 为整数且在 `u32` 范围内, 返回转换后的值; 否则返回错误.
+    pub fn try_into_u32(value: &Value) -> JsonResult<u32> {
+        FromJson::from_json(value, "")
+    }
+
+    /// 尝试将 JSON 值转换为 `i32`.
+    ///
+    /// # 参数
+    /// - `value`: 要转换的 JSON 值.
+    ///
+    /// # 返回
+    /// 如果 `value` 为整数且在 `i32` 范围内, 返回转换后的值; 否则返回错误.
+    pub fn try_into_i32(value: &Value) -> JsonResult<i32> {
+        FromJson::from_json(value, "")
+    }
+
+    /// 尝试将 JSON 值转换为 [Decimal].
+    ///
+    /// # 参数
+    /// - `value`: 要转换的 JSON 值.
+    ///
+    /// # 返回
+    /// 如果 `value` 为数字或十进制数字符串, 返回转换后的值; 否则返回错误.
+    pub fn try_into_decimal(value: &Value) -> JsonResult<Decimal> {
+        FromJson::from_json(value, "")
+    }
+
+    /// 尝试将 JSON 值转换为 `DateTime<Utc>`.
+    ///
+    /// # 参数
+    /// - `value`: 要转换的 JSON 值.
+    ///
+    /// # 返回
+    /// 如果 `value` 为毫秒时间戳或 RFC3339 字符串, 返回转换后的值; 否则返回错误.
+    pub fn try_into_datetime(value: &Value) -> JsonResult<DateTime<Utc>> {
+        FromJson::from_json(value, "")
+    }
+}
+
+impl Extract {
+    /// 从 JSON 对象中提取指定字段, 并尝试将其转换为数组 (`Vec<Value>`).
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 如果字段存在且类型为数组, 返回该数组的引用; 否则返回错误.
+    pub fn get_array<'a>(value: &'a Value, key: &str) -> JsonResult<&'a Vec<Value>> {
+        Self::get(value, key)
+    }
+
+    /// 从 JSON 对象中提取指定字段, 并尝试将其转换为对象 (`Map<String, Value>`).
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 如果字段存在且为对象, 返回引用; 否则返回错误.
+    pub fn get_map<'a>(value: &'a Value, key: &str) -> JsonResult<&'a Map<String, Value>> {
+        Self::get(value, key)
+    }
+
+    /// 从 JSON 对象中提取指定字段的值.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 如果字段存在, 返回该字段的引用; 否则返回错误.
+    pub fn get_value<'a>(value: &'a Value, key: &str) -> JsonResult<&'a Value> {
+        Self::field(value, key)
+    }
+
+    /// 从 JSON 对象中提取指定字段, 并尝试将其转换为字符串.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 如果字段存在且为字符串, 返回引用; 否则返回错误.
+    pub fn get_str<'a>(value: &'a Value, key: &str) -> JsonResult<&'a str> {
+        Self::get(value, key)
+    }
+
+    /// 从 JSON 对象中提取指定字段, 并尝试将其转换为 `f64`.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 如果字段存在且为数字, 返回 `f64`; 否则返回错误.
+    pub fn get_f64(value: &Value, key: &str) -> JsonResult<f64> {
+        Self::get(value, key)
+    }
+
+    /// 从 JSON 对象中提取指定字段, 并尝试将其转换为 `u64`.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 如果字段存在且为无符号整数, 返回 `u64`; 否则返回错误.
+    pub fn get_u64(value: &Value, key: &str) -> JsonResult<u64> {
+        Self::get(value, key)
+    }
+
+    /// 从 JSON 对象中提取指定字段, 并尝试将其转换为 `i64`.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 如果字段存在且为有符号整数, 返回 `i64`; 否则返回错误.
+    pub fn get_i64(value: &Value, key: &str) -> JsonResult<i64> {
+        Self::get(value, key)
+    }
+
+    /// 从 JSON 对象中提取指定字段, 并尝试将其转换为布尔值.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 如果字段存在且为布尔类型, 返回其值; 否则返回错误.
+    pub fn get_bool(value: &Value, key: &str) -> JsonResult<bool> {
+        Self::get(value, key)
+    }
+
+    /// 从 JSON 对象中提取指定字段, 并尝试将其转换为 `u8`.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 如果字段存在且为整数且在 `u8` 范围内, 返回转换后的值; 否则返回错误.
+    pub fn get_u8(value: &Value, key: &str) -> JsonResult<u8> {
+        Self::get(value, key)
+    }
+
+    /// 从 JSON 对象中提取指定字段, 并尝试将其转换为 `u16`.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 如果字段存在且为整数且在 `u16` 范围内, 返回转换后的值; 否则返回错误.
+    pub fn get_u16(value: &Value, key: &str) -> JsonResult<u16> {
+        Self::get(value, key)
+    }
+
+    /// 从 JSON 对象中提取指定字段, 并尝试将其转换为 `u32`.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 如果字段存在且为整数且在 `u32` 范围内, 返回转换后的值; 否则返回错误.
+    pub fn get_u32(value: &Value, key: &str) -> JsonResult<u32> {
+        Self::get(value, key)
+    }
+
+    /// 从 JSON 对象中提取指定字段, 并尝试将其转换为 `i32`.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 如果字段存在且为整数且在 `i32` 范围内, 返回转换后的值; 否则返回错误.
+    pub fn get_i32(value: &Value, key: &str) -> JsonResult<i32> {
+        Self::get(value, key)
+    }
+
+    /// 从 JSON 对象中提取指定字段, 并尝试将其转换为 [Decimal].
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 如果字段存在且为数字或十进制数字符串, 返回转换后的值; 否则返回错误.
+    pub fn get_decimal(value: &Value, key: &str) -> JsonResult<Decimal> {
+        Self::get(value, key)
+    }
+
+    /// 从 JSON 对象中提取指定字段, 并尝试将其转换为 `DateTime<Utc>`.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 如果字段存在且为毫秒时间戳或 RFC3339 字符串, 返回转换后的值; 否则返回错误.
+    pub fn get_datetime(value: &Value, key: &str) -> JsonResult<DateTime<Utc>> {
+        Self::get(value, key)
+    }
+
+    /// 从 JSON 对象中提取可选字段, 并尝试将其转换为数组 (`Vec<Value>`).
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 字段不存在或为 `null` 时返回 `None`; 字段类型错误时返回错误.
+    pub fn opt_array<'a>(value: &'a Value, key: &str) -> JsonResult<Option<&'a Vec<Value>>> {
+        Self::opt(value, key)
+    }
+
+    /// 从 JSON 对象中提取可选字段, 并尝试将其转换为对象 (`Map<String, Value>`).
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 字段不存在或为 `null` 时返回 `None`; 字段类型错误时返回错误.
+    pub fn opt_map<'a>(value: &'a Value, key: &str) -> JsonResult<Option<&'a Map<String, Value>>> {
+        Self::opt(value, key)
+    }
+
+    /// 从 JSON 对象中提取可选字段的值.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 字段不存在或为 `null` 时返回 `None`, 否则返回该字段的引用.
+    pub fn opt_value<'a>(value: &'a Value, key: &str) -> JsonResult<Option<&'a Value>> {
+        Self::opt(value, key)
+    }
+
+    /// 从 JSON 对象中提取可选字段, 并尝试将其转换为字符串.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 字段不存在或为 `null` 时返回 `None`; 字段类型错误时返回错误.
+    pub fn opt_str<'a>(value: &'a Value, key: &str) -> JsonResult<Option<&'a str>> {
+        Self::opt(value, key)
+    }
+
+    /// 从 JSON 对象中提取可选字段, 并尝试将其转换为 `f64`.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 字段不存在或为 `null` 时返回 `None`; 字段类型错误时返回错误.
+    pub fn opt_f64(value: &Value, key: &str) -> JsonResult<Option<f64>> {
+        Self::opt(value, key)
+    }
+
+    /// 从 JSON 对象中提取可选字段, 并尝试将其转换为 `u64`.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 字段不存在或为 `null` 时返回 `None`; 字段类型错误时返回错误.
+    pub fn opt_u64(value: &Value, key: &str) -> JsonResult<Option<u64>> {
+        Self::opt(value, key)
+    }
+
+    /// 从 JSON 对象中提取可选字段, 并尝试将其转换为 `i64`.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 字段不存在或为 `null` 时返回 `None`; 字段类型错误时返回错误.
+    pub fn opt_i64(value: &Value, key: &str) -> JsonResult<Option<i64>> {
+        Self::opt(value, key)
+    }
+
+    /// 从 JSON 对象中提取可选字段, 并尝试将其转换为布尔值.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 字段不存在或为 `null` 时返回 `None`; 字段类型错误时返回错误.
+    pub fn opt_bool(value: &Value, key: &str) -> JsonResult<Option<bool>> {
+        Self::opt(value, key)
+    }
+
+    /// 从 JSON 对象中提取可选字段, 并尝试将其转换为 `u8`.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 字段不存在或为 `null` 时返回 `None`; 字段类型错误时返回错误.
+    pub fn opt_u8(value: &Value, key: &str) -> JsonResult<Option<u8>> {
+        Self::opt(value, key)
+    }
+
+    /// 从 JSON 对象中提取可选字段, 并尝试将其转换为 `u16`.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 字段不存在或为 `null` 时返回 `None`; 字段类型错误时返回错误.
+    pub fn opt_u16(value: &Value, key: &str) -> JsonResult<Option<u16>> {
+        Self::opt(value, key)
+    }
+
+    /// 从 JSON 对象中提取可选字段, 并尝试将其转换为 `u32`.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 字段不存在或为 `null` 时返回 `None`; 字段类型错误时返回错误.
+    pub fn opt_u32(value: &Value, key: &str) -> JsonResult<Option<u32>> {
+        Self::opt(value, key)
+    }
+
+    /// 从 JSON 对象中提取可选字段, 并尝试将其转换为 `i32`.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 字段不存在或为 `null` 时返回 `None`; 字段类型错误时返回错误.
+    pub fn opt_i32(value: &Value, key: &str) -> JsonResult<Option<i32>> {
+        Self::opt(value, key)
+    }
+
+    /// 从 JSON 对象中提取可选字段, 并尝试将其转换为 [Decimal].
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 字段不存在或为 `null` 时返回 `None`; 字段类型错误时返回错误.
+    pub fn opt_decimal(value: &Value, key: &str) -> JsonResult<Option<Decimal>> {
+        Self::opt(value, key)
+    }
+
+    /// 从 JSON 对象中提取可选字段, 并尝试将其转换为 `DateTime<Utc>`.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    ///
+    /// # 返回
+    /// 字段不存在或为 `null` 时返回 `None`; 字段类型错误时返回错误.
+    pub fn opt_datetime(value: &Value, key: &str) -> JsonResult<Option<DateTime<Utc>>> {
+        Self::opt(value, key)
+    }
+
+    /// 从 JSON 对象中提取字段并转换为字符串, 字段不存在时返回默认值.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    /// - `default`: 字段不存在或为 `null` 时返回的默认值.
+    ///
+    /// # 返回
+    /// 字段类型错误时返回错误.
+    pub fn get_str_or<'a>(value: &'a Value, key: &str, default: &'a str) -> JsonResult<&'a str> {
+        Self::get_or(value, key, default)
+    }
+
+    /// 从 JSON 对象中提取字段并转换为 `f64`, 字段不存在时返回默认值.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    /// - `default`: 字段不存在或为 `null` 时返回的默认值.
+    ///
+    /// # 返回
+    /// 字段类型错误时返回错误.
+    pub fn get_f64_or(value: &Value, key: &str, default: f64) -> JsonResult<f64> {
+        Self::get_or(value, key, default)
+    }
+
+    /// 从 JSON 对象中提取字段并转换为 `u64`, 字段不存在时返回默认值.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    /// - `default`: 字段不存在或为 `null` 时返回的默认值.
+    ///
+    /// # 返回
+    /// 字段类型错误时返回错误.
+    pub fn get_u64_or(value: &Value, key: &str, default: u64) -> JsonResult<u64> {
+        Self::get_or(value, key, default)
+    }
+
+    /// 从 JSON 对象中提取字段并转换为 `i64`, 字段不存在时返回默认值.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    /// - `default`: 字段不存在或为 `null` 时返回的默认值.
+    ///
+    /// # 返回
+    /// 字段类型错误时返回错误.
+    pub fn get_i64_or(value: &Value, key: &str, default: i64) -> JsonResult<i64> {
+        Self::get_or(value, key, default)
+    }
+
+    /// 从 JSON 对象中提取字段并转换为布尔值, 字段不存在时返回默认值.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    /// - `default`: 字段不存在或为 `null` 时返回的默认值.
+    ///
+    /// # 返回
+    /// 字段类型错误时返回错误.
+    pub fn get_bool_or(value: &Value, key: &str, default: bool) -> JsonResult<bool> {
+        Self::get_or(value, key, default)
+    }
+
+    /// 从 JSON 对象中提取字段并转换为 `u8`, 字段不存在时返回默认值.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    /// - `default`: 字段不存在或为 `null` 时返回的默认值.
+    ///
+    /// # 返回
+    /// 字段类型错误时返回错误.
+    pub fn get_u8_or(value: &Value, key: &str, default: u8) -> JsonResult<u8> {
+        Self::get_or(value, key, default)
+    }
+
+    /// 从 JSON 对象中提取字段并转换为 `u16`, 字段不存在时返回默认值.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    /// - `default`: 字段不存在或为 `null` 时返回的默认值.
+    ///
+    /// # 返回
+    /// 字段类型错误时返回错误.
+    pub fn get_u16_or(value: &Value, key: &str, default: u16) -> JsonResult<u16> {
+        Self::get_or(value, key, default)
+    }
+
+    /// 从 JSON 对象中提取字段并转换为 `u32`, 字段不存在时返回默认值.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    /// - `default`: 字段不存在或为 `null` 时返回的默认值.
+    ///
+    /// # 返回
+    /// 字段类型错误时返回错误.
+    pub fn get_u32_or(value: &Value, key: &str, default: u32) -> JsonResult<u32> {
+        Self::get_or(value, key, default)
+    }
+
+    /// 从 JSON 对象中提取字段并转换为 `i32`, 字段不存在时返回默认值.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    /// - `default`: 字段不存在或为 `null` 时返回的默认值.
+    ///
+    /// # 返回
+    /// 字段类型错误时返回错误.
+    pub fn get_i32_or(value: &Value, key: &str, default: i32) -> JsonResult<i32> {
+        Self::get_or(value, key, default)
+    }
+
+    /// 从 JSON 对象中提取字段并转换为 [Decimal], 字段不存在时返回默认值.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    /// - `default`: 字段不存在或为 `null` 时返回的默认值.
+    ///
+    /// # 返回
+    /// 字段类型错误时返回错误.
+    pub fn get_decimal_or(value: &Value, key: &str, default: Decimal) -> JsonResult<Decimal> {
+        Self::get_or(value, key, default)
+    }
+
+    /// 从 JSON 对象中提取字段并转换为 `DateTime<Utc>`, 字段不存在时返回默认值.
+    ///
+    /// # 参数
+    /// - `value`: JSON 对象.
+    /// - `key`: 要提取的字段名.
+    /// - `default`: 字段不存在或为 `null` 时返回的默认值.
+    ///
+    /// # 返回
+    /// 字段类型错误时返回错误.
+    pub fn get_datetime_or(
+        value: &Value,
+        key: &str,
+        default: DateTime<Utc>,
+    ) -> JsonResult<DateTime<Utc>> {
+        Self::get_or(value, key, default)
+    }
+
+    /// 按路径提取字段, 并尝试将其转换为数组 (`Vec<Value>`).
+    ///
+    /// # 参数
+    /// - `value`: JSON 根节点.
+    /// - `path`: JSON Pointer 或点号路径, 如 `/a/b/0` 或 `a.b[0]`.
+    ///
+    /// # 返回
+    /// 如果路径存在且类型为数组, 返回该数组的引用; 否则返回错误.
+    pub fn get_array_at<'a>(value: &'a Value, path: &str) -> JsonResult<&'a Vec<Value>> {
+        Self::get_at(value, path)
+    }
+
+    /// 按路径提取字段, 并尝试将其转换为对象 (`Map<String, Value>`).
+    ///
+    /// # 参数
+    /// - `value`: JSON 根节点.
+    /// - `path`: JSON Pointer 或点号路径.
+    ///
+    /// # 返回
+    /// 如果路径存在且为对象, 返回引用; 否则返回错误.
+    pub fn get_map_at<'a>(value: &'a Value, path: &str) -> JsonResult<&'a Map<String, Value>> {
+        Self::get_at(value, path)
+    }
+
+    /// 按路径提取字段的值.
+    ///
+    /// # 参数
+    /// - `value`: JSON 根节点.
+    /// - `path`: JSON Pointer 或点号路径.
+    ///
+    /// # 返回
+    /// 如果路径存在, 返回该字段的引用; 否则返回错误.
+    pub fn get_value_at<'a>(value: &'a Value, path: &str) -> JsonResult<&'a Value> {
+        Self::get_at(value, path)
+    }
+
+    /// 按路径提取字段, 并尝试将其转换为字符串.
+    ///
+    /// # 参数
+    /// - `value`: JSON 根节点.
+    /// - `path`: JSON Pointer 或点号路径.
+    ///
+    /// # 返回
+    /// 如果路径存在且为字符串, 返回引用; 否则返回错误.
+    pub fn get_str_at<'a>(value: &'a Value, path: &str) -> JsonResult<&'a str> {
+        Self::get_at(value, path)
+    }
+
+    /// 按路径提取字段, 并尝试将其转换为 `f64`.
+    ///
+    /// # 参数
+    /// - `value`: JSON 根节点.
+    /// - `path`: JSON Pointer 或点号路径.
+    ///
+    /// # 返回
+    /// 如果路径存在且为数字, 返回 `f64`; 否则返回错误.
+    pub fn get_f64_at(value: &Value, path: &str) -> JsonResult<f64> {
+        Self::get_at(value, path)
+    }
+
+    /// 按路径提取字段, 并尝试将其转换为 `u64`.
+    ///
+    /// # 参数
+    /// - `value`: JSON 根节点.
+    /// - `path`: JSON Pointer 或点号路径.
+    ///
+    /// # 返回
+    /// 如果路径存在且为无符号整数, 返回 `u64`; 否则返回错误.
+    pub fn get_u64_at(value: &Value, path: &str) -> JsonResult<u64> {
+        Self::get_at(value, path)
+    }
+
+    /// 按路径提取字段, 并尝试将其转换为 `i64`.
+    ///
+    /// # 参数
+    /// - `value`: JSON 根节点.
+    /// - `path`: JSON Pointer 或点号路径.
+    ///
+    /// # 返回
+    /// 如果路径存在且为有符号整数, 返回 `i64`; 否则返回错误.
+    pub fn get_i64_at(value: &Value, path: &str) -> JsonResult<i64> {
+        Self::get_at(value, path)
+    }
+
+    /// 按路径提取字段, 并尝试将其转换为布尔值.
+    ///
+    /// # 参数
+    /// - `value`: JSON 根节点.
+    /// - `path`: JSON Pointer 或点号路径.
+    ///
+    /// # 返回
+    /// 如果路径存在且为布尔类型, 返回其值; 否则返回错误.
+    pub fn get_bool_at(value: &Value, path: &str) -> JsonResult<bool> {
+        Self::get_at(value, path)
+    }
+
+    /// 按路径提取字段, 并尝试将其转换为 `u8`.
+    ///
+    /// # 参数
+    /// - `value`: JSON 根节点.
+    /// - `path`: JSON Pointer 或点号路径.
+    ///
+    /// # 返回
+    /// 如果路径存在且为整数且在 `u8` 范围内, 返回转换后的值; 否则返回错误.
+    pub fn get_u8_at(value: &Value, path: &str) -> JsonResult<u8> {
+        Self::get_at(value, path)
+    }
+
+    /// 按路径提取字段, 并尝试将其转换为 `u16`.
+    ///
+    /// # 参数
+    /// - `value`: JSON 根节点.
+    /// - `path`: JSON Pointer 或点号路径.
+    ///
+    /// # 返回
+    /// 如果路径存在且为整数且在 `u16` 范围内, 返回转换后的值; 否则返回错误.
+    pub fn get_u16_at(value: &Value, path: &str) -> JsonResult<u16> {
+        Self::get_at(value, path)
+    }
+
+    /// 按路径提取字段, 并尝试将其转换为 `u32`.
+    ///
+    /// # 参数
+    /// - `value`: JSON 根节点.
+    /// - `path`: JSON Pointer 或点号路径.
+    ///
+    /// # 返回
+    /// 如果路径存在且为整数且在 `u32` 范围内, 返回转换后的值; 否则返回错误.
+    pub fn get_u32_at(value: &Value, path: &str) -> JsonResult<u32> {
+        Self::get_at(value, path)
+    }
+
+    /// 按路径提取字段, 并尝试将其转换为 `i32`.
+    ///
+    /// # 参数
+    /// - `value`: JSON 根节点.
+    /// - `path`: JSON Pointer 或点号路径.
+    ///
+    /// # 返回
+    /// 如果路径存在且为整数且在 `i32` 范围内, 返回转换后的值; 否则返回错误.
+    pub fn get_i32_at(value: &Value, path: &str) -> JsonResult<i32> {
+        Self::get_at(value, path)
+    }
+
+    /// 按路径提取字段, 并尝试将其转换为 [Decimal].
+    ///
+    /// # 参数
+    /// - `value`: JSON 根节点.
+    /// - `path`: JSON Pointer 或点号路径.
+    ///
+    /// # 返回
+    /// 如果路径存在且为数字或十进制数字符串, 返回转换后的值; 否则返回错误.
+    pub fn get_decimal_at(value: &Value, path: &str) -> JsonResult<Decimal> {
+        Self::get_at(value, path)
+    }
+
+    /// 按路径提取字段, 并尝试将其转换为 `DateTime<Utc>`.
+    ///
+    /// # 参数
+    /// - `value`: JSON 根节点.
+    /// - `path`: JSON Pointer 或点号路径.
+    ///
+    /// # 返回
+    /// 如果路径存在且为毫秒时间戳或 RFC3339 字符串, 返回转换后的值; 否则返回错误.
+    pub fn get_datetime_at(value: &Value, path: &str) -> JsonResult<DateTime<Utc>> {
+        Self::get_at(value, path)
+    }
+
+    /// 提取指定字段, 字段不存在时返回 [`JsonError::MissingField`].
+    fn field<'a>(value: &'a Value, key: &str) -> JsonResult<&'a Value> {
+        value.get(key).ok_or_else(|| JsonError::missing(key))
+    }
+
+    /// 提取指定字段并转换为目标类型.
+    fn get<'a, T: FromJson<'a>>(value: &'a Value, key: &str) -> JsonResult<T> {
+        T::from_json(Self::field(value, key)?, key)
+    }
+
+    /// 提取可选字段并转换为目标类型, 字段不存在或为 `null` 时返回 `None`.
+    fn opt<'a, T: FromJson<'a>>(value: &'a Value, key: &str) -> JsonResult<Option<T>> {
+        match value.get(key) {
+            None | Some(Value::Null) => Ok(None),
+            Some(field) => T::from_json(field, key).map(Some),
+        }
+    }
+
+    /// 提取字段并转换为目标类型, 字段不存在或为 `null` 时返回默认值.
+    fn get_or<'a, T: FromJson<'a>>(value: &'a Value, key: &str, default: T) -> JsonResult<T> {
+        Ok(Self::opt(value, key)?.unwrap_or(default))
+    }
+
+    /// 按路径查找值并转换为目标类型, 类型错误时以完整路径报错.
+    fn get_at<'a, T: FromJson<'a>>(value: &'a Value, path: &str) -> JsonResult<T> {
+        let path = JsonPath::parse(path)?;
+        T::from_json(path.lookup(value)?, &path.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn value() -> Value {
+        json!({"id": 7, "name": "d1", "empty": null, "big": 300, "price": "9.90"})
+    }
+
+    #[test]
+    fn get_reports_missing_and_mismatched_fields() {
+        let value = value();
+        assert_eq!(Extract::get_u8(&value, "id"), Ok(7));
+        assert_eq!(
+            Extract::get_u8(&value, "missing"),
+            Err(JsonError::missing("missing"))
+        );
+        assert_eq!(
+            Extract::get_str(&value, "empty"),
+            Err(JsonError::mismatch("empty", "字符串", &Value::Null))
+        );
+        assert!(matches!(
+            Extract::get_u8(&value, "big"),
+            Err(JsonError::OutOfRange { target: "u8", .. })
+        ));
+        assert_eq!(
+            Extract::get_decimal(&value, "price"),
+            Ok(Decimal::new(990, 2))
+        );
+    }
+
+    #[test]
+    fn opt_treats_missing_and_null_as_none() {
+        let value = value();
+        assert_eq!(Extract::opt_str(&value, "missing"), Ok(None));
+        assert_eq!(Extract::opt_str(&value, "empty"), Ok(None));
+        assert_eq!(Extract::opt_value(&value, "empty"), Ok(None));
+        assert_eq!(Extract::opt_str(&value, "name"), Ok(Some("d1")));
+        assert_eq!(Extract::opt_u16(&value, "big"), Ok(Some(300)));
+        // 类型错误不会被当作缺失
+        assert_eq!(
+            Extract::opt_str(&value, "id"),
+            Err(JsonError::mismatch("id", "字符串", &json!(7)))
+        );
+        assert!(matches!(
+            Extract::opt_u8(&value, "big"),
+            Err(JsonError::OutOfRange { .. })
+        ));
+        assert_eq!(Extract::opt_datetime(&value, "empty"), Ok(None));
+    }
+
+    #[test]
+    fn or_uses_default_only_for_missing_and_null() {
+        let value = value();
+        assert_eq!(Extract::get_u32_or(&value, "missing", 5), Ok(5));
+        assert_eq!(Extract::get_str_or(&value, "empty", "x"), Ok("x"));
+        assert_eq!(Extract::get_u64_or(&value, "id", 5), Ok(7));
+        assert_eq!(
+            Extract::get_bool_or(&value, "name", false),
+            Err(JsonError::mismatch("name", "布尔值", &json!("d1")))
+        );
+        assert_eq!(
+            Extract::get_decimal_or(&value, "missing", Decimal::ONE),
+            Ok(Decimal::ONE)
+        );
+    }
+
+    #[test]
+    fn convert_reports_root_path() {
+        assert_eq!(Convert::try_into_i32(&json!(-5)), Ok(-5));
+        let err = Convert::try_into_u8(&json!(-5)).unwrap_err();
+        assert_eq!(err.path(), "");
+        assert_eq!(err.to_string(), "数值超出范围, $ 的值 -5 无法转换为 u8.");
+    }
+}
@@ -0,0 +1,508 @@
+//! JSON 局部更新与比较
+//!
+//! - RFC 7396 JSON Merge Patch: [`Patch::merge`], [`Patch::merge_diff`]
+//! - RFC 6902 JSON Patch: [`Patch::apply`]
+//! - 深度合并: [`Patch::deep_merge`]
+//! - 结构化比较: [`Patch::diff`]
+//!
+//! 所有路径都使用 JSON Pointer.
+
+use super::error::{JsonError, JsonResult};
+use super::path::{JsonPath, Segment, parse_index};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// 提供合并、打补丁和比较 [Value] 的方法.
+pub struct Patch;
+
+/// RFC 6902 中的一个操作.
+///
+/// 可以直接从 `{"op": "add", "path": "/a", "value": 1}` 形式的 JSON 反序列化.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOp {
+    /// 添加字段或插入数组元素, 数组下标为 `-` 时追加到末尾.
+    Add {
+        /// 目标位置.
+        path: String,
+        /// 要添加的值.
+        value: Value,
+    },
+    /// 删除字段或数组元素.
+    Remove {
+        /// 目标位置.
+        path: String,
+    },
+    /// 替换已存在的值.
+    Replace {
+        /// 目标位置.
+        path: String,
+        /// 新值.
+        value: Value,
+    },
+    /// 将 `from` 处的值移动到 `path`.
+    Move {
+        /// 源位置.
+        from: String,
+        /// 目标位置.
+        path: String,
+    },
+    /// 将 `from` 处的值复制到 `path`.
+    Copy {
+        /// 源位置.
+        from: String,
+        /// 目标位置.
+        path: String,
+    },
+    /// 检查 `path` 处的值是否等于 `value`, 不相等时整个补丁失败.
+    Test {
+        /// 目标位置.
+        path: String,
+        /// 期望的值.
+        value: Value,
+    },
+}
+
+/// [`Patch::diff`] 中的变更类型.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    /// 新增.
+    Added,
+    /// 删除.
+    Removed,
+    /// 修改.
+    Changed,
+}
+
+/// [`Patch::diff`] 中的一处变更.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Change {
+    /// 变更类型.
+    pub kind: ChangeKind,
+    /// 变更位置的 JSON Pointer.
+    pub path: String,
+    /// 旧值, 新增时为 `None`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old: Option<Value>,
+    /// 新值, 删除时为 `None`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new: Option<Value>,
+}
+
+impl Patch {
+    /// 按 RFC 7396 将 `patch` 合并到 `target`.
+    ///
+    /// # 参数
+    /// - `target`: 被修改的值.
+    /// - `patch`: 合并补丁, 其中值为 `null` 的字段会从 `target` 中删除.
+    pub fn merge(target: &mut Value, patch: &Value) {
+        let Value::Object(patch) = patch else {
+            *target = patch.clone();
+            return;
+        };
+        if !target.is_object() {
+            *target = Value::Object(Map::new());
+        }
+        if let Value::Object(map) = target {
+            for (key, value) in patch {
+                if value.is_null() {
+                    map.remove(key);
+                } else {
+                    Self::merge(map.entry(key.clone()).or_insert(Value::Null), value);
+                }
+            }
+        }
+    }
+
+    /// 生成从 `from` 变为 `to` 的 RFC 7396 合并补丁.
+    ///
+    /// # 参数
+    /// - `from`: 原始值.
+    /// - `to`: 目标值.
+    ///
+    /// # 返回
+    /// 合并补丁. 由于 `null` 表示删除, `to` 中值为 `null` 的字段无法通过合并补丁表达.
+    pub fn merge_diff(from: &Value, to: &Value) -> Value {
+        let (Value::Object(from), Value::Object(to)) = (from, to) else {
+            return to.clone();
+        };
+        let mut patch = Map::new();
+        for key in from.keys() {
+            if !to.contains_key(key) {
+                patch.insert(key.clone(), Value::Null);
+            }
+        }
+        for (key, value) in to {
+            match from.get(key) {
+                Some(old) if old == value => {}
+                Some(old) => {
+                    patch.insert(key.clone(), Self::merge_diff(old, value));
+                }
+                None => {
+                    patch.insert(key.clone(), value.clone());
+                }
+            }
+        }
+        Value::Object(patch)
+    }
+
+    /// 深度合并, 将 `source` 合并到 `target`.
+    ///
+    /// 与 [`Patch::merge`] 不同, `source` 中的 `null` 会被保留, 不表示删除.
+    /// 两边都是对象时递归合并, 其余情况 (包括数组) 用 `source` 替换.
+    ///
+    /// # 参数
+    /// - `target`: 被修改的值.
+    /// - `source`: 要合并进来的值.
+    pub fn deep_merge(target: &mut Value, source: &Value) {
+        match (target, source) {
+            (Value::Object(target), Value::Object(source)) => {
+                for (key, value) in source {
+                    match target.get_mut(key) {
+                        Some(existing) => Self::deep_merge(existing, value),
+                        None => {
+                            target.insert(key.clone(), value.clone());
+                        }
+                    }
+                }
+            }
+            (target, source) => *target = source.clone(),
+        }
+    }
+
+    /// 按 RFC 6902 依次执行补丁操作.
+    ///
+    /// 所有操作要么全部生效, 要么在出错时保持 `doc` 不变.
+    ///
+    /// # 参数
+    /// - `doc`: 被修改的文档.
+    /// - `ops`: 补丁操作.
+    ///
+    /// # 返回
+    /// 出错时返回第一个失败操作的错误, `test` 不通过时返回 [`JsonError::TestFailed`].
+    pub fn apply(doc: &mut Value, ops: &[PatchOp]) -> JsonResult<()> {
+        let mut working = doc.clone();
+        for op in ops {
+            apply_op(&mut working, op)?;
+        }
+        *doc = working;
+        Ok(())
+    }
+
+    /// 解析 JSON 格式的补丁并执行, 见 [`Patch::apply`].
+    ///
+    /// # 参数
+    /// - `doc`: 被修改的文档.
+    /// - `ops`: JSON 数组形式的补丁.
+    ///
+    /// # 返回
+    /// 补丁格式错误时返回 [`JsonError::InvalidPatch`]; 其余同 [`Patch::apply`].
+    pub fn apply_json(doc: &mut Value, ops: &Value) -> JsonResult<()> {
+        let ops: Vec<PatchOp> =
+            serde_json::from_value(ops.clone()).map_err(|e| JsonError::InvalidPatch {
+                reason: e.to_string(),
+            })?;
+        Self::apply(doc, &ops)
+    }
+
+    /// 比较两个值, 列出所有新增、删除和修改的位置.
+    ///
+    /// 对象按字段比较, 数组按下标比较.
+    ///
+    /// # 参数
+    /// - `from`: 原始值.
+    /// - `to`: 目标值.
+    ///
+    /// # 返回
+    /// 所有变更, 按路径顺序排列.
+    pub fn diff(from: &Value, to: &Value) -> Vec<Change> {
+        let mut changes = Vec::new();
+        diff_into(from, to, &mut String::new(), &mut changes);
+        changes
+    }
+}
+
+fn apply_op(doc: &mut Value, op: &PatchOp) -> JsonResult<()> {
+    match op {
+        PatchOp::Add { path, value } => add(doc, &JsonPath::pointer(path)?, value.clone()),
+        PatchOp::Remove { path } => remove(doc, &JsonPath::pointer(path)?).map(drop),
+        PatchOp::Replace { path, value } => {
+            *JsonPath::pointer(path)?.lookup_mut(doc)? = value.clone();
+            Ok(())
+        }
+        PatchOp::Move { from, path } => {
+            let (from, to) = (JsonPath::pointer(from)?, JsonPath::pointer(path)?);
+            if from.is_proper_prefix_of(&to) {
+                return Err(JsonError::InvalidPath {
+                    path: path.clone(),
+                    reason: "不能移动到自身的子节点",
+                });
+            }
+            let value = remove(doc, &from)?;
+            add(doc, &to, value)
+        }
+        PatchOp::Copy { from, path } => {
+            let value = JsonPath::pointer(from)?.lookup(doc)?.clone();
+            add(doc, &JsonPath::pointer(path)?, value)
+        }
+        PatchOp::Test { path, value } => {
+            if JsonPath::pointer(path)?.lookup(doc)? == value {
+                Ok(())
+            } else {
+                Err(JsonError::TestFailed { path: path.clone() })
+            }
+        }
+    }
+}
+
+fn add(doc: &mut Value, path: &JsonPath, value: Value) -> JsonResult<()> {
+    let Some((parent, Segment::Key(token))) = path.split_last() else {
+        *doc = value;
+        return Ok(());
+    };
+    match parent.lookup_mut(doc)? {
+        Value::Object(map) => {
+            map.insert(token.clone(), value);
+        }
+        Value::Array(array) if token == "-" => array.push(value),
+        Value::Array(array) => {
+            let index = array_index(token, path, array.len() + 1)?;
+            array.insert(index, value);
+        }
+        other => return Err(JsonError::mismatch(parent.to_string(), "对象或数组", other)),
+    }
+    Ok(())
+}
+
+fn remove(doc: &mut Value, path: &JsonPath) -> JsonResult<Value> {
+    let Some((parent, Segment::Key(token))) = path.split_last() else {
+        return Err(JsonError::InvalidPath {
+            path: path.to_string(),
+            reason: "不能删除根节点",
+        });
+    };
+    match parent.lookup_mut(doc)? {
+        Value::Object(map) => map
+            .remove(token)
+            .ok_or_else(|| JsonError::missing(path.to_string())),
+        Value::Array(array) => {
+            let index = array_index(token, path, array.len())?;
+            Ok(array.remove(index))
+        }
+        other => Err(JsonError::mismatch(parent.to_string(), "对象或数组", other)),
+    }
+}
+
+/// 解析数组下标, 并检查小于 `len`.
+fn array_index(token: &str, path: &JsonPath, len: usize) -> JsonResult<usize> {
+    let index = parse_index(token).ok_or_else(|| JsonError::InvalidPath {
+        path: path.to_string(),
+        reason: "数组下标应为非负整数",
+    })?;
+    if index < len {
+        Ok(index)
+    } else {
+        Err(JsonError::missing(path.to_string()))
+    }
+}
+
+fn diff_into(from: &Value, to: &Value, path: &mut String, changes: &mut Vec<Change>) {
+    match (from, to) {
+        (Value::Object(from), Value::Object(to)) => {
+            let mut keys: Vec<&String> = from.keys().chain(to.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let len = path.len();
+                path.push('/');
+                path.push_str(&key.replace('~', "~0").replace('/', "~1"));
+                diff_entry(from.get(key), to.get(key), path, changes);
+                path.truncate(len);
+            }
+        }
+        (Value::Array(from), Value::Array(to)) => {
+            for i in 0..from.len().max(to.len()) {
+                let len = path.len();
+                path.push_str(&format!("/{i}"));
+                diff_entry(from.get(i), to.get(i), path, changes);
+                path.truncate(len);
+            }
+        }
+        (from, to) if from != to => changes.push(Change {
+            kind: ChangeKind::Changed,
+            path: path.clone(),
+            old: Some(from.clone()),
+            new: Some(to.clone()),
+        }),
+        _ => {}
+    }
+}
+
+fn diff_entry(
+    from: Option<&Value>,
+    to: Option<&Value>,
+    path: &mut String,
+    changes: &mut Vec<Change>,
+) {
+    match (from, to) {
+        (Some(from), Some(to)) => diff_into(from, to, path, changes),
+        (Some(from), None) => changes.push(Change {
+            kind: ChangeKind::Removed,
+            path: path.clone(),
+            old: Some(from.clone()),
+            new: None,
+        }),
+        (None, Some(to)) => changes.push(Change {
+            kind: ChangeKind::Added,
+            path: path.clone(),
+            old: None,
+            new: Some(to.clone()),
+        }),
+        (None, None) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn merge_follows_rfc_7396() {
+        let mut target = json!({"a": "b", "c": {"d": "e", "f": "g"}, "tags": [1, 2]});
+        Patch::merge(
+            &mut target,
+            &json!({"a": "z", "c": {"f": null}, "tags": [3], "n": {"x": 1}}),
+        );
+        assert_eq!(
+            target,
+            json!({"a": "z", "c": {"d": "e"}, "tags": [3], "n": {"x": 1}})
+        );
+    }
+
+    #[test]
+    fn merge_diff_round_trips() {
+        let from = json!({"a": 1, "b": {"c": 2, "d": 3}, "e": [1]});
+        let to = json!({"a": 1, "b": {"c": 4}, "e": [1, 2], "f": true});
+        let patch = Patch::merge_diff(&from, &to);
+        assert_eq!(
+            patch,
+            json!({"b": {"c": 4, "d": null}, "e": [1, 2], "f": true})
+        );
+        let mut merged = from.clone();
+        Patch::merge(&mut merged, &patch);
+        assert_eq!(merged, to);
+    }
+
+    #[test]
+    fn deep_merge_keeps_nulls() {
+        let mut target = json!({"a": {"b": 1, "c": 2}, "list": [1, 2]});
+        Patch::deep_merge(&mut target, &json!({"a": {"c": null}, "list": [3]}));
+        assert_eq!(target, json!({"a": {"b": 1, "c": null}, "list": [3]}));
+    }
+
+    #[test]
+    fn apply_runs_all_operations() {
+        let mut doc = json!({"foo": {"bar": "baz", "waldo": "fred"}, "qux": {"corge": "grault"}, "list": [1, 3]});
+        Patch::apply_json(
+            &mut doc,
+            &json!([
+                {"op": "test", "path": "/foo/bar", "value": "baz"},
+                {"op": "add", "path": "/list/1", "value": 2},
+                {"op": "add", "path": "/list/-", "value": 4},
+                {"op": "remove", "path": "/foo/bar"},
+                {"op": "replace", "path": "/qux/corge", "value": "x"},
+                {"op": "move", "from": "/foo/waldo", "path": "/qux/thud"},
+                {"op": "copy", "from": "/qux/thud", "path": "/copied"}
+            ]),
+        )
+        .unwrap();
+        assert_eq!(
+            doc,
+            json!({
+                "foo": {},
+                "qux": {"corge": "x", "thud": "fred"},
+                "list": [1, 2, 3, 4],
+                "copied": "fred"
+            })
+        );
+    }
+
+    #[test]
+    fn apply_is_atomic() {
+        let original = json!({"a": 1});
+        let mut doc = original.clone();
+        let err = Patch::apply(
+            &mut doc,
+            &[
+                PatchOp::Add {
+                    path: "/b".to_owned(),
+                    value: json!(2),
+                },
+                PatchOp::Test {
+                    path: "/a".to_owned(),
+                    value: json!(2),
+                },
+            ],
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            JsonError::TestFailed {
+                path: "/a".to_owned()
+            }
+        );
+        assert_eq!(doc, original);
+    }
+
+    #[test]
+    fn apply_rejects_invalid_patches() {
+        let mut doc = json!({"a": {"b": 1}});
+        let err = Patch::apply_json(&mut doc, &json!([{"op": "jump", "path": "/a"}])).unwrap_err();
+        assert!(matches!(err, JsonError::InvalidPatch { .. }), "{err:?}");
+        let err =
+            Patch::apply_json(&mut doc, &json!([{"op": "remove", "path": "a.b"}])).unwrap_err();
+        assert!(matches!(err, JsonError::InvalidPath { .. }), "{err:?}");
+        let err = Patch::apply_json(
+            &mut doc,
+            &json!([{"op": "move", "from": "/a", "path": "/a/b/c"}]),
+        )
+        .unwrap_err();
+        assert!(matches!(err, JsonError::InvalidPath { .. }), "{err:?}");
+        let err =
+            Patch::apply_json(&mut doc, &json!([{"op": "remove", "path": "/x"}])).unwrap_err();
+        assert!(matches!(err, JsonError::MissingField { .. }), "{err:?}");
+    }
+
+    #[test]
+    fn diff_lists_changes_in_path_order() {
+        let from = json!({"a": 1, "b": [1, 2], "c": {"d": true}});
+        let to = json!({"a": 2, "b": [1], "c": {"d": true, "e/f": null}});
+        let changes = Patch::diff(&from, &to);
+        assert_eq!(
+            changes,
+            [
+                Change {
+                    kind: ChangeKind::Changed,
+                    path: "/a".to_owned(),
+                    old: Some(json!(1)),
+                    new: Some(json!(2)),
+                },
+                Change {
+                    kind: ChangeKind::Removed,
+                    path: "/b/1".to_owned(),
+                    old: Some(json!(2)),
+                    new: None,
+                },
+                Change {
+                    kind: ChangeKind::Added,
+                    path: "/c/e~1f".to_owned(),
+                    old: None,
+                    new: Some(json!(null)),
+                },
+            ]
+        );
+        assert!(Patch::diff(&from, &from).is_empty());
+    }
+}
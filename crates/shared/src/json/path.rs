@@ -0,0 +1,409 @@
+//! JSON 路径
+//!
+//! 支持两种写法:
+//! - JSON Pointer (RFC 6901): `/payload/device/sensors/2/value`
+//! - 点号路径: `payload.device.sensors[2].value`
+//!
+//! 以 `/` 开头 (或为空字符串) 的按 JSON Pointer 解析, 其余按点号路径解析.
+//! 字段名中包含 `.` 或 `[` 时, 请使用 JSON Pointer.
+
+use super::error::{JsonError, JsonResult};
+use serde_json::Value;
+use std::fmt;
+
+/// 路径中的一段.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Segment {
+    /// 对象字段名.
+    ///
+    /// JSON Pointer 中的每一段都解析为 `Key`, 遇到数组时再按下标解释.
+    Key(String),
+    /// 数组下标.
+    Index(usize),
+}
+
+/// 路径的写法, 用于按原写法输出出错位置.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Syntax {
+    Pointer,
+    Dotted,
+}
+
+/// 解析后的 JSON 路径.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JsonPath {
+    syntax: Syntax,
+    segments: Vec<Segment>,
+}
+
+impl JsonPath {
+    /// 解析路径字符串.
+    ///
+    /// # 参数
+    /// - `path`: JSON Pointer 或点号路径.
+    ///
+    /// # 返回
+    /// 解析成功返回路径; 如果路径格式错误, 返回错误.
+    pub fn parse(path: &str) -> JsonResult<Self> {
+        if path.is_empty() || path.starts_with('/') {
+            Ok(Self::parse_pointer(path))
+        } else {
+            Self::parse_dotted(path)
+        }
+    }
+
+    /// 路径中的所有段.
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// 按路径查找值.
+    ///
+    /// # 参数
+    /// - `value`: 根节点.
+    ///
+    /// # 返回
+    /// 如果路径上的每一段都存在, 返回目标值的引用;
+    /// 否则返回错误, 错误信息中包含出错位置, 并区分缺失字段和类型错误.
+    /// JSON Pointer 在数组上遇到不是下标的段 (如 `01`, `x`) 时返回路径错误.
+    pub fn lookup<'a>(&self, value: &'a Value) -> JsonResult<&'a Value> {
+        let mut current = value;
+        for (i, segment) in self.segments.iter().enumerate() {
+            let next = match (segment, current) {
+                (Segment::Key(key), Value::Object(map)) => map.get(key),
+                (Segment::Key(key), Value::Array(array)) if self.syntax == Syntax::Pointer => {
+                    array.get(self.index(key)?)
+                }
+                (Segment::Index(index), Value::Array(array)) => array.get(*index),
+                (Segment::Key(_), other) => {
+                    return Err(JsonError::mismatch(self.render(i), "对象", other));
+                }
+                (Segment::Index(_), other) => {
+                    return Err(JsonError::mismatch(self.render(i), "数组", other));
+                }
+            };
+            current = next.ok_or_else(|| JsonError::missing(self.render(i + 1)))?;
+        }
+        Ok(current)
+    }
+
+    /// 按路径查找值, 返回可变引用. 出错规则与 [`JsonPath::lookup`] 相同.
+    ///
+    /// # 参数
+    /// - `value`: 根节点.
+    ///
+    /// # 返回
+    /// 如果路径上的每一段都存在, 返回目标值的可变引用; 否则返回错误.
+    pub fn lookup_mut<'a>(&self, value: &'a mut Value) -> JsonResult<&'a mut Value> {
+        let mut current = value;
+        for (i, segment) in self.segments.iter().enumerate() {
+            // 可变借用不能在匹配守卫中使用, 先算出数组下标.
+            let index = match segment {
+                Segment::Key(key) if self.syntax == Syntax::Pointer && current.is_array() => {
+                    Some(self.index(key)?)
+                }
+                Segment::Key(_) => None,
+                Segment::Index(index) => Some(*index),
+            };
+            let next = match (segment, index, current) {
+                (_, Some(index), Value::Array(array)) => array.get_mut(index),
+                (Segment::Key(key), _, Value::Object(map)) => map.get_mut(key),
+                (Segment::Key(_), _, other) => {
+                    return Err(JsonError::mismatch(self.render(i), "对象", other));
+                }
+                (Segment::Index(_), _, other) => {
+                    return Err(JsonError::mismatch(self.render(i), "数组", other));
+                }
+            };
+            current = next.ok_or_else(|| JsonError::missing(self.render(i + 1)))?;
+        }
+        Ok(current)
+    }
+
+    /// 解析 JSON Pointer, 不接受点号路径. JSON Patch 等标准格式只允许 JSON Pointer.
+    pub(crate) fn pointer(path: &str) -> JsonResult<Self> {
+        if path.is_empty() || path.starts_with('/') {
+            Ok(Self::parse_pointer(path))
+        } else {
+            Err(JsonError::InvalidPath {
+                path: path.to_owned(),
+                reason: "应为 JSON Pointer",
+            })
+        }
+    }
+
+    /// 拆分为父路径和最后一段, 根路径返回 `None`.
+    pub(crate) fn split_last(&self) -> Option<(Self, &Segment)> {
+        let (last, parent) = self.segments.split_last()?;
+        let parent = Self {
+            syntax: self.syntax,
+            segments: parent.to_vec(),
+        };
+        Some((parent, last))
+    }
+
+    /// 判断 `self` 是否为 `other` 的真前缀.
+    pub(crate) fn is_proper_prefix_of(&self, other: &Self) -> bool {
+        self.segments.len() < other.segments.len() && other.segments.starts_with(&self.segments)
+    }
+
+    /// JSON Pointer 在数组上的一段, 按下标解析.
+    fn index(&self, token: &str) -> JsonResult<usize> {
+        parse_index(token).ok_or_else(|| JsonError::InvalidPath {
+            path: self.to_string(),
+            reason: "数组下标应为非负整数, 且不能有前导零",
+        })
+    }
+
+    /// 按原写法输出前 `len` 段.
+    pub(crate) fn render(&self, len: usize) -> String {
+        let segments = &self.segments[..len];
+        match self.syntax {
+            Syntax::Pointer => segments
+                .iter()
+                .map(|s| match s {
+                    Segment::Key(key) => format!("/{}", key.replace('~', "~0").replace('/', "~1")),
+                    Segment::Index(index) => format!("/{index}"),
+                })
+                .collect(),
+            Syntax::Dotted => {
+                let mut out = String::new();
+                for segment in segments {
+                    match segment {
+                        Segment::Key(key) => {
+                            if !out.is_empty() {
+                                out.push('.');
+                            }
+                            out.push_str(key);
+                        }
+                        Segment::Index(index) => out.push_str(&format!("[{index}]")),
+                    }
+                }
+                out
+            }
+        }
+    }
+
+    fn parse_pointer(path: &str) -> Self {
+        let segments = path
+            .split('/')
+            .skip(1)
+            .map(|token| Segment::Key(token.replace("~1", "/").replace("~0", "~")))
+            .collect();
+        Self {
+            syntax: Syntax::Pointer,
+            segments,
+        }
+    }
+
+    fn parse_dotted(path: &str) -> JsonResult<Self> {
+        let mut segments = Vec::new();
+        let mut chars = path.chars().peekable();
+        let mut key = String::new();
+        // 上一段是否以 `]` 结束, 此时后面只能跟 `.` 或 `[`.
+        let mut after_index = false;
+        let invalid = |reason| JsonError::InvalidPath {
+            path: path.to_owned(),
+            reason,
+        };
+
+        while let Some(c) = chars.next() {
+            match c {
+                '.' => {
+                    if key.is_empty() && !after_index {
+                        return Err(invalid("存在空字段名"));
+                    }
+                    if !key.is_empty() {
+                        segments.push(Segment::Key(std::mem::take(&mut key)));
+                    }
+                    after_index = false;
+                    if chars.peek().is_none() {
+                        return Err(invalid("不能以 `.` 结尾"));
+                    }
+                }
+                '[' => {
+                    // `[` 前应为字段名或者上一个下标, 不能是开头或 `.`
+                    if !key.is_empty() {
+                        segments.push(Segment::Key(std::mem::take(&mut key)));
+                    } else if !after_index {
+                        return Err(invalid("下标前缺少字段名"));
+                    }
+                    let mut digits = String::new();
+                    loop {
+                        match chars.next() {
+                            Some(']') => break,
+                            Some(d) if d.is_ascii_digit() => digits.push(d),
+                            _ => return Err(invalid("下标应为 `[数字]`")),
+                        }
+                    }
+                    let index = digits.parse().map_err(|_| invalid("下标应为 `[数字]`"))?;
+                    segments.push(Segment::Index(index));
+                    after_index = true;
+                }
+                ']' => return Err(invalid("多余的 `]`")),
+                c => {
+                    if after_index {
+                        return Err(invalid("`]` 后应为 `.` 或 `[`"));
+                    }
+                    key.push(c);
+                }
+            }
+        }
+        if !key.is_empty() {
+            segments.push(Segment::Key(key));
+        }
+
+        Ok(Self {
+            syntax: Syntax::Dotted,
+            segments,
+        })
+    }
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.render(self.segments.len()))
+    }
+}
+
+/// 按 RFC 6901 解析数组下标, 不允许前导零.
+pub(crate) fn parse_index(token: &str) -> Option<usize> {
+    if token.is_empty() || (token.len() > 1 && token.starts_with('0')) {
+        return None;
+    }
+    if !token.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    token.parse().ok()
+}
+
+/// 返回 JSON 值类型的名称, 用于错误信息.
+pub(crate) fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "空值",
+        Value::Bool(_) => "布尔值",
+        Value::Number(_) => "数字",
+        Value::String(_) => "字符串",
+        Value::Array(_) => "数组",
+        Value::Object(_) => "对象",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::Extract;
+    use serde_json::json;
+
+    fn doc() -> Value {
+        json!({
+            "payload": {
+                "device": {
+                    "sensors": [{"value": 1}, {"value": 2}, {"value": 3.5}],
+                    "a/b": {"m~n": true}
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn pointer_and_dotted_paths_find_same_value() {
+        let doc = doc();
+        let pointer = JsonPath::parse("/payload/device/sensors/2/value").unwrap();
+        let dotted = JsonPath::parse("payload.device.sensors[2].value").unwrap();
+        assert_eq!(pointer.lookup(&doc).unwrap(), &json!(3.5));
+        assert_eq!(dotted.lookup(&doc).unwrap(), &json!(3.5));
+        assert_eq!(JsonPath::parse("").unwrap().lookup(&doc).unwrap(), &doc);
+    }
+
+    #[test]
+    fn pointer_unescapes_tokens() {
+        let path = JsonPath::parse("/payload/device/a~1b/m~0n").unwrap();
+        assert_eq!(path.lookup(&doc()).unwrap(), &json!(true));
+        assert_eq!(path.to_string(), "/payload/device/a~1b/m~0n");
+    }
+
+    #[test]
+    fn errors_keep_original_syntax() {
+        let doc = doc();
+        let err = JsonPath::parse("payload.device.sensors[5].value")
+            .unwrap()
+            .lookup(&doc)
+            .unwrap_err();
+        assert_eq!(err, JsonError::missing("payload.device.sensors[5]"));
+
+        let err = JsonPath::parse("/payload/device/sensors/x")
+            .unwrap()
+            .lookup(&doc)
+            .unwrap_err();
+        assert!(
+            matches!(&err, JsonError::InvalidPath { path, .. } if path == "/payload/device/sensors/x"),
+            "{err:?}"
+        );
+
+        let err = JsonPath::parse("payload.device.sensors.x")
+            .unwrap()
+            .lookup(&doc)
+            .unwrap_err();
+        assert!(
+            matches!(&err, JsonError::TypeMismatch { path, expected: "对象", found: "数组" } if path == "payload.device.sensors"),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn pointer_rejects_leading_zero_index() {
+        let mut doc = doc();
+        let path = JsonPath::parse("/payload/device/sensors/01/value").unwrap();
+        let expected = JsonError::InvalidPath {
+            path: "/payload/device/sensors/01/value".to_owned(),
+            reason: "数组下标应为非负整数, 且不能有前导零",
+        };
+        assert_eq!(path.lookup(&doc), Err(expected.clone()));
+        assert_eq!(path.lookup_mut(&mut doc).map(|_| ()), Err(expected));
+        // 下标格式正确但越界时是缺失
+        assert_eq!(
+            JsonPath::parse("/payload/device/sensors/10")
+                .unwrap()
+                .lookup(&doc),
+            Err(JsonError::missing("/payload/device/sensors/10"))
+        );
+        assert_eq!(parse_index("0"), Some(0));
+        assert_eq!(parse_index("10"), Some(10));
+        assert_eq!(parse_index("-1"), None);
+    }
+
+    #[test]
+    fn invalid_dotted_paths() {
+        for path in [
+            "a..b", "a.", "[0]", "a[x]", "a]", "a[0]b", "a[0", "a.[0]", "a[0].[1]",
+        ] {
+            assert!(
+                matches!(JsonPath::parse(path), Err(JsonError::InvalidPath { .. })),
+                "{path}"
+            );
+        }
+        assert_eq!(
+            JsonPath::parse("a[0][1].b").unwrap().segments(),
+            [
+                Segment::Key("a".to_owned()),
+                Segment::Index(0),
+                Segment::Index(1),
+                Segment::Key("b".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn lookup_mut_updates_value() {
+        let mut doc = doc();
+        *JsonPath::parse("/payload/device/sensors/0/value")
+            .unwrap()
+            .lookup_mut(&mut doc)
+            .unwrap() = json!(10);
+        assert_eq!(
+            Extract::get_u64_at(&doc, "payload.device.sensors[0].value"),
+            Ok(10)
+        );
+    }
+}
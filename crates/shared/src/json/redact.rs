@@ -0,0 +1,338 @@
+//! 敏感字段脱敏
+//!
+//! 在记录日志前, 将密码、令牌等字段替换为掩码. 支持两种规则:
+//! - 字段名: 不区分大小写, 并忽略 `_` 和 `-`, 如 `password` 同时匹配 `passWord` 和 `pass_word`.
+//! - 路径: 点号路径或 JSON Pointer, `*` 匹配任意一段, 如 `device.credentials.*` 或 `/users/*/pin`.
+//!
+//! 使用例子:
+//!
+//! ```ignore
+//! use internal_shared::json::Redactor;
+//!
+//! log::debug!("请求体: {}", Redactor::global().display(&body));
+//! ```
+
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::OnceLock;
+
+/// 默认脱敏的字段名.
+const DEFAULT_KEYS: &[&str] = &[
+    "password",
+    "passwd",
+    "pwd",
+    "secret",
+    "client_secret",
+    "token",
+    "access_token",
+    "refresh_token",
+    "authorization",
+    "api_key",
+    "private_key",
+];
+
+/// 默认掩码.
+const DEFAULT_MASK: &str = "******";
+
+static GLOBAL: OnceLock<Redactor> = OnceLock::new();
+
+/// 脱敏规则.
+#[derive(Debug, Clone)]
+pub struct Redactor {
+    keys: HashSet<String>,
+    paths: Vec<Vec<String>>,
+    mask: String,
+}
+
+/// 序列化时脱敏的包装, 通过 [`Redactor::display`] 创建.
+///
+/// 实现了 [`fmt::Display`], 输出脱敏后的紧凑 JSON.
+pub struct Redacted<'a, T: ?Sized> {
+    redactor: &'a Redactor,
+    value: &'a T,
+}
+
+impl Default for Redactor {
+    /// 包含常见敏感字段名 (`password`, `token`, `secret` 等) 的规则.
+    fn default() -> Self {
+        Self::empty().with_keys(DEFAULT_KEYS.iter().copied())
+    }
+}
+
+impl Redactor {
+    /// 创建不包含任何规则的脱敏器.
+    pub fn empty() -> Self {
+        Self {
+            keys: HashSet::new(),
+            paths: Vec::new(),
+            mask: DEFAULT_MASK.to_owned(),
+        }
+    }
+
+    /// 添加需要脱敏的字段名.
+    #[must_use]
+    pub fn with_keys<I, S>(mut self, keys: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.keys
+            .extend(keys.into_iter().map(|k| normalize(k.as_ref())));
+        self
+    }
+
+    /// 添加需要脱敏的路径, 点号路径或 JSON Pointer, `*` 匹配任意一段.
+    #[must_use]
+    pub fn with_paths<I, S>(mut self, paths: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.paths
+            .extend(paths.into_iter().map(|p| parse_pattern(p.as_ref())));
+        self
+    }
+
+    /// 设置掩码, 默认为 `******`.
+    #[must_use]
+    pub fn with_mask(mut self, mask: impl Into<String>) -> Self {
+        self.mask = mask.into();
+        self
+    }
+
+    /// 设置全局脱敏规则, 只能设置一次, 应在启动时调用.
+    ///
+    /// # Errors
+    ///
+    /// 已经设置过 (或已经通过 [`Redactor::global`] 使用了默认规则) 时, 返回传入的规则.
+    pub fn set_global(redactor: Self) -> Result<(), Self> {
+        GLOBAL.set(redactor)
+    }
+
+    /// 全局脱敏规则, 未设置时使用 [`Redactor::default`].
+    pub fn global() -> &'static Self {
+        GLOBAL.get_or_init(Self::default)
+    }
+
+    /// 返回脱敏后的副本.
+    pub fn redact(&self, value: &Value) -> Value {
+        let mut value = value.clone();
+        self.redact_in_place(&mut value);
+        value
+    }
+
+    /// 原地脱敏.
+    pub fn redact_in_place(&self, value: &mut Value) {
+        let mut path = Vec::new();
+        self.walk(value, &mut path);
+    }
+
+    /// 包装任意可序列化的值, 输出时先序列化再脱敏.
+    pub fn display<'a, T: Serialize + ?Sized>(&'a self, value: &'a T) -> Redacted<'a, T> {
+        Redacted {
+            redactor: self,
+            value,
+        }
+    }
+
+    /// 将原始字节按 JSON 解析并脱敏后输出, 用于记录 MQTT 或 HTTP 消息体.
+    ///
+    /// 不是合法的 JSON 时无法判断其中是否有敏感内容, 只输出长度.
+    pub fn redact_bytes(&self, bytes: &[u8]) -> String {
+        match serde_json::from_slice::<Value>(bytes) {
+            Ok(mut value) => {
+                self.redact_in_place(&mut value);
+                value.to_string()
+            }
+            Err(_) => format!("<{} 字节非 JSON 数据>", bytes.len()),
+        }
+    }
+
+    fn walk(&self, value: &mut Value, path: &mut Vec<String>) {
+        if self.matches_path(path) {
+            *value = Value::String(self.mask.clone());
+            return;
+        }
+        match value {
+            Value::Object(map) => {
+                for (key, child) in map.iter_mut() {
+                    if self.keys.contains(&normalize(key)) {
+                        *child = Value::String(self.mask.clone());
+                        continue;
+                    }
+                    path.push(key.clone());
+                    self.walk(child, path);
+                    path.pop();
+                }
+            }
+            Value::Array(items) => {
+                for (i, child) in items.iter_mut().enumerate() {
+                    path.push(i.to_string());
+                    self.walk(child, path);
+                    path.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn matches_path(&self, path: &[String]) -> bool {
+        self.paths.iter().any(|pattern| {
+            pattern.len() == path.len() && pattern.iter().zip(path).all(|(p, s)| p == "*" || p == s)
+        })
+    }
+}
+
+impl<T: Serialize + ?Sized> fmt::Display for Redacted<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match serde_json::to_value(self.value) {
+            Ok(mut value) => {
+                self.redactor.redact_in_place(&mut value);
+                write!(f, "{value}")
+            }
+            Err(e) => write!(f, "<序列化失败: {e}>"),
+        }
+    }
+}
+
+/// 统一字段名: 转为小写, 去掉 `_` 和 `-`.
+fn normalize(key: &str) -> String {
+    key.chars()
+        .filter(|c| *c != '_' && *c != '-')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// 将路径规则拆分为段, `a.b[0]` 和 `/a/b/0` 得到相同的结果.
+fn parse_pattern(pattern: &str) -> Vec<String> {
+    if let Some(pointer) = pattern.strip_prefix('/') {
+        return pointer
+            .split('/')
+            .map(|t| t.replace("~1", "/").replace("~0", "~"))
+            .collect();
+    }
+    pattern
+        .split('.')
+        .flat_map(|part| part.split(['[', ']']))
+        .filter(|s| !s.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn default_keys_are_masked_at_any_depth() {
+        let value = json!({
+            "user": "alice",
+            "password": "p",
+            "nested": {"access_token": "t", "list": [{"api_key": "k", "id": 1}]}
+        });
+        assert_eq!(
+            Redactor::default().redact(&value),
+            json!({
+                "user": "alice",
+                "password": "******",
+                "nested": {"access_token": "******", "list": [{"api_key": "******", "id": 1}]}
+            })
+        );
+    }
+
+    #[test]
+    fn keys_ignore_case_underscore_and_dash() {
+        let value = json!({"PassWord": 1, "pass_word": 2, "Client-Secret": 3, "passwords": 4});
+        assert_eq!(
+            Redactor::default().redact(&value),
+            json!({"PassWord": "******", "pass_word": "******", "Client-Secret": "******", "passwords": 4})
+        );
+    }
+
+    #[test]
+    fn custom_keys_paths_and_mask() {
+        let redactor = Redactor::empty()
+            .with_keys(["Serial-No"])
+            .with_paths(["device.location", "/meta/a~1b"])
+            .with_mask("x");
+        let value = json!({
+            "serial_no": "s",
+            "password": "p",
+            "device": {"location": {"lat": 1}, "name": "d"},
+            "meta": {"a/b": 1, "a": 2}
+        });
+        assert_eq!(
+            redactor.redact(&value),
+            json!({
+                "serial_no": "x",
+                "password": "p",
+                "device": {"location": "x", "name": "d"},
+                "meta": {"a/b": "x", "a": 2}
+            })
+        );
+    }
+
+    #[test]
+    fn wildcard_matches_one_segment_including_array_indexes() {
+        let value = json!({
+            "users": [{"pin": 1, "name": "a"}, {"pin": 2}],
+            "device": {"credentials": {"user": "u", "key": "k"}, "id": 1},
+            "groups": [[1, 2]]
+        });
+        let expected = json!({
+            "users": [{"pin": "******", "name": "a"}, {"pin": "******"}],
+            "device": {"credentials": {"user": "******", "key": "******"}, "id": 1},
+            "groups": ["******"]
+        });
+        let dotted =
+            Redactor::empty().with_paths(["users[*].pin", "device.credentials.*", "groups.*"]);
+        let pointer =
+            Redactor::empty().with_paths(["/users/*/pin", "/device/credentials/*", "/groups/*"]);
+        assert_eq!(dotted.redact(&value), expected);
+        assert_eq!(pointer.redact(&value), expected);
+        // `*` 只匹配一段, 不匹配更深的路径
+        let shallow = Redactor::empty().with_paths(["*.pin"]);
+        assert_eq!(shallow.redact(&value), value);
+    }
+
+    #[test]
+    fn redact_bytes_hides_non_json() {
+        let redactor = Redactor::default();
+        assert_eq!(
+            redactor.redact_bytes(br#"{"token":"t","n":1}"#),
+            r#"{"n":1,"token":"******"}"#
+        );
+        assert_eq!(
+            redactor.redact_bytes(b"password=p"),
+            "<10 字节非 JSON 数据>"
+        );
+        assert_eq!(
+            redactor.redact_bytes(&[0xff, 0xfe, b'{']),
+            "<3 字节非 JSON 数据>"
+        );
+    }
+
+    #[test]
+    fn redact_in_place_and_display() {
+        let mut value = json!({"a": {"b": {"secret": {"deep": 1}, "c": [1]}}});
+        Redactor::default().redact_in_place(&mut value);
+        assert_eq!(value, json!({"a": {"b": {"secret": "******", "c": [1]}}}));
+
+        #[derive(Serialize)]
+        struct Login<'a> {
+            user: &'a str,
+            password: &'a str,
+        }
+        let login = Login {
+            user: "u",
+            password: "p",
+        };
+        assert_eq!(
+            Redactor::default().display(&login).to_string(),
+            r#"{"password":"******","user":"u"}"#
+        );
+    }
+}
@@ -0,0 +1,640 @@
+//! JSON Schema 校验
+//!
+//! 支持 draft 2020-12 的一个子集:
+//! - 通用: `type`, `enum`, `const`
+//! - 数字: `minimum`, `maximum`, `exclusiveMinimum`, `exclusiveMaximum`
+//! - 字符串: `minLength`, `maxLength`, `pattern`
+//! - 数组: `items`, `minItems`, `maxItems`
+//! - 对象: `properties`, `required`, `additionalProperties`
+//!
+//! 注解关键字 (`title`, `description`, `$schema`, `$id`, `$comment`, `default`, `examples` 等) 会被忽略;
+//! 其他不支持的关键字 (如 `$ref`, `allOf`, `patternProperties`) 在编译时报错, 以免校验被静默跳过.
+//!
+//! `enum` 和 `const` 比较数字时按数值比较, `1` 和 `1.0` 相等.
+//!
+//! 使用例子:
+//!
+//! ```ignore
+//! use internal_shared::json::JsonSchema;
+//! use serde_json::json;
+//!
+//! let schema = JsonSchema::compile(&json!({
+//!     "type": "object",
+//!     "required": ["id"],
+//!     "properties": {"id": {"type": "integer", "minimum": 1}}
+//! }))
+//! .unwrap();
+//!
+//! let err = schema.validate(&json!({"id": 0})).unwrap_err();
+//! assert_eq!(err.violations[0].path, "/id");
+//! ```
+
+use super::error::{JsonError, JsonResult};
+use regex::Regex;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use thiserror::Error;
+
+/// 编译后的 JSON Schema.
+#[derive(Debug, Clone)]
+pub struct JsonSchema {
+    root: Schema,
+}
+
+/// 一处校验失败.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Violation {
+    /// 出错位置的 JSON Pointer, 根节点为空字符串.
+    pub path: String,
+    /// 错误说明.
+    pub message: String,
+}
+
+/// 校验失败, 包含所有违反约束的位置.
+#[derive(Debug, Clone, PartialEq, Eq, Error, Serialize)]
+#[error("数据校验失败: {}", summary(violations))]
+pub struct ValidationError {
+    /// 所有违反约束的位置.
+    pub violations: Vec<Violation>,
+}
+
+#[derive(Debug, Clone)]
+enum Schema {
+    /// `true` 接受任意值, `false` 拒绝任意值.
+    Bool(bool),
+    Node(Box<Node>),
+}
+
+#[derive(Debug, Clone, Default)]
+struct Node {
+    types: Option<Vec<Type>>,
+    enumeration: Option<Vec<Value>>,
+    constant: Option<Value>,
+    minimum: Option<f64>,
+    maximum: Option<f64>,
+    exclusive_minimum: Option<f64>,
+    exclusive_maximum: Option<f64>,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    pattern: Option<Regex>,
+    items: Option<Schema>,
+    min_items: Option<usize>,
+    max_items: Option<usize>,
+    properties: BTreeMap<String, Schema>,
+    required: Vec<String>,
+    additional_properties: Option<Schema>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Null,
+    Boolean,
+    Integer,
+    Number,
+    String,
+    Array,
+    Object,
+}
+
+impl JsonSchema {
+    /// 编译 JSON Schema.
+    ///
+    /// # 参数
+    /// - `schema`: Schema 文档.
+    ///
+    /// # 返回
+    /// 编译成功返回 [JsonSchema]; 如果关键字的值不合法 (如 `pattern` 不是合法的正则表达式),
+    /// 返回 [`JsonError::InvalidSchema`].
+    pub fn compile(schema: &Value) -> JsonResult<Self> {
+        Ok(Self {
+            root: Schema::compile(schema, "")?,
+        })
+    }
+
+    /// 从 JSON 文件加载并编译 Schema.
+    ///
+    /// # Errors
+    ///
+    /// 文件读取失败、JSON 解析失败或 Schema 不合法时返回错误.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let schema: Value = serde_json::from_str(&text)?;
+        Ok(Self::compile(&schema)?)
+    }
+
+    /// 校验 JSON 值.
+    ///
+    /// # 参数
+    /// - `value`: 要校验的值.
+    ///
+    /// # 返回
+    /// 全部符合返回 `Ok(())`; 否则返回所有违反约束的位置.
+    pub fn validate(&self, value: &Value) -> Result<(), ValidationError> {
+        let mut violations = Vec::new();
+        self.root.validate(value, "", &mut violations);
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError { violations })
+        }
+    }
+}
+
+impl Schema {
+    fn compile(schema: &Value, path: &str) -> JsonResult<Self> {
+        match schema {
+            Value::Bool(b) => Ok(Self::Bool(*b)),
+            Value::Object(map) => Ok(Self::Node(Box::new(Node::compile(map, path)?))),
+            _ => Err(invalid(path, "Schema 应为对象或布尔值")),
+        }
+    }
+
+    fn validate(&self, value: &Value, path: &str, out: &mut Vec<Violation>) {
+        match self {
+            Self::Bool(true) => {}
+            Self::Bool(false) => push(out, path, "不允许出现该值.".to_owned()),
+            Self::Node(node) => node.validate(value, path, out),
+        }
+    }
+}
+
+impl Node {
+    fn compile(map: &Map<String, Value>, path: &str) -> JsonResult<Self> {
+        let mut node = Self::default();
+        for (keyword, value) in map {
+            let at = format!("{path}/{}", escape(keyword));
+            match keyword.as_str() {
+                "type" => node.types = Some(compile_types(value, &at)?),
+                "enum" => {
+                    let values = value
+                        .as_array()
+                        .ok_or_else(|| invalid(&at, "`enum` 应为数组"))?;
+                    node.enumeration = Some(values.clone());
+                }
+                "const" => node.constant = Some(value.clone()),
+                "minimum" => node.minimum = Some(number(value, &at)?),
+                "maximum" => node.maximum = Some(number(value, &at)?),
+                "exclusiveMinimum" => node.exclusive_minimum = Some(number(value, &at)?),
+                "exclusiveMaximum" => node.exclusive_maximum = Some(number(value, &at)?),
+                "minLength" => node.min_length = Some(count(value, &at)?),
+                "maxLength" => node.max_length = Some(count(value, &at)?),
+                "pattern" => {
+                    let pattern = value
+                        .as_str()
+                        .ok_or_else(|| invalid(&at, "`pattern` 应为字符串"))?;
+                    let regex = Regex::new(pattern).map_err(|e| invalid(&at, e))?;
+                    node.pattern = Some(regex);
+                }
+                "items" => node.items = Some(Schema::compile(value, &at)?),
+                "minItems" => node.min_items = Some(count(value, &at)?),
+                "maxItems" => node.max_items = Some(count(value, &at)?),
+                "properties" => {
+                    let properties = value
+                        .as_object()
+                        .ok_or_else(|| invalid(&at, "`properties` 应为对象"))?;
+                    for (name, schema) in properties {
+                        let at = format!("{at}/{}", escape(name));
+                        node.properties
+                            .insert(name.clone(), Schema::compile(schema, &at)?);
+                    }
+                }
+                "required" => {
+                    let names = value
+                        .as_array()
+                        .ok_or_else(|| invalid(&at, "`required` 应为字符串数组"))?;
+                    for name in names {
+                        let name = name
+                            .as_str()
+                            .ok_or_else(|| invalid(&at, "`required` 应为字符串数组"))?;
+                        node.required.push(name.to_owned());
+                    }
+                }
+                "additionalProperties" => {
+                    node.additional_properties = Some(Schema::compile(value, &at)?);
+                }
+                keyword if ANNOTATIONS.contains(&keyword) => {}
+                _ => return Err(invalid(&at, format!("不支持的关键字 `{keyword}`"))),
+            }
+        }
+        Ok(node)
+    }
+
+    fn validate(&self, value: &Value, path: &str, out: &mut Vec<Violation>) {
+        if let Some(types) = &self.types
+            && !types.iter().any(|t| t.matches(value))
+        {
+            let expected: Vec<_> = types.iter().map(|t| t.name()).collect();
+            let message = format!(
+                "类型错误, 应为 {}, 实际为 {}.",
+                expected.join(" 或 "),
+                Type::of(value).name()
+            );
+            push(out, path, message);
+            return;
+        }
+
+        if let Some(values) = &self.enumeration
+            && !values.iter().any(|v| json_eq(v, value))
+        {
+            let allowed: Vec<_> = values.iter().map(Value::to_string).collect();
+            let message = format!("值 {value} 不在允许的范围 [{}] 内.", allowed.join(", "));
+            push(out, path, message);
+        }
+        if let Some(constant) = &self.constant
+            && !json_eq(constant, value)
+        {
+            push(out, path, format!("值 {value} 应等于 {constant}."));
+        }
+
+        match value {
+            Value::Number(n) => self.validate_number(n.as_f64().unwrap_or(f64::NAN), path, out),
+            Value::String(s) => self.validate_string(s, path, out),
+            Value::Array(items) => self.validate_array(items, path, out),
+            Value::Object(map) => self.validate_object(map, path, out),
+            _ => {}
+        }
+    }
+
+    fn validate_number(&self, n: f64, path: &str, out: &mut Vec<Violation>) {
+        if let Some(min) = self.minimum
+            && n < min
+        {
+            push(out, path, format!("值 {n} 小于最小值 {min}."));
+        }
+        if let Some(max) = self.maximum
+            && n > max
+        {
+            push(out, path, format!("值 {n} 大于最大值 {max}."));
+        }
+        if let Some(min) = self.exclusive_minimum
+            && n <= min
+        {
+            push(out, path, format!("值 {n} 应大于 {min}."));
+        }
+        if let Some(max) = self.exclusive_maximum
+            && n >= max
+        {
+            push(out, path, format!("值 {n} 应小于 {max}."));
+        }
+    }
+
+    fn validate_string(&self, s: &str, path: &str, out: &mut Vec<Violation>) {
+        let len = s.chars().count();
+        if let Some(min) = self.min_length
+            && len < min
+        {
+            push(out, path, format!("长度 {len} 小于最小长度 {min}."));
+        }
+        if let Some(max) = self.max_length
+            && len > max
+        {
+            push(out, path, format!("长度 {len} 大于最大长度 {max}."));
+        }
+        if let Some(pattern) = &self.pattern
+            && !pattern.is_match(s)
+        {
+            push(out, path, format!("不匹配正则表达式 {}.", pattern.as_str()));
+        }
+    }
+
+    fn validate_array(&self, items: &[Value], path: &str, out: &mut Vec<Violation>) {
+        if let Some(min) = self.min_items
+            && items.len() < min
+        {
+            push(out, path, format!("元素个数 {} 少于 {min}.", items.len()));
+        }
+        if let Some(max) = self.max_items
+            && items.len() > max
+        {
+            push(out, path, format!("元素个数 {} 多于 {max}.", items.len()));
+        }
+        if let Some(schema) = &self.items {
+            for (i, item) in items.iter().enumerate() {
+                schema.validate(item, &format!("{path}/{i}"), out);
+            }
+        }
+    }
+
+    fn validate_object(&self, map: &Map<String, Value>, path: &str, out: &mut Vec<Violation>) {
+        for name in &self.required {
+            if !map.contains_key(name) {
+                push(
+                    out,
+                    &format!("{path}/{}", escape(name)),
+                    "缺失必要字段.".to_owned(),
+                );
+            }
+        }
+        for (name, value) in map {
+            let at = format!("{path}/{}", escape(name));
+            match (self.properties.get(name), &self.additional_properties) {
+                (Some(schema), _) => schema.validate(value, &at, out),
+                (None, Some(Schema::Bool(false))) => push(out, &at, "不允许的字段.".to_owned()),
+                (None, Some(schema)) => schema.validate(value, &at, out),
+                (None, None) => {}
+            }
+        }
+    }
+}
+
+impl Type {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "null" => Self::Null,
+            "boolean" => Self::Boolean,
+            "integer" => Self::Integer,
+            "number" => Self::Number,
+            "string" => Self::String,
+            "array" => Self::Array,
+            "object" => Self::Object,
+            _ => return None,
+        })
+    }
+
+    fn of(value: &Value) -> Self {
+        match value {
+            Value::Null => Self::Null,
+            Value::Bool(_) => Self::Boolean,
+            Value::Number(_) if Self::Integer.matches(value) => Self::Integer,
+            Value::Number(_) => Self::Number,
+            Value::String(_) => Self::String,
+            Value::Array(_) => Self::Array,
+            Value::Object(_) => Self::Object,
+        }
+    }
+
+    fn matches(self, value: &Value) -> bool {
+        match (self, value) {
+            (Self::Null, Value::Null)
+            | (Self::Boolean, Value::Bool(_))
+            | (Self::Number, Value::Number(_))
+            | (Self::String, Value::String(_))
+            | (Self::Array, Value::Array(_))
+            | (Self::Object, Value::Object(_)) => true,
+            (Self::Integer, Value::Number(n)) => {
+                n.is_i64() || n.is_u64() || n.as_f64().is_some_and(|f| f.fract() == 0.0)
+            }
+            _ => false,
+        }
+    }
+
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Null => "null",
+            Self::Boolean => "boolean",
+            Self::Integer => "integer",
+            Self::Number => "number",
+            Self::String => "string",
+            Self::Array => "array",
+            Self::Object => "object",
+        }
+    }
+}
+
+/// 只起说明作用、不影响校验的关键字.
+const ANNOTATIONS: &[&str] = &[
+    "title",
+    "description",
+    "$schema",
+    "$id",
+    "$comment",
+    "default",
+    "examples",
+    "deprecated",
+    "readOnly",
+    "writeOnly",
+];
+
+/// 比较两个 JSON 值, 数字按数值比较.
+fn json_eq(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => match (x.as_i64(), y.as_i64()) {
+            (Some(x), Some(y)) => x == y,
+            _ => match (x.as_u64(), y.as_u64()) {
+                (Some(x), Some(y)) => x == y,
+                _ => x.as_f64() == y.as_f64(),
+            },
+        },
+        (Value::Array(x), Value::Array(y)) => {
+            x.len() == y.len() && x.iter().zip(y).all(|(x, y)| json_eq(x, y))
+        }
+        (Value::Object(x), Value::Object(y)) => {
+            x.len() == y.len()
+                && x.iter()
+                    .all(|(k, v)| y.get(k).is_some_and(|other| json_eq(v, other)))
+        }
+        _ => a == b,
+    }
+}
+
+fn compile_types(value: &Value, path: &str) -> JsonResult<Vec<Type>> {
+    let parse = |v: &Value| {
+        v.as_str()
+            .and_then(Type::parse)
+            .ok_or_else(|| invalid(path, format!("未知的类型 {v}")))
+    };
+    match value {
+        Value::Array(names) => names.iter().map(parse).collect(),
+        v => Ok(vec![parse(v)?]),
+    }
+}
+
+fn number(value: &Value, path: &str) -> JsonResult<f64> {
+    value.as_f64().ok_or_else(|| invalid(path, "应为数字"))
+}
+
+fn count(value: &Value, path: &str) -> JsonResult<usize> {
+    value
+        .as_u64()
+        .and_then(|n| usize::try_from(n).ok())
+        .ok_or_else(|| invalid(path, "应为非负整数"))
+}
+
+fn invalid(path: &str, reason: impl ToString) -> JsonError {
+    JsonError::InvalidSchema {
+        path: path.to_owned(),
+        reason: reason.to_string(),
+    }
+}
+
+fn push(out: &mut Vec<Violation>, path: &str, message: String) {
+    out.push(Violation {
+        path: path.to_owned(),
+        message,
+    });
+}
+
+/// 按 RFC 6901 转义 JSON Pointer 中的一段.
+fn escape(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+fn summary(violations: &[Violation]) -> String {
+    violations
+        .iter()
+        .map(|v| {
+            let path = if v.path.is_empty() { "/" } else { &v.path };
+            format!("{path} {}", v.message)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> JsonSchema {
+        JsonSchema::compile(&json!({
+            "type": "object",
+            "required": ["id", "name"],
+            "properties": {
+                "id": {"type": "integer", "minimum": 1},
+                "name": {"type": "string", "minLength": 2, "maxLength": 8, "pattern": "^[a-z]+$"},
+                "level": {"enum": ["low", "high"]},
+                "ratio": {"type": "number", "exclusiveMinimum": 0, "exclusiveMaximum": 1},
+                "tags": {"type": "array", "items": {"type": "string"}, "maxItems": 2},
+                "extra": {"type": ["string", "null"]},
+                "a/b": {"const": true}
+            },
+            "additionalProperties": false
+        }))
+        .unwrap()
+    }
+
+    fn paths(value: &Value) -> Vec<String> {
+        let mut paths: Vec<_> = schema()
+            .validate(value)
+            .unwrap_err()
+            .violations
+            .into_iter()
+            .map(|v| v.path)
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn accepts_valid_document() {
+        let value = json!({
+            "id": 1, "name": "abc", "level": "low", "ratio": 0.5,
+            "tags": ["x"], "extra": null, "a/b": true
+        });
+        assert_eq!(schema().validate(&value), Ok(()));
+        // 整数形式的浮点数也算 integer
+        assert_eq!(schema().validate(&json!({"id": 2.0, "name": "ab"})), Ok(()));
+    }
+
+    #[test]
+    fn collects_all_violations() {
+        let value = json!({
+            "id": 0, "name": "ABC", "level": "mid", "ratio": 1,
+            "tags": ["x", 1, "z"], "extra": 3, "a/b": false, "other": 1
+        });
+        assert_eq!(
+            paths(&value),
+            [
+                "/a~1b", "/extra", "/id", "/level", "/name", "/other", "/ratio", "/tags", "/tags/1"
+            ]
+        );
+        assert_eq!(paths(&json!({})), ["/id", "/name"]);
+        assert_eq!(paths(&json!([])), [""]);
+    }
+
+    #[test]
+    fn string_length_counts_chars() {
+        let schema = JsonSchema::compile(&json!({"maxLength": 2})).unwrap();
+        assert_eq!(schema.validate(&json!("温度")), Ok(()));
+        assert!(schema.validate(&json!("温度计")).is_err());
+    }
+
+    #[test]
+    fn boolean_schemas() {
+        assert!(
+            JsonSchema::compile(&json!(true))
+                .unwrap()
+                .validate(&json!(1))
+                .is_ok()
+        );
+        assert!(
+            JsonSchema::compile(&json!(false))
+                .unwrap()
+                .validate(&json!(1))
+                .is_err()
+        );
+        let schema =
+            JsonSchema::compile(&json!({"additionalProperties": {"type": "integer"}})).unwrap();
+        assert!(schema.validate(&json!({"a": 1})).is_ok());
+        assert!(schema.validate(&json!({"a": "x"})).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_schemas() {
+        for (schema, path) in [
+            (json!(1), ""),
+            (json!({"type": "int"}), "/type"),
+            (json!({"pattern": "("}), "/pattern"),
+            (json!({"minLength": -1}), "/minLength"),
+            (
+                json!({"properties": {"a": {"enum": 1}}}),
+                "/properties/a/enum",
+            ),
+            (json!({"$ref": "#/$defs/a"}), "/$ref"),
+            (json!({"items": {"anyOf": []}}), "/items/anyOf"),
+            (json!({"patternProperties": {}}), "/patternProperties"),
+        ] {
+            let err = JsonSchema::compile(&schema).unwrap_err();
+            assert!(
+                matches!(&err, JsonError::InvalidSchema { path: p, .. } if p == path),
+                "{schema}: {err:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn ignores_annotations() {
+        let schema = JsonSchema::compile(&json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "$id": "urn:test",
+            "title": "t",
+            "description": "d",
+            "default": 1,
+            "examples": [1],
+            "type": "integer"
+        }))
+        .unwrap();
+        assert!(schema.validate(&json!(1)).is_ok());
+        assert!(schema.validate(&json!("1")).is_err());
+    }
+
+    #[test]
+    fn enum_and_const_are_both_checked() {
+        let schema = JsonSchema::compile(&json!({"enum": [1, 2, 3], "const": 2})).unwrap();
+        assert!(schema.validate(&json!(2)).is_ok());
+        assert!(schema.validate(&json!(1)).is_err());
+        assert!(schema.validate(&json!(4)).is_err());
+        assert_eq!(schema.validate(&json!(5)).unwrap_err().violations.len(), 2);
+    }
+
+    #[test]
+    fn enum_and_const_compare_numbers_by_value() {
+        let schema = JsonSchema::compile(&json!({"enum": [1, [2], {"a": 3}]})).unwrap();
+        for value in [json!(1.0), json!([2.0]), json!({"a": 3.0})] {
+            assert!(schema.validate(&value).is_ok(), "{value}");
+        }
+        assert!(schema.validate(&json!(1.5)).is_err());
+        let schema = JsonSchema::compile(&json!({"const": 1.0})).unwrap();
+        assert!(schema.validate(&json!(1)).is_ok());
+        assert!(schema.validate(&json!(u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn error_message_lists_violations() {
+        let err = schema().validate(&json!({"id": 1})).unwrap_err();
+        assert_eq!(err.to_string(), "数据校验失败: /name 缺失必要字段.");
+    }
+}
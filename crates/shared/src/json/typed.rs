@@ -0,0 +1,292 @@
+//! 将 JSON 值转换为具体类型, 供 [Convert](super::Convert) 和 [Extract](super::Extract) 共用.
+
+use super::error::{JsonError, JsonResult};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde_json::{Map, Number, Value};
+use std::str::FromStr;
+
+/// 可以从 JSON 值转换得到的类型.
+pub(crate) trait FromJson<'a>: Sized {
+    /// 转换 JSON 值, `path` 只用于生成错误信息.
+    fn from_json(value: &'a Value, path: &str) -> JsonResult<Self>;
+}
+
+impl<'a> FromJson<'a> for &'a Value {
+    fn from_json(value: &'a Value, _path: &str) -> JsonResult<Self> {
+        Ok(value)
+    }
+}
+
+impl<'a> FromJson<'a> for &'a Vec<Value> {
+    fn from_json(value: &'a Value, path: &str) -> JsonResult<Self> {
+        value
+            .as_array()
+            .ok_or_else(|| JsonError::mismatch(path, "数组", value))
+    }
+}
+
+impl<'a> FromJson<'a> for &'a Map<String, Value> {
+    fn from_json(value: &'a Value, path: &str) -> JsonResult<Self> {
+        value
+            .as_object()
+            .ok_or_else(|| JsonError::mismatch(path, "对象", value))
+    }
+}
+
+impl<'a> FromJson<'a> for &'a str {
+    fn from_json(value: &'a Value, path: &str) -> JsonResult<Self> {
+        value
+            .as_str()
+            .ok_or_else(|| JsonError::mismatch(path, "字符串", value))
+    }
+}
+
+impl FromJson<'_> for f64 {
+    fn from_json(value: &Value, path: &str) -> JsonResult<Self> {
+        value
+            .as_f64()
+            .ok_or_else(|| JsonError::mismatch(path, "浮点数", value))
+    }
+}
+
+impl FromJson<'_> for u64 {
+    fn from_json(value: &Value, path: &str) -> JsonResult<Self> {
+        match value {
+            Value::Number(n) if n.is_i64() && !n.is_u64() => Err(out_of_range(path, "u64", n)),
+            _ => value
+                .as_u64()
+                .ok_or_else(|| JsonError::mismatch(path, "无符号整数", value)),
+        }
+    }
+}
+
+impl FromJson<'_> for i64 {
+    fn from_json(value: &Value, path: &str) -> JsonResult<Self> {
+        match value {
+            Value::Number(n) if n.is_u64() && !n.is_i64() => Err(out_of_range(path, "i64", n)),
+            _ => value
+                .as_i64()
+                .ok_or_else(|| JsonError::mismatch(path, "有符号整数", value)),
+        }
+    }
+}
+
+impl FromJson<'_> for bool {
+    fn from_json(value: &Value, path: &str) -> JsonResult<Self> {
+        value
+            .as_bool()
+            .ok_or_else(|| JsonError::mismatch(path, "布尔值", value))
+    }
+}
+
+/// 为窄整数类型实现 [FromJson], 先按 `u64`/`i64` 读取, 再检查范围.
+macro_rules! impl_narrow_int {
+    ($($ty:ty => $wide:ty),* $(,)?) => {$(
+        impl FromJson<'_> for $ty {
+            fn from_json(value: &Value, path: &str) -> JsonResult<Self> {
+                let wide = <$wide>::from_json(value, path).map_err(|e| match e {
+                    JsonError::OutOfRange { path, value, .. } => JsonError::OutOfRange {
+                        path,
+                        target: stringify!($ty),
+                        value,
+                    },
+                    e => e,
+                })?;
+                <$ty>::try_from(wide).map_err(|_| JsonError::OutOfRange {
+                    path: path.to_owned(),
+                    target: stringify!($ty),
+                    value: wide.to_string(),
+                })
+            }
+        }
+    )*};
+}
+
+impl_narrow_int!(u8 => u64, u16 => u64, u32 => u64, i32 => i64);
+
+/// 数字或字符串 (如 `"12.34"`) 都可以转换为 [Decimal].
+///
+/// 数字按其文本表示解析, 不经过 `f64`, 避免精度丢失.
+impl FromJson<'_> for Decimal {
+    fn from_json(value: &Value, path: &str) -> JsonResult<Self> {
+        match value {
+            Value::Number(n) => {
+                let text = n.to_string();
+                Decimal::from_str(&text)
+                    .or_else(|_| Decimal::from_scientific(&text))
+                    .map_err(|_| out_of_range(path, "Decimal", n))
+            }
+            Value::String(s) => Decimal::from_str(s.trim())
+                .or_else(|_| Decimal::from_scientific(s.trim()))
+                .map_err(|_| JsonError::InvalidFormat {
+                    path: path.to_owned(),
+                    expected: "十进制数",
+                    value: s.clone(),
+                }),
+            _ => Err(JsonError::mismatch(path, "数字或字符串", value)),
+        }
+    }
+}
+
+/// 整数按毫秒时间戳解析, 字符串按 RFC3339 解析. 带小数的数字是类型错误.
+impl FromJson<'_> for DateTime<Utc> {
+    fn from_json(value: &Value, path: &str) -> JsonResult<Self> {
+        match value {
+            Value::Number(n) if n.is_f64() => Err(JsonError::TypeMismatch {
+                path: path.to_owned(),
+                expected: "整数毫秒时间戳或 RFC3339 字符串",
+                found: "浮点数",
+            }),
+            Value::Number(n) => {
+                let millis = n
+                    .as_i64()
+                    .ok_or_else(|| out_of_range(path, "毫秒时间戳", n))?;
+                DateTime::from_timestamp_millis(millis)
+                    .ok_or_else(|| out_of_range(path, "毫秒时间戳", n))
+            }
+            Value::String(s) => DateTime::parse_from_rfc3339(s)
+                .map(|t| t.with_timezone(&Utc))
+                .map_err(|_| JsonError::InvalidFormat {
+                    path: path.to_owned(),
+                    expected: "RFC3339 时间",
+                    value: s.clone(),
+                }),
+            _ => Err(JsonError::mismatch(
+                path,
+                "毫秒时间戳或 RFC3339 字符串",
+                value,
+            )),
+        }
+    }
+}
+
+fn out_of_range(path: &str, target: &'static str, n: &Number) -> JsonError {
+    JsonError::OutOfRange {
+        path: path.to_owned(),
+        target,
+        value: n.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn convert<'a, T: FromJson<'a>>(value: &'a Value) -> JsonResult<T> {
+        T::from_json(value, "v")
+    }
+
+    fn out_of_range(target: &'static str, value: &str) -> JsonError {
+        JsonError::OutOfRange {
+            path: "v".to_owned(),
+            target,
+            value: value.to_owned(),
+        }
+    }
+
+    #[test]
+    fn narrow_unsigned_limits() {
+        assert_eq!(convert::<u8>(&json!(255)), Ok(255));
+        assert_eq!(convert::<u8>(&json!(256)), Err(out_of_range("u8", "256")));
+        assert_eq!(convert::<u16>(&json!(65535)), Ok(65535));
+        assert_eq!(
+            convert::<u16>(&json!(65536)),
+            Err(out_of_range("u16", "65536"))
+        );
+        assert_eq!(convert::<u32>(&json!(u32::MAX)), Ok(u32::MAX));
+        // 负数转无符号整数是超出范围, 不是类型错误
+        assert_eq!(convert::<u8>(&json!(-1)), Err(out_of_range("u8", "-1")));
+        assert_eq!(convert::<u64>(&json!(-1)), Err(out_of_range("u64", "-1")));
+        assert_eq!(
+            convert::<u16>(&json!(1.5)),
+            Err(JsonError::mismatch("v", "无符号整数", &json!(1.5)))
+        );
+        assert_eq!(
+            convert::<u8>(&json!("1")),
+            Err(JsonError::mismatch("v", "无符号整数", &json!("1")))
+        );
+    }
+
+    #[test]
+    fn signed_limits() {
+        assert_eq!(convert::<i32>(&json!(i32::MIN)), Ok(i32::MIN));
+        assert_eq!(
+            convert::<i32>(&json!(i64::from(i32::MAX) + 1)),
+            Err(out_of_range("i32", "2147483648"))
+        );
+        assert_eq!(
+            convert::<i64>(&json!(u64::MAX)),
+            Err(out_of_range("i64", "18446744073709551615"))
+        );
+        assert_eq!(
+            convert::<i32>(&json!(u64::MAX)),
+            Err(out_of_range("i32", "18446744073709551615"))
+        );
+    }
+
+    #[test]
+    fn decimal_from_string_and_number() {
+        assert_eq!(
+            convert::<Decimal>(&json!(" 12.34 ")),
+            Ok(Decimal::new(1234, 2))
+        );
+        assert_eq!(convert::<Decimal>(&json!("1e3")), Ok(Decimal::new(1000, 0)));
+        assert_eq!(convert::<Decimal>(&json!(0.1)), Ok(Decimal::new(1, 1)));
+        assert_eq!(convert::<Decimal>(&json!(42)), Ok(Decimal::new(42, 0)));
+        assert_eq!(
+            convert::<Decimal>(&json!("abc")),
+            Err(JsonError::InvalidFormat {
+                path: "v".to_owned(),
+                expected: "十进制数",
+                value: "abc".to_owned(),
+            })
+        );
+        assert!(matches!(
+            convert::<Decimal>(&json!(1e300)),
+            Err(JsonError::OutOfRange { .. })
+        ));
+        assert!(matches!(
+            convert::<Decimal>(&json!(true)),
+            Err(JsonError::TypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn datetime_from_rfc3339_and_epoch_millis() {
+        let expected = DateTime::from_timestamp_millis(1_700_000_000_123).unwrap();
+        assert_eq!(
+            convert::<DateTime<Utc>>(&json!("2023-11-14T22:13:20.123Z")),
+            Ok(expected)
+        );
+        assert_eq!(
+            convert::<DateTime<Utc>>(&json!("2023-11-15T06:13:20.123+08:00")),
+            Ok(expected)
+        );
+        assert_eq!(
+            convert::<DateTime<Utc>>(&json!(1_700_000_000_123_i64)),
+            Ok(expected)
+        );
+        assert!(matches!(
+            convert::<DateTime<Utc>>(&json!("2023-11-14 22:13:20")),
+            Err(JsonError::InvalidFormat { .. })
+        ));
+        assert_eq!(
+            convert::<DateTime<Utc>>(&json!(1.7e12)),
+            Err(JsonError::TypeMismatch {
+                path: "v".to_owned(),
+                expected: "整数毫秒时间戳或 RFC3339 字符串",
+                found: "浮点数",
+            })
+        );
+        assert!(matches!(
+            convert::<DateTime<Utc>>(&json!(i64::MAX)),
+            Err(JsonError::OutOfRange { .. })
+        ));
+        assert!(matches!(
+            convert::<DateTime<Utc>>(&json!(u64::MAX)),
+            Err(JsonError::OutOfRange { .. })
+        ));
+    }
+}
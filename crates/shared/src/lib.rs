@@ -2,7 +2,10 @@
 
 //! 通用工具库 (日志、配置加载等)
 
+pub mod config;
 pub mod flexi_logger;
 pub mod json;
 pub mod reqwest;
+pub mod secret;
+pub mod trace;
 pub mod yaml;
@@ -0,0 +1,490 @@
+//! 请求认证与签名
+//!
+//! [Auth] 在每次发送 (包括重试) 之前修改请求, 调用方不需要关心认证细节. 内置:
+//! - [BearerAuth]: 固定的 Bearer 令牌.
+//! - [BasicAuth]: HTTP Basic 认证.
+//! - [OAuth2ClientCredentials]: OAuth2 客户端凭证模式, 缓存令牌并在过期前刷新.
+//! - [HmacSigner]: HMAC-SHA256 签名, 包含时间戳、随机数和消息体摘要.
+//!
+//! 也可以实现 [Auth] 后通过 [HttpClient::with_auth](super::HttpClient::with_auth) 使用.
+
+use super::hex;
+use anyhow::{Context, Result, bail};
+use async_trait::async_trait;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use rand::distr::Alphanumeric;
+use reqwest::header::{AUTHORIZATION, HeaderName, HeaderValue};
+use reqwest::{Client, Request};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+
+/// 请求认证.
+#[async_trait]
+pub trait Auth: Send + Sync {
+    /// 在发送前修改请求, 如添加请求头或签名. 每次重试都会重新调用.
+    async fn apply(&self, request: &mut Request) -> Result<()>;
+}
+
+/// 认证配置, 通过 `type` 区分.
+///
+/// ```yaml
+/// auth:
+///   type: oauth2_client_credentials
+///   token_url: "https://auth.example.com/oauth/token"
+///   client_id: "demo"
+///   client_secret: "${PARTNER_CLIENT_SECRET}"
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuthOptions {
+    /// 固定的 Bearer 令牌.
+    Bearer {
+        /// 令牌.
+        token: String,
+    },
+    /// HTTP Basic 认证.
+    Basic {
+        /// 用户名.
+        username: String,
+        /// 密码.
+        password: Option<String>,
+    },
+    /// OAuth2 客户端凭证模式.
+    Oauth2ClientCredentials {
+        /// 获取令牌的地址.
+        token_url: String,
+        /// 客户端 ID.
+        client_id: String,
+        /// 客户端密钥.
+        client_secret: String,
+        /// 申请的权限范围.
+        scope: Option<String>,
+        /// 在令牌过期前多少秒刷新, 默认 60.
+        #[serde(default = "default_refresh_before_secs")]
+        refresh_before_secs: u64,
+    },
+    /// HMAC-SHA256 签名.
+    HmacSha256 {
+        /// 密钥 ID, 放在 `X-Key-Id` 请求头中.
+        key_id: String,
+        /// 签名密钥.
+        secret: String,
+    },
+}
+
+const fn default_refresh_before_secs() -> u64 {
+    60
+}
+
+impl AuthOptions {
+    /// 按配置创建认证.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - 获取 OAuth2 令牌时使用的客户端.
+    pub fn build(&self, client: Client) -> Arc<dyn Auth> {
+        match self.clone() {
+            Self::Bearer { token } => Arc::new(BearerAuth::new(token)),
+            Self::Basic { username, password } => Arc::new(BasicAuth::new(username, password)),
+            Self::Oauth2ClientCredentials {
+                token_url,
+                client_id,
+                client_secret,
+                scope,
+                refresh_before_secs,
+            } => Arc::new(
+                OAuth2ClientCredentials::new(client, token_url, client_id, client_secret)
+                    .with_scope(scope)
+                    .with_refresh_before(Duration::from_secs(refresh_before_secs)),
+            ),
+            Self::HmacSha256 { key_id, secret } => Arc::new(HmacSigner::new(key_id, secret)),
+        }
+    }
+}
+
+/// 固定的 Bearer 令牌.
+pub struct BearerAuth {
+    token: String,
+}
+
+impl BearerAuth {
+    /// 使用令牌创建.
+    pub fn new(token: impl Into<String>) -> Self {
+        Self {
+            token: token.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl Auth for BearerAuth {
+    async fn apply(&self, request: &mut Request) -> Result<()> {
+        set_header(request, AUTHORIZATION, &format!("Bearer {}", self.token))
+    }
+}
+
+/// HTTP Basic 认证.
+pub struct BasicAuth {
+    value: String,
+}
+
+impl BasicAuth {
+    /// 使用用户名和密码创建.
+    pub fn new(username: impl Into<String>, password: Option<String>) -> Self {
+        let credentials = format!("{}:{}", username.into(), password.unwrap_or_default());
+        Self {
+            value: format!("Basic {}", STANDARD.encode(credentials)),
+        }
+    }
+}
+
+#[async_trait]
+impl Auth for BasicAuth {
+    async fn apply(&self, request: &mut Request) -> Result<()> {
+        set_header(request, AUTHORIZATION, &self.value)
+    }
+}
+
+/// OAuth2 客户端凭证模式.
+///
+/// 令牌缓存在内存中, 在过期前 `refresh_before` 时刷新. 并发请求只会触发一次刷新.
+pub struct OAuth2ClientCredentials {
+    client: Client,
+    token_url: String,
+    client_id: String,
+    client_secret: String,
+    scope: Option<String>,
+    refresh_before: Duration,
+    token: Mutex<Option<CachedToken>>,
+}
+
+struct CachedToken {
+    access_token: String,
+    expires_at: Instant,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    /// 有效期, 秒. 没有返回时按 1 小时处理.
+    expires_in: Option<u64>,
+}
+
+impl OAuth2ClientCredentials {
+    /// 创建, 令牌通过 `client` 从 `token_url` 获取.
+    pub fn new(
+        client: Client,
+        token_url: impl Into<String>,
+        client_id: impl Into<String>,
+        client_secret: impl Into<String>,
+    ) -> Self {
+        Self {
+            client,
+            token_url: token_url.into(),
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            scope: None,
+            refresh_before: Duration::from_secs(default_refresh_before_secs()),
+            token: Mutex::new(None),
+        }
+    }
+
+    /// 设置申请的权限范围.
+    #[must_use]
+    pub fn with_scope(mut self, scope: Option<String>) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// 设置提前刷新的时间, 默认 60 秒.
+    #[must_use]
+    pub const fn with_refresh_before(mut self, refresh_before: Duration) -> Self {
+        self.refresh_before = refresh_before;
+        self
+    }
+
+    /// 获取有效的令牌, 快过期时刷新.
+    async fn access_token(&self) -> Result<String> {
+        let mut cached = self.token.lock().await;
+        if let Some(token) = cached.as_ref()
+            && Instant::now() + self.refresh_before < token.expires_at
+        {
+            return Ok(token.access_token.clone());
+        }
+
+        let token = self.fetch().await?;
+        let access_token = token.access_token.clone();
+        *cached = Some(token);
+        Ok(access_token)
+    }
+
+    async fn fetch(&self) -> Result<CachedToken> {
+        let mut form = vec![("grant_type", "client_credentials")];
+        if let Some(scope) = &self.scope {
+            form.push(("scope", scope));
+        }
+        let response = self
+            .client
+            .post(&self.token_url)
+            .basic_auth(&self.client_id, Some(&self.client_secret))
+            .form(&form)
+            .send()
+            .await
+            .with_context(|| format!("请求令牌 {} 失败", self.token_url))?;
+        let status = response.status();
+        if !status.is_success() {
+            bail!("请求令牌 {} 返回 {status}", self.token_url);
+        }
+        let token: TokenResponse = response
+            .json()
+            .await
+            .with_context(|| format!("解析令牌 {} 失败", self.token_url))?;
+        log::debug!("已获取 OAuth2 令牌: {}", self.token_url);
+        Ok(CachedToken {
+            access_token: token.access_token,
+            expires_at: Instant::now() + Duration::from_secs(token.expires_in.unwrap_or(3600)),
+        })
+    }
+}
+
+#[async_trait]
+impl Auth for OAuth2ClientCredentials {
+    async fn apply(&self, request: &mut Request) -> Result<()> {
+        let token = self.access_token().await?;
+        set_header(request, AUTHORIZATION, &format!("Bearer {token}"))
+    }
+}
+
+/// HMAC-SHA256 请求签名.
+///
+/// 添加以下请求头:
+/// - `X-Key-Id`: 密钥 ID.
+/// - `X-Timestamp`: Unix 时间戳, 秒.
+/// - `X-Nonce`: 16 位随机字符串.
+/// - `X-Content-SHA256`: 消息体 SHA-256 的十六进制.
+/// - `X-Signature`: 对下面的字符串签名后的 base64, 各行用 `\n` 连接:
+///   `METHOD`, 路径和查询参数, 时间戳, 随机数, 消息体摘要.
+///
+/// 流式消息体 (如 [HttpClient::post_multipart](super::HttpClient::post_multipart)) 无法提前读取,
+/// 签名时返回错误, 请求不会发出.
+pub struct HmacSigner {
+    key_id: String,
+    secret: Vec<u8>,
+}
+
+impl HmacSigner {
+    /// 使用密钥 ID 和签名密钥创建.
+    pub fn new(key_id: impl Into<String>, secret: impl Into<String>) -> Self {
+        Self {
+            key_id: key_id.into(),
+            secret: secret.into().into_bytes(),
+        }
+    }
+
+    /// 计算签名.
+    fn sign(&self, payload: &str) -> Result<String> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).context("签名密钥无效")?;
+        mac.update(payload.as_bytes());
+        Ok(STANDARD.encode(mac.finalize().into_bytes()))
+    }
+}
+
+#[async_trait]
+impl Auth for HmacSigner {
+    async fn apply(&self, request: &mut Request) -> Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            .to_string();
+        let nonce: String = rand::rng()
+            .sample_iter(&Alphanumeric)
+            .take(16)
+            .map(char::from)
+            .collect();
+        let body = match request.body() {
+            Some(body) => body.as_bytes().context("消息体是流, 无法计算 HMAC 签名")?,
+            None => &[],
+        };
+        let digest = hex(&Sha256::digest(body));
+        let payload = canonical(request, &timestamp, &nonce, &digest);
+        let signature = self.sign(&payload)?;
+
+        set_header(request, HeaderName::from_static("x-key-id"), &self.key_id)?;
+        set_header(request, HeaderName::from_static("x-timestamp"), &timestamp)?;
+        set_header(request, HeaderName::from_static("x-nonce"), &nonce)?;
+        set_header(
+            request,
+            HeaderName::from_static("x-content-sha256"),
+            &digest,
+        )?;
+        set_header(request, HeaderName::from_static("x-signature"), &signature)
+    }
+}
+
+/// 待签名的字符串.
+fn canonical(request: &Request, timestamp: &str, nonce: &str, digest: &str) -> String {
+    let url = request.url();
+    let path = match url.query() {
+        Some(query) => format!("{}?{query}", url.path()),
+        None => url.path().to_owned(),
+    };
+    format!(
+        "{}\n{path}\n{timestamp}\n{nonce}\n{digest}",
+        request.method()
+    )
+}
+
+fn set_header(request: &mut Request, name: HeaderName, value: &str) -> Result<()> {
+    let mut value =
+        HeaderValue::from_str(value).with_context(|| format!("请求头 {name} 的值无效"))?;
+    if name == AUTHORIZATION {
+        value.set_sensitive(true);
+    }
+    request.headers_mut().insert(name, value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::{Body, Method};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const BODY_DIGEST: &str = "037c9214eef74cc3887f3a4f085b4e17d76280dafd273b0ee160c09c4ba1cfd4";
+
+    fn request(method: Method, url: &str) -> Request {
+        Request::new(method, url.parse().unwrap())
+    }
+
+    fn header(request: &Request, name: &str) -> String {
+        request.headers()[name].to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn hmac_canonical_payload_and_signature() {
+        let mut request = request(Method::POST, "https://api.example.com/v1/devices?page=2");
+        *request.body_mut() = Some(Body::from(r#"{"id":1}"#));
+        let digest = hex(&Sha256::digest(r#"{"id":1}"#));
+        assert_eq!(digest, BODY_DIGEST);
+
+        let payload = canonical(&request, "1700000000", "abcdefghijklmnop", &digest);
+        assert_eq!(
+            payload,
+            format!("POST\n/v1/devices?page=2\n1700000000\nabcdefghijklmnop\n{BODY_DIGEST}")
+        );
+        let signer = HmacSigner::new("key-1", "s3cret");
+        assert_eq!(
+            signer.sign(&payload).unwrap(),
+            "32/3NZYCgd5iDZ6IzcCwph8s0d9dzvFGIpJVF2AGbzY="
+        );
+    }
+
+    #[tokio::test]
+    async fn hmac_sets_headers_and_rejects_streamed_body() {
+        let signer = HmacSigner::new("key-1", "s3cret");
+        let mut get = request(Method::GET, "https://api.example.com/v1/devices");
+        signer.apply(&mut get).await.unwrap();
+        assert_eq!(header(&get, "x-key-id"), "key-1");
+        assert_eq!(header(&get, "x-nonce").len(), 16);
+        // 没有消息体时为空字符串的摘要
+        assert_eq!(
+            header(&get, "x-content-sha256"),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        let payload = canonical(
+            &get,
+            &header(&get, "x-timestamp"),
+            &header(&get, "x-nonce"),
+            &header(&get, "x-content-sha256"),
+        );
+        assert_eq!(header(&get, "x-signature"), signer.sign(&payload).unwrap());
+
+        let mut post = request(Method::POST, "https://api.example.com/v1/files");
+        let chunks: Vec<std::io::Result<&'static str>> = vec![Ok("part")];
+        *post.body_mut() = Some(Body::wrap_stream(futures_util::stream::iter(chunks)));
+        assert!(signer.apply(&mut post).await.is_err());
+        assert!(post.headers().get("x-signature").is_none());
+    }
+
+    #[tokio::test]
+    async fn basic_auth_encodes_credentials() {
+        let mut request = request(Method::GET, "https://api.example.com/");
+        BasicAuth::new("user", Some("pass".to_owned()))
+            .apply(&mut request)
+            .await
+            .unwrap();
+        assert_eq!(header(&request, "authorization"), "Basic dXNlcjpwYXNz");
+        assert!(request.headers()[AUTHORIZATION].is_sensitive());
+
+        BasicAuth::new("user", None)
+            .apply(&mut request)
+            .await
+            .unwrap();
+        assert_eq!(header(&request, "authorization"), "Basic dXNlcjo=");
+    }
+
+    /// 令牌服务, 每次返回 `token-{次数}`, 有效期为 `expires_in` 秒.
+    async fn token_server(expires_in: u64) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/oauth/token", listener.local_addr().unwrap());
+        let count = Arc::new(AtomicUsize::new(0));
+        let served = count.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 4096];
+                let n = socket.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+                assert!(request.starts_with("post /oauth/token"));
+                // client:clear
+                assert!(request.contains("authorization: basic y2xpzw50omnszwfy"));
+                assert!(request.contains("grant_type=client_credentials&scope=read"));
+                let n = served.fetch_add(1, Ordering::SeqCst) + 1;
+                let body = format!(r#"{{"access_token":"token-{n}","expires_in":{expires_in}}}"#);
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (url, count)
+    }
+
+    async fn authorize(auth: &OAuth2ClientCredentials) -> String {
+        let mut request = request(Method::GET, "https://api.example.com/");
+        auth.apply(&mut request).await.unwrap();
+        header(&request, "authorization")
+    }
+
+    #[tokio::test]
+    async fn oauth2_caches_token_until_refresh_window() {
+        let (url, count) = token_server(3600).await;
+        let auth = OAuth2ClientCredentials::new(Client::new(), url, "client", "clear")
+            .with_scope(Some("read".to_owned()));
+        assert_eq!(authorize(&auth).await, "Bearer token-1");
+        assert_eq!(authorize(&auth).await, "Bearer token-1");
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn oauth2_refreshes_token_before_expiry() {
+        // 有效期 30 秒, 在过期前 60 秒刷新, 所以每次都会刷新
+        let (url, count) = token_server(30).await;
+        let auth = OAuth2ClientCredentials::new(Client::new(), url, "client", "clear")
+            .with_scope(Some("read".to_owned()))
+            .with_refresh_before(Duration::from_secs(60));
+        assert_eq!(authorize(&auth).await, "Bearer token-1");
+        assert_eq!(authorize(&auth).await, "Bearer token-2");
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,572 @@
+//! 可配置的 HTTP 客户端
+//!
+//! 每个上游接口使用各自的 [HttpClient], 有独立的基础地址、超时、请求头、代理、证书、连接池,
+//! 以及熔断和限流.
+//!
+//! 配置例子:
+//!
+//! ```yaml
+//! http_clients:
+//!   partner:
+//!     base_url: "https://api.example.com/v1"
+//!     timeout_ms: 10000
+//!     headers:
+//!       X-Tenant: "demo"
+//!     retry:
+//!       max_attempts: 5
+//!     auth:
+//!       type: bearer
+//!       token: "${PARTNER_TOKEN}"
+//!     circuit_breaker:
+//!       failure_threshold: 5
+//!       open_ms: 30000
+//!     max_concurrency: 32
+//!     rate_limit:
+//!       per_second: 50
+//!       burst: 100
+//! ```
+
+use super::limit::Limits;
+use super::retry::Layers;
+use super::{
+    Auth, AuthOptions, CircuitBreakerOptions, HttpResult, LimitStats, RateLimitOptions,
+    RetryPolicy, decode, read_body, retry,
+};
+use crate::config::{ConfigErrors, Validate};
+use crate::json::Redactor;
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use reqwest::header::{ACCEPT, HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Certificate, Client, ClientBuilder, Method, Proxy, RequestBuilder, Url};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// 默认 User-Agent.
+const DEFAULT_USER_AGENT: &str = concat!("rust_template/", env!("CARGO_PKG_VERSION"));
+
+/// HTTP 客户端配置
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct HttpClientOptions {
+    /// 基础地址, 请求路径不是完整 URL 时拼接在它后面
+    pub base_url: Option<String>,
+    /// 建立连接的超时, 毫秒
+    pub connect_timeout_ms: u64,
+    /// 两次读取之间的超时, 毫秒. 不设置时普通请求不限制, 传输大文件时使用 `timeout_ms`
+    pub read_timeout_ms: Option<u64>,
+    /// 整个请求 (包括读取响应体) 的超时, 毫秒. 传输大文件时不使用, 见 [HttpClient::download_to_file]
+    pub timeout_ms: u64,
+    /// User-Agent
+    pub user_agent: String,
+    /// 每个请求都带上的请求头
+    pub headers: BTreeMap<String, String>,
+    /// 代理地址, 如 `http://127.0.0.1:8080` 或 `socks5://127.0.0.1:1080`
+    pub proxy: Option<String>,
+    /// 额外信任的 CA 证书文件 (PEM, 可以包含多个证书)
+    pub ca_file: Option<String>,
+    /// 每个主机最多保留的空闲连接数
+    pub pool_max_idle_per_host: Option<usize>,
+    /// 空闲连接的保留时间, 毫秒
+    pub pool_idle_timeout_ms: Option<u64>,
+    /// 重试策略, 不设置时使用全局策略
+    pub retry: Option<RetryPolicy>,
+    /// 认证方式, 见 [AuthOptions]
+    pub auth: Option<AuthOptions>,
+    /// 按主机熔断, 不设置时不熔断
+    pub circuit_breaker: Option<CircuitBreakerOptions>,
+    /// 最多同时发出的请求数, 不设置时不限制
+    pub max_concurrency: Option<usize>,
+    /// 令牌桶限流, 不设置时不限制
+    pub rate_limit: Option<RateLimitOptions>,
+}
+
+impl Default for HttpClientOptions {
+    fn default() -> Self {
+        Self {
+            base_url: None,
+            connect_timeout_ms: 5_000,
+            read_timeout_ms: None,
+            timeout_ms: 30_000,
+            user_agent: DEFAULT_USER_AGENT.to_owned(),
+            headers: BTreeMap::new(),
+            proxy: None,
+            ca_file: None,
+            pool_max_idle_per_host: None,
+            pool_idle_timeout_ms: None,
+            retry: None,
+            auth: None,
+            circuit_breaker: None,
+            max_concurrency: None,
+            rate_limit: None,
+        }
+    }
+}
+
+impl Validate for HttpClientOptions {
+    fn validate(&self, section: &str, errors: &mut ConfigErrors) {
+        if let Some(base_url) = &self.base_url
+            && let Err(e) = Url::parse(base_url)
+        {
+            errors.push(section, format!("base_url `{base_url}` 无效: {e}"));
+        }
+        errors.check(
+            self.connect_timeout_ms > 0,
+            section,
+            "connect_timeout_ms 必须大于 0",
+        );
+        errors.check(self.timeout_ms > 0, section, "timeout_ms 必须大于 0");
+        errors.check(
+            self.read_timeout_ms != Some(0),
+            section,
+            "read_timeout_ms 必须大于 0",
+        );
+        for (name, value) in &self.headers {
+            errors.check(
+                HeaderName::try_from(name.as_str()).is_ok(),
+                section,
+                format!("请求头名称 `{name}` 无效"),
+            );
+            errors.check(
+                HeaderValue::try_from(value.as_str()).is_ok(),
+                section,
+                format!("请求头 `{name}` 的值无效"),
+            );
+        }
+        if let Some(proxy) = &self.proxy
+            && let Err(e) = Proxy::all(proxy)
+        {
+            errors.push(section, format!("proxy `{proxy}` 无效: {e}"));
+        }
+        if let Some(ca_file) = &self.ca_file {
+            errors.check(
+                Path::new(ca_file).is_file(),
+                section,
+                format!("ca_file `{ca_file}` 不存在"),
+            );
+        }
+        if let Some(AuthOptions::Oauth2ClientCredentials { token_url, .. }) = &self.auth
+            && let Err(e) = Url::parse(token_url)
+        {
+            errors.push(section, format!("auth.token_url `{token_url}` 无效: {e}"));
+        }
+        if let Some(retry) = &self.retry {
+            errors.check(
+                retry.max_attempts > 0,
+                section,
+                "retry.max_attempts 必须大于 0",
+            );
+        }
+        self.validate_limits(section, errors);
+    }
+}
+
+impl HttpClientOptions {
+    /// 校验熔断、并发上限和限流.
+    fn validate_limits(&self, section: &str, errors: &mut ConfigErrors) {
+        if let Some(breaker) = &self.circuit_breaker {
+            errors.check(
+                breaker.failure_threshold > 0,
+                section,
+                "circuit_breaker.failure_threshold 必须大于 0",
+            );
+            errors.check(
+                breaker.open_ms > 0,
+                section,
+                "circuit_breaker.open_ms 必须大于 0",
+            );
+        }
+        errors.check(
+            self.max_concurrency != Some(0),
+            section,
+            "max_concurrency 必须大于 0",
+        );
+        if let Some(rate_limit) = &self.rate_limit {
+            errors.check(
+                rate_limit.per_second.is_finite() && rate_limit.per_second > 0.0,
+                section,
+                "rate_limit.per_second 必须大于 0",
+            );
+            errors.check(rate_limit.burst > 0, section, "rate_limit.burst 必须大于 0");
+        }
+    }
+}
+
+/// 命名的 HTTP 客户端, `clone` 的开销很小, 共享同一个连接池.
+#[derive(Clone)]
+pub struct HttpClient {
+    name: Arc<str>,
+    client: Client,
+    /// 传输大文件用的客户端, 没有整个请求的超时
+    stream_client: Client,
+    base_url: Option<String>,
+    retry: Option<RetryPolicy>,
+    auth: Option<Arc<dyn Auth>>,
+    limits: Arc<Limits>,
+}
+
+impl HttpClient {
+    /// 按配置创建客户端.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - 客户端名称, 用于日志.
+    /// * `opt` - 客户端配置.
+    ///
+    /// # Errors
+    ///
+    /// 配置校验不通过 (见 [Validate]), 或者创建客户端失败时返回错误.
+    pub fn new(name: &str, opt: &HttpClientOptions) -> Result<Self> {
+        let mut errors = ConfigErrors::default();
+        opt.validate(name, &mut errors);
+        errors
+            .into_result()
+            .with_context(|| format!("HTTP 客户端 {name} 的配置无效"))?;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static("*/*"));
+        for (key, value) in &opt.headers {
+            let key = HeaderName::try_from(key.as_str())
+                .with_context(|| format!("请求头名称 `{key}` 无效"))?;
+            let value = HeaderValue::try_from(value.as_str())
+                .with_context(|| format!("请求头 `{key}` 的值无效"))?;
+            headers.insert(key, value);
+        }
+
+        let timeout = Duration::from_millis(opt.timeout_ms);
+        let read_timeout = opt.read_timeout_ms.map(Duration::from_millis);
+        let mut builder = client_builder(opt, headers.clone())?.timeout(timeout);
+        if let Some(read_timeout) = read_timeout {
+            builder = builder.read_timeout(read_timeout);
+        }
+        let client = builder
+            .build()
+            .with_context(|| format!("创建 HTTP 客户端 {name} 失败"))?;
+        // 大文件的传输时间无法预估, 不限制整个请求的时间, 只通过两次读取之间的超时发现卡住的连接
+        let stream_client = client_builder(opt, headers)?
+            .read_timeout(read_timeout.unwrap_or(timeout))
+            .build()
+            .with_context(|| format!("创建 HTTP 客户端 {name} 失败"))?;
+        Ok(Self {
+            name: name.into(),
+            auth: opt.auth.as_ref().map(|auth| auth.build(client.clone())),
+            client,
+            stream_client,
+            base_url: opt.base_url.clone(),
+            retry: opt.retry.clone(),
+            limits: Arc::new(Limits::new(
+                name,
+                opt.circuit_breaker.clone(),
+                opt.max_concurrency,
+                opt.rate_limit.as_ref(),
+            )),
+        })
+    }
+
+    /// 按 `opt` 更新熔断、并发上限和限流, 所有 `clone` 出来的客户端同时生效. 其他配置不变.
+    ///
+    /// # Errors
+    ///
+    /// 这三项配置校验不通过时返回错误, 保持原来的限制.
+    pub fn update_limits(&self, opt: &HttpClientOptions) -> Result<()> {
+        let mut errors = ConfigErrors::default();
+        opt.validate_limits(&self.name, &mut errors);
+        errors
+            .into_result()
+            .with_context(|| format!("HTTP 客户端 {} 的熔断和限流配置无效", self.name))?;
+        self.limits.update(
+            opt.circuit_breaker.clone(),
+            opt.max_concurrency,
+            opt.rate_limit.as_ref(),
+        );
+        Ok(())
+    }
+
+    /// 使用自定义的认证方式, 替换配置中的 `auth`.
+    #[must_use]
+    pub fn with_auth(mut self, auth: Arc<dyn Auth>) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// 客户端名称.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 客户端的重试策略, 没有配置时为全局策略.
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        self.retry.as_ref().unwrap_or_else(|| RetryPolicy::global())
+    }
+
+    /// 熔断和限流的统计, 没有配置时为 `None`.
+    pub fn stats(&self) -> Option<LimitStats> {
+        self.limits.is_configured().then(|| self.limits.stats())
+    }
+
+    /// 拼接完整地址. `path` 已经是完整 URL 或者没有配置基础地址时直接返回.
+    pub fn url(&self, path: &str) -> String {
+        match &self.base_url {
+            Some(base) if !path.starts_with("http://") && !path.starts_with("https://") => {
+                format!(
+                    "{}/{}",
+                    base.trim_end_matches('/'),
+                    path.trim_start_matches('/')
+                )
+            }
+            _ => path.to_owned(),
+        }
+    }
+
+    /// 创建请求, 用于上面的方法不能满足的场景. 通过 [HttpClient::send] 发送时会进行认证.
+    pub fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.client.request(method, self.url(path))
+    }
+
+    /// 创建传输大文件的请求, 没有整个请求的超时, 读取间隔超过 `read_timeout_ms`
+    /// (没有配置时为 `timeout_ms`) 时失败.
+    pub(super) fn stream_request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.stream_client.request(method, self.url(path))
+    }
+
+    /// 发送 [HttpClient::request] 创建的请求, 按指定策略重试, 返回最后一次的响应.
+    ///
+    /// 不检查状态码. 请求体是流时无法重试, 只会发送一次.
+    pub async fn send(
+        &self,
+        request: RequestBuilder,
+        policy: &RetryPolicy,
+    ) -> HttpResult<reqwest::Response> {
+        match request.try_clone() {
+            Some(template) => {
+                let build = || template.try_clone().expect("请求已确认可以复制");
+                retry::send(build, policy, self.layers()).await
+            }
+            None => retry::send_once(request, self.layers()).await,
+        }
+    }
+
+    /// 发送 GET 请求, 按客户端的策略重试.
+    pub async fn get(&self, path: &str) -> HttpResult<Bytes> {
+        self.get_with(path, self.retry_policy()).await
+    }
+
+    /// 发送 GET 请求, 按指定策略重试.
+    pub async fn get_with(&self, path: &str, policy: &RetryPolicy) -> HttpResult<Bytes> {
+        let url = self.url(path);
+        let response = retry::send(|| self.client.get(&url), policy, self.layers()).await;
+        read_body(&url, response).await
+    }
+
+    /// 发送 GET 请求, 按客户端的策略重试, 并将响应体解析为 `R`.
+    pub async fn get_json<R: DeserializeOwned>(&self, path: &str) -> HttpResult<R> {
+        decode(&self.url(path), &self.get(path).await?)
+    }
+
+    /// 发送 POST 请求, 消息体为 json, 不重试.
+    pub async fn post_json<T: Serialize + ?Sized>(
+        &self,
+        path: &str,
+        json_data: &T,
+    ) -> HttpResult<Bytes> {
+        self.post_json_with(path, json_data, &RetryPolicy::none())
+            .await
+    }
+
+    /// 发送 POST 请求, 消息体为 json, 按指定策略重试.
+    ///
+    /// 只有接口是幂等的 (如带有幂等键) 时才应该重试.
+    pub async fn post_json_with<T: Serialize + ?Sized>(
+        &self,
+        path: &str,
+        json_data: &T,
+        policy: &RetryPolicy,
+    ) -> HttpResult<Bytes> {
+        let url = self.url(path);
+        log::debug!(
+            "[{}] POST {url}, 请求体: {}",
+            self.name,
+            Redactor::global().display(json_data)
+        );
+        let response = retry::send(
+            || self.client.post(&url).json(json_data),
+            policy,
+            self.layers(),
+        )
+        .await;
+        read_body(&url, response).await
+    }
+
+    /// 发送 POST 请求, 消息体为 json, 不重试, 并将响应体解析为 `R`.
+    pub async fn post_json_as<T, R>(&self, path: &str, json_data: &T) -> HttpResult<R>
+    where
+        T: Serialize + ?Sized,
+        R: DeserializeOwned,
+    {
+        decode(&self.url(path), &self.post_json(path, json_data).await?)
+    }
+
+    fn layers(&self) -> Layers<'_> {
+        Layers {
+            limits: Some(&self.limits),
+            auth: self.auth.as_deref(),
+        }
+    }
+}
+
+/// 按配置创建 [ClientBuilder], 不包括整个请求和读取的超时.
+fn client_builder(opt: &HttpClientOptions, headers: HeaderMap) -> Result<ClientBuilder> {
+    let mut builder = Client::builder()
+        .user_agent(&opt.user_agent)
+        .default_headers(headers)
+        .connect_timeout(Duration::from_millis(opt.connect_timeout_ms));
+    if let Some(proxy) = &opt.proxy {
+        builder = builder.proxy(Proxy::all(proxy).with_context(|| format!("代理 `{proxy}` 无效"))?);
+    }
+    if let Some(ca_file) = &opt.ca_file {
+        let pem = fs::read(ca_file).with_context(|| format!("读取证书文件 {ca_file} 失败"))?;
+        let certs = Certificate::from_pem_bundle(&pem)
+            .with_context(|| format!("证书文件 {ca_file} 无效"))?;
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    if let Some(max) = opt.pool_max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(max);
+    }
+    if let Some(ms) = opt.pool_idle_timeout_ms {
+        builder = builder.pool_idle_timeout(Duration::from_millis(ms));
+    }
+    Ok(builder)
+}
+
+/// 去掉可以在运行中调整的配置, 用于比较其他配置是否变化.
+fn without_limits(opt: &HttpClientOptions) -> HttpClientOptions {
+    HttpClientOptions {
+        circuit_breaker: None,
+        max_concurrency: None,
+        rate_limit: None,
+        ..opt.clone()
+    }
+}
+
+/// 按名称管理的多个客户端.
+#[derive(Clone, Default)]
+pub struct HttpClients {
+    clients: HashMap<String, HttpClient>,
+}
+
+impl HttpClients {
+    /// 按配置创建所有客户端.
+    ///
+    /// # Errors
+    ///
+    /// 任意客户端创建失败时返回错误.
+    pub fn new(options: &BTreeMap<String, HttpClientOptions>) -> Result<Self> {
+        let clients = options
+            .iter()
+            .map(|(name, opt)| Ok((name.clone(), HttpClient::new(name, opt)?)))
+            .collect::<Result<_>>()?;
+        Ok(Self { clients })
+    }
+
+    /// 配置重新加载后调用, 更新各客户端的熔断、并发上限和限流.
+    ///
+    /// 其他配置的变化, 以及新增或删除客户端, 需要重启才能生效, 只记录警告.
+    pub fn reload(
+        &self,
+        old: &BTreeMap<String, HttpClientOptions>,
+        new: &BTreeMap<String, HttpClientOptions>,
+    ) {
+        for (name, opt) in new {
+            let Some(client) = self.clients.get(name) else {
+                log::warn!("新增的 HTTP 客户端 {name} 需要重启才能使用.");
+                continue;
+            };
+            let Some(old) = old.get(name) else {
+                continue;
+            };
+            if without_limits(old) != without_limits(opt) {
+                log::warn!("HTTP 客户端 {name} 除熔断和限流以外的配置需要重启才能生效.");
+            }
+            if (&old.circuit_breaker, old.max_concurrency, &old.rate_limit)
+                != (&opt.circuit_breaker, opt.max_concurrency, &opt.rate_limit)
+            {
+                match client.update_limits(opt) {
+                    Ok(()) => log::info!("HTTP 客户端 {name} 的熔断和限流配置已更新."),
+                    Err(e) => log::error!("{e:#}"),
+                }
+            }
+        }
+        for name in old.keys().filter(|name| !new.contains_key(*name)) {
+            log::warn!("删除的 HTTP 客户端 {name} 在重启前仍可使用.");
+        }
+    }
+
+    /// 按名称获取客户端.
+    pub fn get(&self, name: &str) -> Option<&HttpClient> {
+        self.clients.get(name)
+    }
+
+    /// 所有配置了熔断或限流的客户端的统计, 按名称排序.
+    pub fn stats(&self) -> BTreeMap<String, LimitStats> {
+        self.clients
+            .iter()
+            .filter_map(|(name, client)| Some((name.clone(), client.stats()?)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reload_updates_limits_of_shared_clients() {
+        let mut old = BTreeMap::new();
+        old.insert("a".to_owned(), HttpClientOptions::default());
+        let clients = HttpClients::new(&old).unwrap();
+        let shared = clients.clone();
+        assert!(shared.get("a").unwrap().stats().is_none());
+
+        let mut new = old.clone();
+        let opt = new.get_mut("a").unwrap();
+        opt.max_concurrency = Some(4);
+        // 需要重启的配置只记录警告
+        opt.timeout_ms = 1_000;
+        new.insert("b".to_owned(), HttpClientOptions::default());
+        clients.reload(&old, &new);
+
+        let stats = shared.get("a").unwrap().stats().unwrap();
+        assert_eq!(stats.max_concurrency, Some(4));
+        assert!(shared.get("b").is_none());
+
+        clients.reload(&new, &old);
+        assert!(shared.get("a").unwrap().stats().is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_rate_limit() {
+        for per_second in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let opt = HttpClientOptions {
+                rate_limit: Some(RateLimitOptions {
+                    per_second,
+                    burst: 1,
+                }),
+                ..HttpClientOptions::default()
+            };
+            let err = HttpClient::new("partner", &opt).err().unwrap();
+            assert!(
+                format!("{err:#}").contains("rate_limit.per_second 必须大于 0"),
+                "{per_second}: {err:#}"
+            );
+            let client = HttpClient::new("partner", &HttpClientOptions::default()).unwrap();
+            assert!(client.update_limits(&opt).is_err());
+            assert!(client.stats().is_none());
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! HTTP 请求错误
+
+use crate::json::Redactor;
+use reqwest::StatusCode;
+use std::path::Path;
+use thiserror::Error;
+
+/// 错误中保留的响应体长度上限 (字节).
+pub(crate) const SNIPPET_LEN: usize = 512;
+
+/// HTTP 请求的结果.
+pub type HttpResult<T> = Result<T, HttpError>;
+
+/// HTTP 请求错误.
+#[derive(Debug, Error)]
+pub enum HttpError {
+    /// 响应状态码不是 2xx.
+    #[error("{url} 返回 {status}: {body_snippet}")]
+    Status {
+        /// 状态码.
+        status: StatusCode,
+        /// 请求地址.
+        url: String,
+        /// 响应体的开头部分, 已脱敏.
+        body_snippet: String,
+    },
+    /// 请求没有得到响应, 如连接失败、超时, 或读取响应体失败.
+    #[error("{url} 请求失败: {source}")]
+    Request {
+        /// 请求地址.
+        url: String,
+        /// 原始错误.
+        #[source]
+        source: reqwest::Error,
+    },
+    /// 认证或签名失败, 请求没有发出.
+    #[error("{url} 认证失败: {reason}")]
+    Auth {
+        /// 请求地址.
+        url: String,
+        /// 失败原因.
+        reason: String,
+    },
+    /// 目标主机熔断中, 请求没有发出.
+    #[error("{url} 熔断中, 请求被拒绝")]
+    CircuitOpen {
+        /// 请求地址.
+        url: String,
+    },
+    /// 读写本地文件失败.
+    #[error("{url} 读写文件 {path} 失败: {source}")]
+    Io {
+        /// 请求地址.
+        url: String,
+        /// 文件路径.
+        path: String,
+        /// 原始错误.
+        #[source]
+        source: std::io::Error,
+    },
+    /// 下载的文件校验和不一致.
+    #[error("{url} 校验失败, 期望 {expected}, 实际 {actual}")]
+    Checksum {
+        /// 请求地址.
+        url: String,
+        /// 期望的校验和.
+        expected: String,
+        /// 实际的校验和.
+        actual: String,
+    },
+    /// 响应体无法解析为期望的类型.
+    #[error("{url} 响应解析失败: {source}, 响应: {body_snippet}")]
+    Decode {
+        /// 请求地址.
+        url: String,
+        /// 响应体的开头部分, 已脱敏.
+        body_snippet: String,
+        /// 原始错误.
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+impl HttpError {
+    /// 响应的状态码, 没有收到响应时为 `None`.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::Status { status, .. } => Some(*status),
+            Self::Request { source, .. } => source.status(),
+            Self::Auth { .. }
+            | Self::CircuitOpen { .. }
+            | Self::Io { .. }
+            | Self::Checksum { .. }
+            | Self::Decode { .. } => None,
+        }
+    }
+
+    /// 请求地址.
+    pub fn url(&self) -> &str {
+        match self {
+            Self::Status { url, .. }
+            | Self::Request { url, .. }
+            | Self::Auth { url, .. }
+            | Self::CircuitOpen { url }
+            | Self::Io { url, .. }
+            | Self::Checksum { url, .. }
+            | Self::Decode { url, .. } => url,
+        }
+    }
+
+    pub(crate) fn request(url: &str, source: reqwest::Error) -> Self {
+        Self::Request {
+            url: url.to_owned(),
+            source,
+        }
+    }
+
+    pub(crate) fn io(url: &str, path: &Path, source: std::io::Error) -> Self {
+        Self::Io {
+            url: url.to_owned(),
+            path: path.display().to_string(),
+            source,
+        }
+    }
+}
+
+/// 截取响应体的开头部分用于错误信息. JSON 会先脱敏.
+pub(crate) fn snippet(body: &[u8]) -> String {
+    let text = match serde_json::from_slice::<serde_json::Value>(body) {
+        Ok(mut value) => {
+            Redactor::global().redact_in_place(&mut value);
+            value.to_string()
+        }
+        Err(_) => String::from_utf8_lossy(body).into_owned(),
+    };
+    if text.len() <= SNIPPET_LEN {
+        return text;
+    }
+    let mut end = SNIPPET_LEN;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...", &text[..end])
+}
@@ -0,0 +1,580 @@
+//! 熔断与限流
+//!
+//! 每个 [HttpClient](super::HttpClient) 可以单独配置:
+//! - 熔断: 按主机统计, 连续失败 (连接错误、超时或 5xx) 达到阈值后打开, 冷却时间内直接拒绝请求;
+//!   冷却结束后进入半开状态, 放行少量请求试探, 成功则关闭, 失败则重新打开.
+//! - 并发上限: 同时发出的请求数, 超过时排队等待.
+//! - 令牌桶限流: 每秒请求数和突发容量, 没有令牌时等待.
+//!
+//! 每次重试都会重新经过这些限制. 状态变化会写日志, 统计信息通过 [LimitStats] 获取.
+//!
+//! 三项配置都可以在运行中通过 [HttpClient::update_limits](super::HttpClient::update_limits) 调整,
+//! 所有 `clone` 出来的客户端同时生效. 熔断保留各主机的状态; 并发上限变化时,
+//! 已经发出的请求仍占用旧的名额, 不计入新的上限.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// 熔断配置
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct CircuitBreakerOptions {
+    /// 连续失败多少次后打开
+    pub failure_threshold: u32,
+    /// 打开后的冷却时间, 毫秒
+    pub open_ms: u64,
+    /// 半开状态下最多同时放行的请求数
+    pub half_open_max_calls: u32,
+}
+
+impl Default for CircuitBreakerOptions {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            open_ms: 30_000,
+            half_open_max_calls: 1,
+        }
+    }
+}
+
+/// 令牌桶限流配置
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RateLimitOptions {
+    /// 每秒产生的令牌数
+    pub per_second: f64,
+    /// 桶的容量, 即允许的突发请求数
+    pub burst: u32,
+}
+
+/// 熔断状态.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// 正常放行.
+    Closed,
+    /// 拒绝所有请求.
+    Open,
+    /// 放行少量请求试探.
+    HalfOpen,
+}
+
+/// 单个主机的熔断统计.
+#[derive(Debug, Clone, Serialize)]
+pub struct BreakerStats {
+    /// 主机, 包含端口.
+    pub host: String,
+    /// 当前状态.
+    pub state: BreakerState,
+    /// 连续失败次数.
+    pub consecutive_failures: u32,
+    /// 累计打开次数.
+    pub opened_total: u64,
+    /// 累计因熔断被拒绝的请求数.
+    pub rejected_total: u64,
+}
+
+/// 客户端的熔断与限流统计.
+#[derive(Debug, Clone, Serialize)]
+pub struct LimitStats {
+    /// 并发上限, 没有配置时为 `None`.
+    pub max_concurrency: Option<usize>,
+    /// 正在发送的请求数 (只在配置了并发上限时统计).
+    pub in_flight: usize,
+    /// 累计因限流等待的次数.
+    pub throttled_total: u64,
+    /// 各主机的熔断状态.
+    pub breakers: Vec<BreakerStats>,
+}
+
+/// 一个客户端的所有限制, 没有配置的项不限制.
+pub(crate) struct Limits {
+    name: String,
+    breakers: Mutex<Breakers>,
+    /// 并发上限和对应的信号量.
+    concurrency: Mutex<Option<(usize, Arc<Semaphore>)>>,
+    bucket: Mutex<Option<TokenBucket>>,
+    throttled_total: AtomicU64,
+}
+
+/// 每个客户端最多记录熔断状态的主机数, 超过时淘汰最久没有请求的主机, 优先淘汰正常的主机.
+const MAX_HOSTS: usize = 1024;
+
+/// 请求放行后持有, 释放时归还并发名额.
+///
+/// 收到结果后调用 [Permit::finish] 记录结果. 没有调用就释放时 (如认证失败、调用方取消或超时)
+/// 不计入熔断, 只归还半开状态的试探名额, 否则该主机会一直被拒绝.
+pub(crate) struct Permit<'a> {
+    limits: &'a Limits,
+    host: String,
+    /// 是否占用了半开状态的试探名额.
+    probe: bool,
+    finished: bool,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+/// 熔断配置和各主机的状态.
+#[derive(Default)]
+struct Breakers {
+    options: Option<CircuitBreakerOptions>,
+    hosts: HashMap<String, Breaker>,
+}
+
+struct Breaker {
+    state: BreakerState,
+    last_used: Instant,
+    consecutive_failures: u32,
+    open_until: Instant,
+    half_open_calls: u32,
+    opened_total: u64,
+    rejected_total: u64,
+}
+
+struct TokenBucket {
+    per_second: f64,
+    burst: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl Limits {
+    /// 创建.
+    pub(crate) fn new(
+        name: &str,
+        breaker: Option<CircuitBreakerOptions>,
+        max_concurrency: Option<usize>,
+        rate_limit: Option<&RateLimitOptions>,
+    ) -> Self {
+        let limits = Self {
+            name: name.to_owned(),
+            breakers: Mutex::new(Breakers::default()),
+            concurrency: Mutex::new(None),
+            bucket: Mutex::new(None),
+            throttled_total: AtomicU64::new(0),
+        };
+        limits.update(breaker, max_concurrency, rate_limit);
+        limits
+    }
+
+    /// 更新配置, 没有变化的项保持原来的状态.
+    pub(crate) fn update(
+        &self,
+        breaker: Option<CircuitBreakerOptions>,
+        max_concurrency: Option<usize>,
+        rate_limit: Option<&RateLimitOptions>,
+    ) {
+        {
+            let mut breakers = lock(&self.breakers);
+            if breaker.is_none() {
+                breakers.hosts.clear();
+            }
+            breakers.options = breaker;
+        }
+        {
+            let mut concurrency = lock(&self.concurrency);
+            if concurrency.as_ref().map(|(n, _)| *n) != max_concurrency {
+                *concurrency = max_concurrency.map(|n| (n, Arc::new(Semaphore::new(n))));
+            }
+        }
+        let mut bucket = lock(&self.bucket);
+        *bucket = rate_limit.map(|opt| {
+            let burst = f64::from(opt.burst);
+            match bucket.take() {
+                // 保留剩余的令牌, 不超过新的容量
+                Some(old) => TokenBucket {
+                    per_second: opt.per_second,
+                    burst,
+                    tokens: old.tokens.min(burst),
+                    last: old.last,
+                },
+                None => TokenBucket {
+                    per_second: opt.per_second,
+                    burst,
+                    tokens: burst,
+                    last: Instant::now(),
+                },
+            }
+        });
+    }
+
+    /// 是否配置了任意一项限制.
+    pub(crate) fn is_configured(&self) -> bool {
+        lock(&self.breakers).options.is_some()
+            || lock(&self.concurrency).is_some()
+            || lock(&self.bucket).is_some()
+    }
+
+    /// 发送前检查熔断、等待限流和并发名额.
+    ///
+    /// # Returns
+    ///
+    /// 熔断打开时返回 `None`, 请求不应发出.
+    pub(crate) async fn acquire(&self, host: &str) -> Option<Permit<'_>> {
+        let probe = self.allow(host)?;
+        if self.acquire_token().await {
+            self.throttled_total.fetch_add(1, Ordering::Relaxed);
+        }
+        let semaphore = lock(&self.concurrency)
+            .as_ref()
+            .map(|(_, semaphore)| semaphore.clone());
+        let permit = match semaphore {
+            Some(semaphore) => semaphore.acquire_owned().await.ok(),
+            None => None,
+        };
+        Some(Permit {
+            limits: self,
+            host: host.to_owned(),
+            probe,
+            finished: false,
+            _permit: permit,
+        })
+    }
+
+    /// 记录请求结果, 更新熔断状态.
+    fn record(&self, host: &str, success: bool) {
+        let mut breakers = lock(&self.breakers);
+        let Breakers {
+            options: Some(opt),
+            hosts,
+        } = &mut *breakers
+        else {
+            return;
+        };
+        let breaker = breaker(hosts, host);
+        match (breaker.state, success) {
+            (BreakerState::Closed, true) => breaker.consecutive_failures = 0,
+            (BreakerState::Closed, false) => {
+                breaker.consecutive_failures += 1;
+                if breaker.consecutive_failures >= opt.failure_threshold {
+                    self.open(host, breaker, opt);
+                }
+            }
+            (BreakerState::HalfOpen, true) => {
+                breaker.state = BreakerState::Closed;
+                breaker.consecutive_failures = 0;
+                breaker.half_open_calls = 0;
+                log::info!("[{}] {host} 熔断关闭, 恢复正常.", self.name);
+            }
+            (BreakerState::HalfOpen, false) => {
+                breaker.consecutive_failures += 1;
+                self.open(host, breaker, opt);
+            }
+            // 打开前已经发出的请求, 结果不影响状态
+            (BreakerState::Open, _) => {}
+        }
+    }
+
+    /// 统计信息.
+    pub(crate) fn stats(&self) -> LimitStats {
+        let mut breakers: Vec<BreakerStats> = lock(&self.breakers)
+            .hosts
+            .iter()
+            .map(|(host, b)| BreakerStats {
+                host: host.clone(),
+                state: b.state,
+                consecutive_failures: b.consecutive_failures,
+                opened_total: b.opened_total,
+                rejected_total: b.rejected_total,
+            })
+            .collect();
+        breakers.sort_by(|a, b| a.host.cmp(&b.host));
+        let concurrency = lock(&self.concurrency);
+        LimitStats {
+            max_concurrency: concurrency.as_ref().map(|(n, _)| *n),
+            in_flight: concurrency
+                .as_ref()
+                .map_or(0, |(n, s)| n - s.available_permits()),
+            throttled_total: self.throttled_total.load(Ordering::Relaxed),
+            breakers,
+        }
+    }
+
+    /// 熔断检查, 必要时从打开转为半开.
+    ///
+    /// # Returns
+    ///
+    /// 拒绝时返回 `None`, 放行时返回是否占用了半开状态的试探名额.
+    fn allow(&self, host: &str) -> Option<bool> {
+        let mut breakers = lock(&self.breakers);
+        let Breakers {
+            options: Some(opt),
+            hosts,
+        } = &mut *breakers
+        else {
+            return Some(false);
+        };
+        let breaker = breaker(hosts, host);
+        if breaker.state == BreakerState::Open && Instant::now() >= breaker.open_until {
+            breaker.state = BreakerState::HalfOpen;
+            breaker.half_open_calls = 0;
+            log::info!("[{}] {host} 熔断半开, 开始试探.", self.name);
+        }
+        let allowed = match breaker.state {
+            BreakerState::Closed => Some(false),
+            BreakerState::Open => None,
+            BreakerState::HalfOpen => {
+                let allowed = breaker.half_open_calls < opt.half_open_max_calls.max(1);
+                if allowed {
+                    breaker.half_open_calls += 1;
+                }
+                allowed.then_some(true)
+            }
+        };
+        if allowed.is_none() {
+            breaker.rejected_total += 1;
+        }
+        allowed
+    }
+
+    /// 没有结果的试探请求结束时, 归还半开状态的试探名额.
+    fn release_probe(&self, host: &str) {
+        let mut breakers = lock(&self.breakers);
+        if let Some(breaker) = breakers.hosts.get_mut(host)
+            && breaker.state == BreakerState::HalfOpen
+        {
+            breaker.half_open_calls = breaker.half_open_calls.saturating_sub(1);
+        }
+    }
+
+    fn open(&self, host: &str, breaker: &mut Breaker, opt: &CircuitBreakerOptions) {
+        breaker.state = BreakerState::Open;
+        breaker.open_until = Instant::now() + Duration::from_millis(opt.open_ms);
+        breaker.opened_total += 1;
+        log::warn!(
+            "[{}] {host} 连续失败 {} 次, 熔断打开 {}ms.",
+            self.name,
+            breaker.consecutive_failures,
+            opt.open_ms
+        );
+    }
+}
+
+impl Permit<'_> {
+    /// 记录请求结果并归还并发名额.
+    pub(crate) fn finish(mut self, success: bool) {
+        self.finished = true;
+        self.limits.record(&self.host, success);
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if !self.finished && self.probe {
+            self.limits.release_probe(&self.host);
+        }
+    }
+}
+
+/// 取出主机的熔断状态, 没有时新建, 并更新最后请求的时间.
+fn breaker<'a>(hosts: &'a mut HashMap<String, Breaker>, host: &str) -> &'a mut Breaker {
+    if hosts.len() >= MAX_HOSTS
+        && !hosts.contains_key(host)
+        && let Some(idle) = hosts
+            .iter()
+            .min_by_key(|(_, b)| (b.state != BreakerState::Closed, b.last_used))
+            .map(|(host, _)| host.clone())
+    {
+        hosts.remove(&idle);
+    }
+    let breaker = hosts.entry(host.to_owned()).or_insert_with(Breaker::new);
+    breaker.last_used = Instant::now();
+    breaker
+}
+
+impl Breaker {
+    fn new() -> Self {
+        Self {
+            state: BreakerState::Closed,
+            last_used: Instant::now(),
+            consecutive_failures: 0,
+            open_until: Instant::now(),
+            half_open_calls: 0,
+            opened_total: 0,
+            rejected_total: 0,
+        }
+    }
+}
+
+impl Limits {
+    /// 从令牌桶取一个令牌, 没有时等待. 返回是否等待过.
+    async fn acquire_token(&self) -> bool {
+        let mut waited = false;
+        loop {
+            let wait = {
+                let mut bucket = lock(&self.bucket);
+                let Some(bucket) = bucket.as_mut() else {
+                    return waited;
+                };
+                let now = Instant::now();
+                bucket.tokens = (bucket.tokens
+                    + now.duration_since(bucket.last).as_secs_f64() * bucket.per_second)
+                    .min(bucket.burst);
+                bucket.last = now;
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return waited;
+                }
+                Duration::from_secs_f64((1.0 - bucket.tokens) / bucket.per_second)
+            };
+            waited = true;
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(open_ms: u64) -> Limits {
+        let breaker = CircuitBreakerOptions {
+            failure_threshold: 2,
+            open_ms,
+            half_open_max_calls: 1,
+        };
+        Limits::new("test", Some(breaker), None, None)
+    }
+
+    fn state(limits: &Limits, host: &str) -> BreakerState {
+        lock(&limits.breakers).hosts[host].state
+    }
+
+    #[tokio::test]
+    async fn opens_after_consecutive_failures() {
+        let limits = limits(60_000);
+        limits.acquire("a:80").await.unwrap().finish(false);
+        limits.acquire("a:80").await.unwrap().finish(true);
+        limits.acquire("a:80").await.unwrap().finish(false);
+        assert_eq!(state(&limits, "a:80"), BreakerState::Closed);
+        limits.acquire("a:80").await.unwrap().finish(false);
+        assert_eq!(state(&limits, "a:80"), BreakerState::Open);
+        assert!(limits.acquire("a:80").await.is_none());
+        // 其他主机不受影响
+        assert!(limits.acquire("b:80").await.is_some());
+
+        let stats = limits.stats();
+        assert_eq!(stats.breakers[0].opened_total, 1);
+        assert_eq!(stats.breakers[0].rejected_total, 1);
+    }
+
+    #[tokio::test]
+    async fn half_open_closes_on_success_and_reopens_on_failure() {
+        let limits = limits(0);
+        for _ in 0..2 {
+            limits.acquire("a:80").await.unwrap().finish(false);
+        }
+        assert_eq!(state(&limits, "a:80"), BreakerState::Open);
+
+        // 冷却结束后只放行一个试探请求
+        let probe = limits.acquire("a:80").await.unwrap();
+        assert_eq!(state(&limits, "a:80"), BreakerState::HalfOpen);
+        assert!(limits.acquire("a:80").await.is_none());
+        probe.finish(false);
+        assert_eq!(state(&limits, "a:80"), BreakerState::Open);
+
+        limits.acquire("a:80").await.unwrap().finish(true);
+        assert_eq!(state(&limits, "a:80"), BreakerState::Closed);
+        assert_eq!(limits.stats().breakers[0].consecutive_failures, 0);
+    }
+
+    #[tokio::test]
+    async fn dropped_probe_releases_half_open_slot() {
+        let limits = limits(0);
+        for _ in 0..2 {
+            limits.acquire("a:80").await.unwrap().finish(false);
+        }
+        // 试探请求没有结果就被释放, 如认证失败
+        drop(limits.acquire("a:80").await.unwrap());
+        assert_eq!(state(&limits, "a:80"), BreakerState::HalfOpen);
+        assert!(limits.acquire("a:80").await.is_some());
+    }
+
+    #[tokio::test]
+    async fn dropped_permits_do_not_open_breaker() {
+        let limits = limits(60_000);
+        // 调用方取消或超时, 主机本身正常
+        for _ in 0..5 {
+            drop(limits.acquire("a:80").await.unwrap());
+        }
+        assert_eq!(state(&limits, "a:80"), BreakerState::Closed);
+        assert_eq!(limits.stats().breakers[0].consecutive_failures, 0);
+    }
+
+    #[tokio::test]
+    async fn evicts_idle_hosts_when_full() {
+        let limits = limits(60_000);
+        for _ in 0..2 {
+            limits.acquire("failing:80").await.unwrap().finish(false);
+        }
+        for i in 0..MAX_HOSTS + 10 {
+            limits
+                .acquire(&format!("h{i}:80"))
+                .await
+                .unwrap()
+                .finish(true);
+        }
+        let hosts = lock(&limits.breakers).hosts.len();
+        assert_eq!(hosts, MAX_HOSTS);
+        // 打开的熔断不会因为其他主机的请求被淘汰
+        assert_eq!(state(&limits, "failing:80"), BreakerState::Open);
+    }
+
+    #[tokio::test]
+    async fn concurrency_permit_is_returned() {
+        let limits = Limits::new("test", None, Some(1), None);
+        let permit = limits.acquire("a:80").await.unwrap();
+        assert_eq!(limits.stats().in_flight, 1);
+        permit.finish(true);
+        assert_eq!(limits.stats().in_flight, 0);
+    }
+
+    #[tokio::test]
+    async fn update_changes_limits_in_place() {
+        let limits = Limits::new("test", None, None, None);
+        assert!(!limits.is_configured());
+
+        let rate_limit = RateLimitOptions {
+            per_second: 1000.0,
+            burst: 1,
+        };
+        limits.update(None, Some(2), Some(&rate_limit));
+        assert!(limits.is_configured());
+        assert_eq!(limits.stats().max_concurrency, Some(2));
+        let first = limits.acquire("a:80").await.unwrap();
+        // 桶容量为 1, 第二个请求需要等待令牌
+        let second = limits.acquire("a:80").await.unwrap();
+        assert_eq!(limits.stats().throttled_total, 1);
+        assert_eq!(limits.stats().in_flight, 2);
+        first.finish(true);
+        second.finish(true);
+
+        // 打开的熔断在调整阈值后保持
+        let breaker = CircuitBreakerOptions {
+            failure_threshold: 1,
+            open_ms: 60_000,
+            half_open_max_calls: 1,
+        };
+        limits.update(Some(breaker.clone()), Some(2), None);
+        limits.acquire("b:80").await.unwrap().finish(false);
+        limits.update(
+            Some(CircuitBreakerOptions {
+                failure_threshold: 3,
+                ..breaker
+            }),
+            Some(2),
+            None,
+        );
+        assert_eq!(state(&limits, "b:80"), BreakerState::Open);
+
+        limits.update(None, None, None);
+        assert!(!limits.is_configured());
+        assert!(limits.acquire("b:80").await.is_some());
+    }
+}
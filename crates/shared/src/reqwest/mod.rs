@@ -0,0 +1,243 @@
+//! reqwest 工具
+//!
+//! 请求返回非 2xx 状态码时返回 [HttpError::Status]. GET 默认按全局策略 ([RetryPolicy::global]) 重试,
+//! POST 默认不重试, 需要时通过 `*_with` 方法传入策略.
+//!
+//! 大文件使用 [HttpClient::download_to_file] 和 [HttpClient::post_multipart], 不会整个载入内存.
+//!
+//! 在追踪范围内发出的请求会带上 `X-Request-Id` 和 `traceparent` 请求头, 见 [crate::trace].
+//!
+//! 模块级函数使用默认客户端; 对接具体的上游接口时, 应按配置创建独立的 [HttpClient].
+//!
+//! 使用例子:
+//!
+//! ```ignore
+//! use internal_shared::reqwest::{HttpError, get_json};
+//!
+//! match get_json::<Device>(url).await {
+//!     Ok(device) => {}
+//!     Err(HttpError::Status { status, .. }) if status == StatusCode::NOT_FOUND => {}
+//!     Err(e) => return Err(e.into()),
+//! }
+//! ```
+
+mod auth;
+mod client;
+mod error;
+mod limit;
+mod retry;
+mod transfer;
+
+pub use auth::{Auth, AuthOptions, BasicAuth, BearerAuth, HmacSigner, OAuth2ClientCredentials};
+pub use client::{HttpClient, HttpClientOptions, HttpClients};
+pub use error::{HttpError, HttpResult};
+pub use limit::{BreakerState, BreakerStats, CircuitBreakerOptions, LimitStats, RateLimitOptions};
+pub use retry::{RetryKind, RetryPolicy};
+pub use transfer::{Checksum, DownloadOptions, MultipartForm, Progress};
+
+use bytes::Bytes;
+use reqwest::Response;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::LazyLock;
+
+/// 模块级函数使用的默认客户端.
+static DEFAULT: LazyLock<HttpClient> = LazyLock::new(|| {
+    HttpClient::new("default", &HttpClientOptions::default()).expect("创建默认 HTTP 客户端失败")
+});
+
+/// 默认客户端, 使用 [HttpClientOptions::default] 和全局重试策略.
+pub fn default_client() -> &'static HttpClient {
+    &DEFAULT
+}
+
+/// 发送 GET 请求, 按全局策略重试.
+pub async fn get(url: &str) -> HttpResult<Bytes> {
+    DEFAULT.get(url).await
+}
+
+/// 发送 GET 请求, 按指定策略重试.
+pub async fn get_with(url: &str, policy: &RetryPolicy) -> HttpResult<Bytes> {
+    DEFAULT.get_with(url, policy).await
+}
+
+/// 发送 GET 请求, 按全局策略重试, 并将响应体解析为 `R`.
+pub async fn get_json<R: DeserializeOwned>(url: &str) -> HttpResult<R> {
+    DEFAULT.get_json(url).await
+}
+
+/// 发送 POST 请求, 消息体为 json, 不重试.
+pub async fn post_json<T: Serialize + ?Sized>(url: &str, json_data: &T) -> HttpResult<Bytes> {
+    DEFAULT.post_json(url, json_data).await
+}
+
+/// 发送 POST 请求, 消息体为 json, 按指定策略重试.
+///
+/// 只有接口是幂等的 (如带有幂等键) 时才应该重试.
+pub async fn post_json_with<T: Serialize + ?Sized>(
+    url: &str,
+    json_data: &T,
+    policy: &RetryPolicy,
+) -> HttpResult<Bytes> {
+    DEFAULT.post_json_with(url, json_data, policy).await
+}
+
+/// 发送 POST 请求, 消息体为 json, 不重试, 并将响应体解析为 `R`.
+pub async fn post_json_as<T, R>(url: &str, json_data: &T) -> HttpResult<R>
+where
+    T: Serialize + ?Sized,
+    R: DeserializeOwned,
+{
+    DEFAULT.post_json_as(url, json_data).await
+}
+
+/// 下载文件到 `dest`, 按全局策略重试, 返回文件大小. 见 [HttpClient::download_to_file].
+pub async fn download_to_file(
+    url: &str,
+    dest: impl AsRef<Path>,
+    opt: &DownloadOptions,
+) -> HttpResult<u64> {
+    DEFAULT.download_to_file(url, dest, opt).await
+}
+
+/// 发送 multipart 请求, 不重试. 见 [HttpClient::post_multipart].
+pub async fn post_multipart(url: &str, form: MultipartForm) -> HttpResult<Bytes> {
+    DEFAULT.post_multipart(url, form).await
+}
+
+/// 检查状态码并读取响应体.
+async fn read_body(url: &str, response: HttpResult<Response>) -> HttpResult<Bytes> {
+    let response = response?;
+    if !response.status().is_success() {
+        return Err(status_error(url, response).await);
+    }
+    response
+        .bytes()
+        .await
+        .map_err(|e| HttpError::request(url, e))
+}
+
+/// 读取响应体, 转换为 [HttpError::Status].
+async fn status_error(url: &str, response: Response) -> HttpError {
+    let status = response.status();
+    match response.bytes().await {
+        Ok(body) => HttpError::Status {
+            status,
+            url: url.to_owned(),
+            body_snippet: error::snippet(&body),
+        },
+        Err(e) => HttpError::request(url, e),
+    }
+}
+
+/// 将响应体解析为 `R`.
+fn decode<R: DeserializeOwned>(url: &str, body: &[u8]) -> HttpResult<R> {
+    serde_json::from_slice(body).map_err(|source| HttpError::Decode {
+        url: url.to_owned(),
+        body_snippet: error::snippet(body),
+        source,
+    })
+}
+
+/// 转换为小写十六进制.
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .fold(String::with_capacity(bytes.len() * 2), |mut s, b| {
+            let _ = write!(s, "{b:02x}");
+            s
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+    use serde::Deserialize;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[derive(Debug, Deserialize)]
+    struct Device {
+        id: u64,
+    }
+
+    /// 启动只返回一次 `status` 和 `body` 的服务器, 返回地址.
+    async fn serve_once(status: &str, body: String) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let response = format!(
+            "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 4096];
+            let _ = socket.read(&mut buf).await.unwrap();
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+        url
+    }
+
+    fn client() -> HttpClient {
+        let opt = HttpClientOptions {
+            retry: Some(RetryPolicy::none()),
+            ..HttpClientOptions::default()
+        };
+        HttpClient::new("test", &opt).unwrap()
+    }
+
+    #[tokio::test]
+    async fn decodes_json_response() {
+        let url = serve_once("200 OK", r#"{"id":7}"#.to_owned()).await;
+        let device: Device = client().get_json(&url).await.unwrap();
+        assert_eq!(device.id, 7);
+    }
+
+    #[tokio::test]
+    async fn non_success_status_keeps_redacted_truncated_body() {
+        let body = serde_json::json!({"password": "hunter2", "stack": "x".repeat(1000)});
+        let url = serve_once("500 Internal Server Error", body.to_string()).await;
+        let err = client().get_json::<Device>(&url).await.unwrap_err();
+        assert_eq!(err.status(), Some(StatusCode::INTERNAL_SERVER_ERROR));
+        assert_eq!(err.url(), url);
+        let HttpError::Status { body_snippet, .. } = err else {
+            panic!("应为 Status: {err}");
+        };
+        assert!(
+            body_snippet.contains(r#""password":"******""#),
+            "{body_snippet}"
+        );
+        assert!(!body_snippet.contains("hunter2"));
+        assert!(body_snippet.ends_with("..."));
+        assert_eq!(body_snippet.len(), error::SNIPPET_LEN + 3);
+    }
+
+    #[tokio::test]
+    async fn invalid_json_is_a_decode_error() {
+        let url = serve_once("200 OK", r#"{"id":"d1","token":"t0ken"}"#.to_owned()).await;
+        let err = client().get_json::<Device>(&url).await.unwrap_err();
+        assert_eq!(err.status(), None);
+        let HttpError::Decode {
+            body_snippet,
+            source,
+            ..
+        } = err
+        else {
+            panic!("应为 Decode: {err}");
+        };
+        assert!(source.is_data());
+        assert_eq!(body_snippet, r#"{"id":"d1","token":"******"}"#);
+    }
+
+    #[test]
+    fn snippet_truncates_on_char_boundary() {
+        let body = "设".repeat(200);
+        let snippet = error::snippet(body.as_bytes());
+        assert!(snippet.ends_with("..."));
+        assert!(snippet.len() <= error::SNIPPET_LEN + 3);
+        assert_eq!(error::snippet(b"not json"), "not json");
+    }
+}
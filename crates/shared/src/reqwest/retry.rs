@@ -0,0 +1,343 @@
+//! 请求重试
+//!
+//! 按 [RetryPolicy] 重试失败的请求, 间隔按指数增长并加入随机抖动, 支持 `Retry-After` 响应头.
+//! 只应对幂等请求 (如 GET) 使用重试, 非幂等请求需要调用方明确传入策略.
+
+use super::limit::Limits;
+use super::{Auth, HttpError, HttpResult};
+use crate::trace::TraceContext;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::Deserialize;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+static GLOBAL: OnceLock<RetryPolicy> = OnceLock::new();
+
+/// 每次发送都要经过的处理: 熔断限流和认证.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Layers<'a> {
+    /// 熔断、并发上限和限流.
+    pub(crate) limits: Option<&'a Limits>,
+    /// 认证或签名.
+    pub(crate) auth: Option<&'a dyn Auth>,
+}
+
+/// 可以重试的请求错误类型.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetryKind {
+    /// 请求超时.
+    Timeout,
+    /// 连接失败.
+    Connect,
+}
+
+/// 重试策略.
+///
+/// 可以从配置文件反序列化, 没有写的字段使用默认值.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// 最多请求次数, 包括第一次. 为 1 时不重试.
+    pub max_attempts: u32,
+    /// 第一次重试的基础间隔, 毫秒. 之后每次翻倍.
+    pub base_delay_ms: u64,
+    /// 重试间隔的上限, 毫秒, 同时限制 `Retry-After`.
+    pub max_delay_ms: u64,
+    /// 需要重试的状态码.
+    pub retry_statuses: Vec<u16>,
+    /// 需要重试的错误类型.
+    pub retry_errors: Vec<RetryKind>,
+    /// 响应中有 `Retry-After` 时是否按它等待.
+    pub respect_retry_after: bool,
+}
+
+impl Default for RetryPolicy {
+    /// 最多 3 次, 间隔 200ms 起, 上限 10s, 重试 408/429/5xx 网关类错误、超时和连接失败.
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay_ms: 200,
+            max_delay_ms: 10_000,
+            retry_statuses: vec![408, 429, 500, 502, 503, 504],
+            retry_errors: vec![RetryKind::Timeout, RetryKind::Connect],
+            respect_retry_after: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 不重试.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// 设置全局重试策略, 只能设置一次, 应在启动时调用.
+    ///
+    /// # Errors
+    ///
+    /// 已经设置过 (或已经通过 [`RetryPolicy::global`] 使用了默认策略) 时, 返回传入的策略.
+    pub fn set_global(policy: Self) -> Result<(), Self> {
+        GLOBAL.set(policy)
+    }
+
+    /// 全局重试策略, 未设置时使用 [`RetryPolicy::default`].
+    pub fn global() -> &'static Self {
+        GLOBAL.get_or_init(Self::default)
+    }
+
+    /// 第 `attempt` 次重试前的等待时间 (从 1 开始), 在 `[d/2, d]` 之间随机, `d` 为指数间隔.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self
+            .base_delay_ms
+            .saturating_mul(1u64 << attempt.saturating_sub(1).min(32));
+        let delay = exp.min(self.max_delay_ms);
+        let jitter = rand::rng().random_range(delay / 2..=delay);
+        Duration::from_millis(jitter)
+    }
+
+    fn should_retry_status(&self, status: StatusCode) -> bool {
+        self.retry_statuses.contains(&status.as_u16())
+    }
+
+    fn should_retry_error(&self, error: &reqwest::Error) -> bool {
+        self.retry_errors.iter().any(|kind| match kind {
+            RetryKind::Timeout => error.is_timeout(),
+            RetryKind::Connect => error.is_connect(),
+        })
+    }
+
+    /// 根据响应头计算等待时间, 没有 `Retry-After` 时使用指数间隔.
+    fn delay(&self, attempt: u32, headers: Option<&HeaderMap>) -> Duration {
+        let retry_after = headers
+            .filter(|_| self.respect_retry_after)
+            .and_then(retry_after);
+        match retry_after {
+            Some(v) => v.min(Duration::from_millis(self.max_delay_ms)),
+            None => self.backoff(attempt),
+        }
+    }
+}
+
+/// 按策略发送请求, 返回最后一次的响应.
+///
+/// 重试次数用完后, 如果最后一次是可重试的状态码, 仍然返回该响应, 由调用方检查状态.
+///
+/// # Arguments
+///
+/// * `build` - 每次重试都会调用, 创建新的请求.
+/// * `policy` - 重试策略.
+/// * `layers` - 每次发送前检查熔断限流, 并对请求进行认证或签名. 熔断打开时直接返回错误, 不再重试.
+pub(crate) async fn send<F>(
+    build: F,
+    policy: &RetryPolicy,
+    layers: Layers<'_>,
+) -> HttpResult<Response>
+where
+    F: Fn() -> RequestBuilder,
+{
+    let max_attempts = policy.max_attempts.max(1);
+    let mut attempt = 1;
+    loop {
+        let (target, url, result) = execute(build(), layers).await?;
+        if attempt >= max_attempts {
+            return result.map_err(|e| HttpError::request(&url, e));
+        }
+        let delay = match &result {
+            Ok(response) if policy.should_retry_status(response.status()) => {
+                let delay = policy.delay(attempt, Some(response.headers()));
+                log::warn!(
+                    "{target} 第 {attempt} 次请求返回 {}, {delay:?} 后重试",
+                    response.status()
+                );
+                delay
+            }
+            Err(e) if policy.should_retry_error(e) => {
+                let delay = policy.delay(attempt, None);
+                log::warn!("{target} 第 {attempt} 次请求失败: {e}, {delay:?} 后重试");
+                delay
+            }
+            _ => return result.map_err(|e| HttpError::request(&url, e)),
+        };
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// 发送一次, 不重试. 用于无法复制的请求, 如消息体是流.
+pub(crate) async fn send_once(request: RequestBuilder, layers: Layers<'_>) -> HttpResult<Response> {
+    let (_, url, result) = execute(request, layers).await?;
+    result.map_err(|e| HttpError::request(&url, e))
+}
+
+/// 检查熔断限流、加上追踪请求头、认证后发送, 返回 `METHOD URL`、URL 和结果.
+///
+/// 并发名额持有到收到响应头为止, 认证失败或者被取消时不计入熔断.
+/// 调用方已经设置的追踪请求头不会被覆盖.
+async fn execute(
+    request: RequestBuilder,
+    layers: Layers<'_>,
+) -> HttpResult<(String, String, reqwest::Result<Response>)> {
+    let (client, request) = request.build_split();
+    let mut request = request.map_err(|e| HttpError::request("", e))?;
+    let url = request.url().to_string();
+    let host = format!(
+        "{}:{}",
+        request.url().host_str().unwrap_or_default(),
+        request.url().port_or_known_default().unwrap_or_default()
+    );
+    let permit = match layers.limits {
+        Some(limits) => Some(
+            limits
+                .acquire(&host)
+                .await
+                .ok_or_else(|| HttpError::CircuitOpen { url: url.clone() })?,
+        ),
+        None => None,
+    };
+    if let Some(context) = TraceContext::current() {
+        for (name, value) in context.propagation() {
+            if !request.headers().contains_key(name)
+                && let Ok(value) = HeaderValue::from_str(&value)
+            {
+                request.headers_mut().insert(name, value);
+            }
+        }
+    }
+    if let Some(auth) = layers.auth {
+        auth.apply(&mut request)
+            .await
+            .map_err(|e| HttpError::Auth {
+                url: url.clone(),
+                reason: format!("{e:#}"),
+            })?;
+    }
+    let target = format!("{} {url}", request.method());
+    let result = client.execute(request).await;
+    if let Some(permit) = permit {
+        let success = matches!(&result, Ok(response) if !response.status().is_server_error());
+        permit.finish(success);
+    }
+    Ok((target, url, result))
+}
+
+/// 解析 `Retry-After`, 支持秒数和 HTTP 日期两种格式.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let at = DateTime::parse_from_rfc2822(value)
+        .ok()?
+        .with_timezone(&Utc);
+    (at - Utc::now()).to_std().ok().or(Some(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reqwest::{HttpClient, HttpClientOptions};
+    use chrono::TimeDelta;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn headers(retry_after: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(retry_after).unwrap());
+        headers
+    }
+
+    #[test]
+    fn retry_after_parses_seconds_and_dates() {
+        assert_eq!(retry_after(&headers("120")), Some(Duration::from_secs(120)));
+        assert_eq!(retry_after(&headers(" 0 ")), Some(Duration::ZERO));
+
+        let at = (Utc::now() + TimeDelta::seconds(30)).to_rfc2822();
+        let delay = retry_after(&headers(&at)).unwrap();
+        assert!(delay > Duration::from_secs(28) && delay <= Duration::from_secs(30));
+
+        let past = (Utc::now() - TimeDelta::seconds(30)).to_rfc2822();
+        assert_eq!(retry_after(&headers(&past)), Some(Duration::ZERO));
+        assert_eq!(
+            retry_after(&headers("Wed, 21 Oct 2015 07:28:00 GMT")),
+            Some(Duration::ZERO)
+        );
+
+        assert_eq!(retry_after(&headers("soon")), None);
+        assert_eq!(retry_after(&headers("-1")), None);
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+    }
+
+    #[test]
+    fn delay_is_capped_and_can_ignore_retry_after() {
+        let policy = RetryPolicy {
+            base_delay_ms: 100,
+            max_delay_ms: 1_000,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(
+            policy.delay(1, Some(&headers("3600"))),
+            Duration::from_secs(1)
+        );
+        assert_eq!(policy.delay(1, Some(&headers("0"))), Duration::ZERO);
+
+        let ignore = RetryPolicy {
+            respect_retry_after: false,
+            ..policy.clone()
+        };
+        let delay = ignore.delay(1, Some(&headers("0")));
+        assert!(delay >= Duration::from_millis(50) && delay <= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let policy = RetryPolicy {
+            base_delay_ms: 100,
+            max_delay_ms: 1_000,
+            ..RetryPolicy::default()
+        };
+        for (attempt, max) in [(1, 100), (2, 200), (3, 400), (5, 1_000), (100, 1_000)] {
+            let delay = policy.backoff(attempt);
+            let max = Duration::from_millis(max);
+            assert!(delay >= max / 2 && delay <= max, "{attempt}: {delay:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_after_retry_after_response() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let responses = [
+                "HTTP/1.1 503 Service Unavailable\r\nRetry-After: 0\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+            ];
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 4096];
+                let _ = socket.read(&mut buf).await.unwrap();
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        let opt = HttpClientOptions {
+            retry: Some(RetryPolicy {
+                base_delay_ms: 5_000,
+                ..RetryPolicy::default()
+            }),
+            ..HttpClientOptions::default()
+        };
+        let client = HttpClient::new("test", &opt).unwrap();
+        let started = std::time::Instant::now();
+        assert_eq!(client.get(&url).await.unwrap(), "ok");
+        // 按 `Retry-After: 0` 立即重试, 没有等待指数间隔
+        assert!(started.elapsed() < Duration::from_secs(2));
+        server.await.unwrap();
+    }
+}
@@ -0,0 +1,432 @@
+//! 大文件传输
+//!
+//! - [HttpClient::download_to_file]: 边下载边写入 `<dest>.part`, 完成并校验后重命名为 `dest`,
+//!   中途失败时保留临时文件, 开启续传后下次通过 `Range` 从断点继续.
+//! - [HttpClient::post_multipart]: 上传文件和表单字段, 文件按流读取, 不会整个载入内存.
+//!
+//! 两者都可以通过 [Progress] 回调报告进度.
+
+use super::{HttpClient, HttpError, HttpResult, RetryPolicy, hex, read_body, status_error};
+use bytes::Bytes;
+use futures_util::TryStreamExt;
+use reqwest::header::{CONTENT_RANGE, HeaderMap, RANGE};
+use reqwest::multipart::{Form, Part};
+use reqwest::{Body, Method, StatusCode};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+
+/// 进度回调, 参数为已传输的字节数和总字节数 (未知时为 `None`).
+pub type Progress = Arc<dyn Fn(u64, Option<u64>) + Send + Sync>;
+
+/// 下载文件的校验和.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Checksum {
+    /// SHA-256 的十六进制, 不区分大小写.
+    Sha256(String),
+}
+
+/// 下载选项.
+#[derive(Clone, Default)]
+pub struct DownloadOptions {
+    /// 下载完成后校验, 不一致时删除临时文件并返回 [HttpError::Checksum].
+    pub checksum: Option<Checksum>,
+    /// 存在上次留下的临时文件时, 是否通过 `Range` 续传.
+    pub resume: bool,
+    /// 进度回调, 续传时已下载的部分也计算在内.
+    pub progress: Option<Progress>,
+}
+
+/// multipart 表单.
+///
+/// ```ignore
+/// let form = MultipartForm::new()
+///     .text("version", "1.2.0")
+///     .file("firmware", "./firmware.bin")
+///     .on_progress(Arc::new(|sent, total| log::info!("已上传 {sent}/{total:?}")));
+/// client.post_multipart("/firmware", form).await?;
+/// ```
+#[derive(Default)]
+pub struct MultipartForm {
+    fields: Vec<(String, Field)>,
+    progress: Option<Progress>,
+}
+
+enum Field {
+    Text(String),
+    Bytes { file_name: String, data: Vec<u8> },
+    File(PathBuf),
+}
+
+impl MultipartForm {
+    /// 创建空表单.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 添加文本字段.
+    #[must_use]
+    pub fn text(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.fields.push((name.into(), Field::Text(value.into())));
+        self
+    }
+
+    /// 添加内存中的文件.
+    #[must_use]
+    pub fn bytes(
+        mut self,
+        name: impl Into<String>,
+        file_name: impl Into<String>,
+        data: impl Into<Vec<u8>>,
+    ) -> Self {
+        let field = Field::Bytes {
+            file_name: file_name.into(),
+            data: data.into(),
+        };
+        self.fields.push((name.into(), field));
+        self
+    }
+
+    /// 添加磁盘上的文件, 发送时按流读取. 文件名取路径的最后一部分, 类型按扩展名推断.
+    #[must_use]
+    pub fn file(mut self, name: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        self.fields.push((name.into(), Field::File(path.into())));
+        self
+    }
+
+    /// 设置进度回调, 只统计文件的字节数, 不含文本字段. 内存中的文件在开始发送前一次计入.
+    #[must_use]
+    pub fn on_progress(mut self, progress: Progress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// 打开所有文件, 转换为 reqwest 的表单.
+    async fn into_form(self, url: &str) -> HttpResult<Form> {
+        // 先打开所有文件得到总大小, 进度回调需要它
+        let mut opened = Vec::with_capacity(self.fields.len());
+        let mut total = 0;
+        for (name, field) in self.fields {
+            let file = match &field {
+                Field::File(path) => {
+                    let file = File::open(path)
+                        .await
+                        .map_err(|e| HttpError::io(url, path, e))?;
+                    let len = file
+                        .metadata()
+                        .await
+                        .map_err(|e| HttpError::io(url, path, e))?
+                        .len();
+                    total += len;
+                    Some((file, len))
+                }
+                Field::Bytes { data, .. } => {
+                    total += data.len() as u64;
+                    None
+                }
+                Field::Text(_) => None,
+            };
+            opened.push((name, field, file));
+        }
+
+        let sent = Arc::new(AtomicU64::new(0));
+        let mut form = Form::new();
+        for (name, field, file) in opened {
+            let part = match (field, file) {
+                (Field::File(path), Some((file, len))) => {
+                    let sent = sent.clone();
+                    let progress = self.progress.clone();
+                    let stream = ReaderStream::new(file).inspect_ok(move |chunk| {
+                        let n = chunk.len() as u64;
+                        let sent = sent.fetch_add(n, Ordering::Relaxed) + n;
+                        if let Some(progress) = &progress {
+                            progress(sent, Some(total));
+                        }
+                    });
+                    let file_name = path
+                        .file_name()
+                        .map(|n| n.to_string_lossy().into_owned())
+                        .unwrap_or_default();
+                    let mime = mime_guess::from_path(&path).first_or_octet_stream();
+                    Part::stream_with_length(Body::wrap_stream(stream), len)
+                        .file_name(file_name)
+                        .mime_str(mime.as_ref())
+                        .map_err(|e| HttpError::request(url, e))?
+                }
+                (Field::Bytes { file_name, data }, _) => {
+                    let n = data.len() as u64;
+                    let sent = sent.fetch_add(n, Ordering::Relaxed) + n;
+                    if let Some(progress) = &self.progress {
+                        progress(sent, Some(total));
+                    }
+                    Part::bytes(data).file_name(file_name)
+                }
+                (Field::Text(value), _) => Part::text(value),
+                (Field::File(_), None) => continue,
+            };
+            form = form.part(name, part);
+        }
+        Ok(form)
+    }
+}
+
+impl HttpClient {
+    /// 下载文件到 `dest`, 按客户端的策略重试, 返回文件大小.
+    ///
+    /// 响应体边下载边写入 `<dest>.part`, 完成后重命名为 `dest`, 不会留下不完整的 `dest`.
+    /// 重试只针对发出请求和响应头; 读取响应体中途失败时直接返回错误, 保留临时文件以便续传.
+    ///
+    /// 不受 `timeout_ms` 限制, 下载多久都不会被中断; 两次读取之间超过 `read_timeout_ms`
+    /// (没有配置时为 `timeout_ms`) 没有收到数据时视为连接卡住, 返回错误.
+    ///
+    /// # Errors
+    ///
+    /// 请求失败、状态码不是 2xx、读写文件失败或校验和不一致时返回错误.
+    pub async fn download_to_file(
+        &self,
+        path: &str,
+        dest: impl AsRef<Path>,
+        opt: &DownloadOptions,
+    ) -> HttpResult<u64> {
+        let url = self.url(path);
+        let dest = dest.as_ref();
+        let temp = part_path(dest);
+        let mut offset = match fs::metadata(&temp).await {
+            Ok(meta) if opt.resume => meta.len(),
+            _ => 0,
+        };
+
+        let mut response = loop {
+            let mut request = self.stream_request(Method::GET, path);
+            if offset > 0 {
+                request = request.header(RANGE, format!("bytes={offset}-"));
+            }
+            let response = self.send(request, self.retry_policy()).await?;
+            match response.status() {
+                StatusCode::PARTIAL_CONTENT if offset > 0 => {
+                    if range_start(response.headers()) == Some(offset) {
+                        break response;
+                    }
+                    log::warn!("[{}] {url} 返回的范围与请求不一致, 重新下载", self.name());
+                    offset = 0;
+                }
+                StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => {
+                    log::warn!("[{}] {url} 不能从 {offset} 续传, 重新下载", self.name());
+                    offset = 0;
+                }
+                status if status.is_success() && status != StatusCode::PARTIAL_CONTENT => {
+                    // 服务端不支持 Range 时返回完整内容
+                    offset = 0;
+                    break response;
+                }
+                _ => return Err(status_error(&url, response).await),
+            }
+        };
+
+        let total = response.content_length().map(|len| len + offset);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(offset > 0)
+            .truncate(offset == 0)
+            .open(&temp)
+            .await
+            .map_err(|e| HttpError::io(&url, &temp, e))?;
+        let mut hasher = Sha256::new();
+        if offset > 0 && opt.checksum.is_some() {
+            hash_file(&temp, &mut hasher)
+                .await
+                .map_err(|e| HttpError::io(&url, &temp, e))?;
+        }
+        if offset > 0 {
+            log::info!("[{}] {url} 从 {offset} 字节处续传", self.name());
+        }
+
+        let mut written = offset;
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| HttpError::request(&url, e))?
+        {
+            file.write_all(&chunk)
+                .await
+                .map_err(|e| HttpError::io(&url, &temp, e))?;
+            if opt.checksum.is_some() {
+                hasher.update(&chunk);
+            }
+            written += chunk.len() as u64;
+            if let Some(progress) = &opt.progress {
+                progress(written, total);
+            }
+        }
+        file.sync_all()
+            .await
+            .map_err(|e| HttpError::io(&url, &temp, e))?;
+        drop(file);
+
+        if let Some(Checksum::Sha256(expected)) = &opt.checksum {
+            let actual = hex(&hasher.finalize());
+            if !actual.eq_ignore_ascii_case(expected) {
+                let _ = fs::remove_file(&temp).await;
+                return Err(HttpError::Checksum {
+                    url,
+                    expected: expected.clone(),
+                    actual,
+                });
+            }
+        }
+        fs::rename(&temp, dest)
+            .await
+            .map_err(|e| HttpError::io(&url, dest, e))?;
+        log::info!(
+            "[{}] 已下载 {url} 到 {}, 共 {written} 字节",
+            self.name(),
+            dest.display()
+        );
+        Ok(written)
+    }
+
+    /// 发送 multipart 请求, 不重试.
+    ///
+    /// 和 [HttpClient::download_to_file] 一样不受 `timeout_ms` 限制, 只检查两次读写之间的超时.
+    ///
+    /// # Errors
+    ///
+    /// 打开文件失败、请求失败或状态码不是 2xx 时返回错误.
+    pub async fn post_multipart(&self, path: &str, form: MultipartForm) -> HttpResult<Bytes> {
+        let url = self.url(path);
+        let form = form.into_form(&url).await?;
+        let request = self.stream_request(Method::POST, path).multipart(form);
+        let response = self.send(request, &RetryPolicy::none()).await;
+        read_body(&url, response).await
+    }
+}
+
+/// 下载时使用的临时文件, `dest` 后加上 `.part`.
+fn part_path(dest: &Path) -> PathBuf {
+    let mut path = dest.as_os_str().to_owned();
+    path.push(".part");
+    PathBuf::from(path)
+}
+
+/// 解析 `Content-Range: bytes start-end/total` 中的 `start`.
+fn range_start(headers: &HeaderMap) -> Option<u64> {
+    let value = headers.get(CONTENT_RANGE)?.to_str().ok()?;
+    let range = value.strip_prefix("bytes ")?;
+    range.split('-').next()?.trim().parse().ok()
+}
+
+/// 将已下载的部分计入摘要.
+async fn hash_file(path: &Path, hasher: &mut Sha256) -> std::io::Result<()> {
+    let mut file = File::open(path).await?;
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        hasher.update(&buf[..n]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reqwest::HttpClientOptions;
+    use reqwest::header::HeaderValue;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+
+    fn headers(content_range: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_RANGE, HeaderValue::from_str(content_range).unwrap());
+        headers
+    }
+
+    #[test]
+    fn range_start_parses_content_range() {
+        assert_eq!(range_start(&headers("bytes 100-199/200")), Some(100));
+        assert_eq!(range_start(&headers("bytes 0-0/*")), Some(0));
+        assert_eq!(range_start(&headers("bytes  42 -99/100")), Some(42));
+    }
+
+    #[test]
+    fn range_start_rejects_invalid_values() {
+        assert_eq!(range_start(&HeaderMap::new()), None);
+        assert_eq!(range_start(&headers("bytes */200")), None);
+        assert_eq!(range_start(&headers("items 0-9/10")), None);
+        assert_eq!(range_start(&headers("bytes x-9/10")), None);
+    }
+
+    /// 慢慢返回响应体, 总时间超过 `timeout_ms`, 但每次读取的间隔都小于它.
+    async fn slow_server(chunks: usize, interval: Duration) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 4096];
+            let _ = socket.read(&mut buf).await.unwrap();
+            let header =
+                format!("HTTP/1.1 200 OK\r\nContent-Length: {chunks}\r\nConnection: close\r\n\r\n");
+            socket.write_all(header.as_bytes()).await.unwrap();
+            for _ in 0..chunks {
+                tokio::time::sleep(interval).await;
+                socket.write_all(b"x").await.unwrap();
+            }
+        });
+        format!("http://{addr}/file")
+    }
+
+    fn client(timeout_ms: u64, read_timeout_ms: Option<u64>) -> HttpClient {
+        let opt = HttpClientOptions {
+            timeout_ms,
+            read_timeout_ms,
+            retry: Some(RetryPolicy::none()),
+            ..HttpClientOptions::default()
+        };
+        HttpClient::new("test", &opt).unwrap()
+    }
+
+    #[tokio::test]
+    async fn download_is_not_limited_by_total_timeout() {
+        let url = slow_server(6, Duration::from_millis(100)).await;
+        let dir = std::env::temp_dir().join(format!("download-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dest = dir.join("slow.bin");
+
+        let size = client(300, None)
+            .download_to_file(&url, &dest, &DownloadOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(size, 6);
+        assert_eq!(std::fs::read(&dest).unwrap(), b"xxxxxx");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn download_fails_when_stalled() {
+        let url = slow_server(2, Duration::from_millis(500)).await;
+        let dir = std::env::temp_dir().join(format!("download-stall-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dest = dir.join("stalled.bin");
+
+        let result = client(30_000, Some(100))
+            .download_to_file(&url, &dest, &DownloadOptions::default())
+            .await;
+        assert!(result.is_err());
+        assert!(!dest.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn part_path_appends_suffix() {
+        assert_eq!(
+            part_path(Path::new("/tmp/firmware.bin")),
+            PathBuf::from("/tmp/firmware.bin.part")
+        );
+    }
+}
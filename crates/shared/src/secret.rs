@@ -0,0 +1,206 @@
+//! 配置文件中的加密值
+//!
+//! 密码等敏感配置可以写成 `ENC[AES256_GCM,<base64>]`, 加载 yaml 时自动解密.
+//! `<base64>` 是 12 字节随机 nonce 加上密文 (含认证标签) 的 base64 编码.
+//!
+//! 密钥是 32 字节的 base64 编码, 按以下顺序读取:
+//! 1. 环境变量 `APP_SECRET_KEY`.
+//! 2. 环境变量 `APP_SECRET_KEY_FILE` 指向的文件, 首尾空白会被忽略.
+//!
+//! 使用例子:
+//!
+//! ```ignore
+//! use internal_shared::secret::SecretKey;
+//!
+//! let key = SecretKey::from_env()?;
+//! let value = key.encrypt("123456")?; // ENC[AES256_GCM,...]
+//! assert_eq!(key.decrypt(&value)?, "123456");
+//! ```
+
+use crate::yaml::for_each_string;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::{Context, Result, anyhow, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde_yaml::Value;
+use std::{env, fs};
+
+/// 保存密钥的环境变量.
+pub const KEY_ENV: &str = "APP_SECRET_KEY";
+/// 保存密钥文件路径的环境变量.
+pub const KEY_FILE_ENV: &str = "APP_SECRET_KEY_FILE";
+
+const PREFIX: &str = "ENC[AES256_GCM,";
+const SUFFIX: &str = "]";
+const NONCE_LEN: usize = 12;
+
+/// AES-256-GCM 密钥.
+#[derive(Clone)]
+pub struct SecretKey {
+    cipher: Aes256Gcm,
+}
+
+impl SecretKey {
+    /// 从 `APP_SECRET_KEY` 或 `APP_SECRET_KEY_FILE` 读取密钥.
+    ///
+    /// # Errors
+    ///
+    /// 两个环境变量都没有设置, 文件读取失败或密钥格式错误时返回错误.
+    pub fn from_env() -> Result<Self> {
+        if let Ok(key) = env::var(KEY_ENV) {
+            return Self::from_base64(&key).with_context(|| format!("{KEY_ENV} 无效"));
+        }
+        if let Ok(path) = env::var(KEY_FILE_ENV) {
+            let key =
+                fs::read_to_string(&path).with_context(|| format!("读取密钥文件 {path} 失败"))?;
+            return Self::from_base64(&key).with_context(|| format!("密钥文件 {path} 无效"));
+        }
+        bail!("没有设置 {KEY_ENV} 或 {KEY_FILE_ENV}")
+    }
+
+    /// 从 base64 编码的 32 字节密钥创建.
+    ///
+    /// # Errors
+    ///
+    /// 不是合法的 base64 或长度不是 32 字节时返回错误.
+    pub fn from_base64(key: &str) -> Result<Self> {
+        let bytes = STANDARD
+            .decode(key.trim())
+            .context("密钥不是合法的 base64")?;
+        if bytes.len() != 32 {
+            bail!("密钥长度应为 32 字节, 实际为 {} 字节", bytes.len());
+        }
+        Ok(Self {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&bytes)),
+        })
+    }
+
+    /// 生成随机密钥, 返回 base64 编码.
+    pub fn generate() -> String {
+        STANDARD.encode(Aes256Gcm::generate_key(OsRng))
+    }
+
+    /// 加密, 返回 `ENC[AES256_GCM,...]` 形式的字符串.
+    ///
+    /// # Errors
+    ///
+    /// 加密失败时返回错误.
+    pub fn encrypt(&self, plaintext: &str) -> Result<String> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| anyhow!("加密失败"))?;
+        let mut data = nonce.to_vec();
+        data.extend_from_slice(&ciphertext);
+        Ok(format!("{PREFIX}{}{SUFFIX}", STANDARD.encode(data)))
+    }
+
+    /// 解密 `ENC[AES256_GCM,...]` 形式的字符串.
+    ///
+    /// # Errors
+    ///
+    /// 格式错误、密钥不匹配或内容被篡改时返回错误.
+    pub fn decrypt(&self, value: &str) -> Result<String> {
+        let encoded = value
+            .trim()
+            .strip_prefix(PREFIX)
+            .and_then(|v| v.strip_suffix(SUFFIX))
+            .ok_or_else(|| anyhow!("加密值应为 {PREFIX}...{SUFFIX} 格式"))?;
+        let data = STANDARD
+            .decode(encoded)
+            .context("加密值不是合法的 base64")?;
+        if data.len() <= NONCE_LEN {
+            bail!("加密值长度不足");
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("解密失败, 密钥不匹配或内容被篡改"))?;
+        String::from_utf8(plaintext).context("解密结果不是合法的 UTF-8")
+    }
+}
+
+/// 是否是 `ENC[AES256_GCM,...]` 形式的加密值.
+pub fn is_encrypted(value: &str) -> bool {
+    let value = value.trim();
+    value.starts_with(PREFIX) && value.ends_with(SUFFIX)
+}
+
+/// 解密 yaml 中所有的加密值.
+///
+/// 只有存在加密值时才读取密钥, 没有加密值的配置不需要设置密钥.
+pub(crate) fn decrypt_yaml(value: &mut Value) -> Result<()> {
+    let mut key: Option<SecretKey> = None;
+    for_each_string(value, &mut |s, path| {
+        if !is_encrypted(s) {
+            return Ok(());
+        }
+        let key = match &mut key {
+            Some(key) => key,
+            None => key.insert(SecretKey::from_env().context("配置中有加密值, 但无法读取密钥")?),
+        };
+        *s = key
+            .decrypt(s)
+            .with_context(|| format!("解密字段 {path} 失败"))?;
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> SecretKey {
+        SecretKey::from_base64(&SecretKey::generate()).unwrap()
+    }
+
+    #[test]
+    fn encrypt_decrypt_round_trip() {
+        let key = key();
+        let encrypted = key.encrypt("p@ss: \"word\" 密码").unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert_eq!(key.decrypt(&encrypted).unwrap(), "p@ss: \"word\" 密码");
+        // 每次使用新的 nonce
+        assert_ne!(key.encrypt("same").unwrap(), key.encrypt("same").unwrap());
+        assert_eq!(
+            key.decrypt(&format!(" {encrypted}\n")).unwrap(),
+            "p@ss: \"word\" 密码"
+        );
+    }
+
+    #[test]
+    fn decrypt_rejects_wrong_key_and_tampering() {
+        let encrypted = key().encrypt("secret").unwrap();
+        assert!(key().decrypt(&encrypted).is_err());
+
+        let key = key();
+        let encrypted = key.encrypt("secret").unwrap();
+        let encoded = &encrypted[PREFIX.len()..encrypted.len() - SUFFIX.len()];
+        let mut data = STANDARD.decode(encoded).unwrap();
+        *data.last_mut().unwrap() ^= 1;
+        let tampered = format!("{PREFIX}{}{SUFFIX}", STANDARD.encode(data));
+        assert!(key.decrypt(&tampered).is_err());
+
+        for invalid in ["secret", "ENC[AES256_GCM,***]", "ENC[AES256_GCM,AAAA]"] {
+            assert!(key.decrypt(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn from_base64_checks_length() {
+        assert!(SecretKey::from_base64(&STANDARD.encode([0u8; 16])).is_err());
+        assert!(SecretKey::from_base64("not base64!").is_err());
+        assert!(SecretKey::from_base64(&format!("{}\n", STANDARD.encode([7u8; 32]))).is_ok());
+    }
+
+    #[test]
+    fn decrypt_yaml_without_encrypted_values_needs_no_key() {
+        let mut value: Value = serde_yaml::from_str("a: plain\nb: [1, two]\n").unwrap();
+        let original = value.clone();
+        decrypt_yaml(&mut value).unwrap();
+        assert_eq!(value, original);
+    }
+}
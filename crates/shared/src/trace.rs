@@ -0,0 +1,282 @@
+//! 请求链路追踪
+//!
+//! 每个请求 (HTTP 请求、MQTT 消息) 在处理时带一个 trace ID, 保存在 tokio 的 task-local 中:
+//! - 日志的每一行都会带上当前的 trace ID.
+//! - 通过 [crate::reqwest] 发出的请求会带上 `X-Request-Id` 和 `traceparent` 请求头.
+//! - 发布 MQTT 消息时放在用户属性 (user property) 中, 键和请求头相同.
+//!
+//! trace ID 按以下顺序获取:
+//! 1. W3C `traceparent` 中的 trace-id.
+//! 2. `X-Request-Id`, 只接受 1 到 128 个字母、数字或 `-_.:`.
+//! 3. 都没有时随机生成 32 位十六进制.
+//!
+//! 使用例子:
+//!
+//! ```ignore
+//! use internal_shared::trace::{self, TraceContext};
+//!
+//! TraceContext::from_headers(request.headers())
+//!     .scope(async {
+//!         log::info!("处理请求"); // 日志中带 trace ID
+//!         trace::spawn(async { /* 新任务中也能拿到同一个 trace ID */ });
+//!     })
+//!     .await;
+//! ```
+//!
+//! 注意 task-local 不会自动传给 `tokio::spawn` 出来的任务, 需要改用 [spawn].
+
+use reqwest::header::HeaderMap;
+use std::fmt;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+
+/// `X-Request-Id` 请求头, 也是 MQTT 用户属性的键.
+pub const REQUEST_ID: &str = "x-request-id";
+/// W3C Trace Context 的 `traceparent` 请求头, 也是 MQTT 用户属性的键.
+pub const TRACEPARENT: &str = "traceparent";
+
+/// `X-Request-Id` 的长度上限.
+const REQUEST_ID_MAX_LEN: usize = 128;
+
+tokio::task_local! {
+    static CURRENT: TraceContext;
+}
+
+/// 一次请求的追踪信息.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceContext {
+    trace_id: Arc<str>,
+}
+
+impl TraceContext {
+    /// 随机生成 trace ID.
+    pub fn generate() -> Self {
+        Self::with_id(&format!("{:032x}", rand::random::<u128>().max(1)))
+    }
+
+    /// 使用指定的 trace ID, 不做校验.
+    pub fn with_id(trace_id: &str) -> Self {
+        Self {
+            trace_id: trace_id.into(),
+        }
+    }
+
+    /// 解析 `traceparent` (`{version}-{trace-id}-{parent-id}-{flags}`), 只取 trace-id.
+    pub fn from_traceparent(value: &str) -> Option<Self> {
+        let mut parts = value.trim().split('-');
+        let version = parts.next()?;
+        let trace_id = parts.next()?;
+        let parent_id = parts.next()?;
+        let flags = parts.next()?;
+        let valid = is_hex(version, 2)
+            && version != "ff"
+            && is_hex(trace_id, 32)
+            && trace_id.bytes().any(|b| b != b'0')
+            && is_hex(parent_id, 16)
+            && is_hex(flags, 2)
+            // 版本 00 不能有更多字段
+            && (version != "00" || parts.next().is_none());
+        valid.then(|| Self::with_id(trace_id))
+    }
+
+    /// 使用 `X-Request-Id` 作为 trace ID, 格式不符时返回 `None`.
+    pub fn from_request_id(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let valid = !value.is_empty()
+            && value.len() <= REQUEST_ID_MAX_LEN
+            && value
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"-_.:".contains(&b));
+        valid.then(|| Self::with_id(value))
+    }
+
+    /// 从 HTTP 请求头获取, 都没有时生成新的.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let get = |name| headers.get(name).and_then(|v| v.to_str().ok());
+        get(TRACEPARENT)
+            .and_then(Self::from_traceparent)
+            .or_else(|| get(REQUEST_ID).and_then(Self::from_request_id))
+            .unwrap_or_else(Self::generate)
+    }
+
+    /// 从 MQTT v5 的用户属性获取, 都没有时生成新的.
+    pub fn from_user_properties(properties: &[(String, String)]) -> Self {
+        let get = |name: &str| {
+            properties
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.as_str())
+        };
+        get(TRACEPARENT)
+            .and_then(Self::from_traceparent)
+            .or_else(|| get(REQUEST_ID).and_then(Self::from_request_id))
+            .unwrap_or_else(Self::generate)
+    }
+
+    /// 当前任务的追踪信息.
+    pub fn current() -> Option<Self> {
+        CURRENT.try_with(Clone::clone).ok()
+    }
+
+    /// trace ID.
+    pub fn trace_id(&self) -> &str {
+        &self.trace_id
+    }
+
+    /// 向下游传递的 `traceparent`, 每次生成新的 parent-id.
+    ///
+    /// trace ID 不是 32 位十六进制 (来自 `X-Request-Id`) 时返回 `None`, 只传 `X-Request-Id`.
+    pub fn traceparent(&self) -> Option<String> {
+        is_hex(&self.trace_id, 32).then(|| {
+            format!(
+                "00-{}-{:016x}-01",
+                self.trace_id.to_ascii_lowercase(),
+                rand::random::<u64>().max(1)
+            )
+        })
+    }
+
+    /// 向下游传递的请求头或用户属性.
+    pub fn propagation(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![(REQUEST_ID, self.trace_id.to_string())];
+        if let Some(traceparent) = self.traceparent() {
+            fields.push((TRACEPARENT, traceparent));
+        }
+        fields
+    }
+
+    /// 在这个追踪信息下运行 `future`.
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        CURRENT.scope(self, future).await
+    }
+}
+
+impl fmt::Display for TraceContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.trace_id)
+    }
+}
+
+/// 当前任务的 trace ID, 不在追踪范围内时返回 `None`.
+pub fn current_id() -> Option<Arc<str>> {
+    CURRENT.try_with(|c| c.trace_id.clone()).ok()
+}
+
+/// 和 `tokio::spawn` 相同, 新任务沿用当前的追踪信息.
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    match TraceContext::current() {
+        Some(context) => tokio::spawn(context.scope(future)),
+        None => tokio::spawn(future),
+    }
+}
+
+/// 是否为指定长度的十六进制字符串.
+fn is_hex(value: &str, len: usize) -> bool {
+    value.len() == len && value.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+    #[test]
+    fn from_traceparent_accepts_valid_values() {
+        let value = format!("00-{TRACE_ID}-00f067aa0ba902b7-01");
+        assert_eq!(
+            TraceContext::from_traceparent(&value).unwrap().trace_id(),
+            TRACE_ID
+        );
+        // 未来的版本可以有更多字段
+        let value = format!("01-{TRACE_ID}-00f067aa0ba902b7-00-extra");
+        assert!(TraceContext::from_traceparent(&value).is_some());
+    }
+
+    #[test]
+    fn from_traceparent_rejects_invalid_values() {
+        let invalid = [
+            String::new(),
+            format!("ff-{TRACE_ID}-00f067aa0ba902b7-01"),
+            format!("00-{}-00f067aa0ba902b7-01", "0".repeat(32)),
+            format!("00-{TRACE_ID}-00f067aa0ba902b7-01-extra"),
+            format!("00-{TRACE_ID}-00f067aa0ba902b7"),
+            format!("00-{}-00f067aa0ba902b7-01", &TRACE_ID[1..]),
+            format!("00-{TRACE_ID}-00f067aa0ba902bz-01"),
+            format!("0-{TRACE_ID}-00f067aa0ba902b7-01"),
+        ];
+        for value in invalid {
+            assert!(TraceContext::from_traceparent(&value).is_none(), "{value}");
+        }
+    }
+
+    #[test]
+    fn from_request_id_checks_charset_and_length() {
+        assert_eq!(
+            TraceContext::from_request_id(" req-1_a.b:c ")
+                .unwrap()
+                .trace_id(),
+            "req-1_a.b:c"
+        );
+        assert!(TraceContext::from_request_id("").is_none());
+        assert!(TraceContext::from_request_id("a b").is_none());
+        assert!(TraceContext::from_request_id(&"a".repeat(REQUEST_ID_MAX_LEN + 1)).is_none());
+    }
+
+    #[test]
+    fn from_headers_prefers_traceparent() {
+        let mut headers = HeaderMap::new();
+        headers.insert(REQUEST_ID, HeaderValue::from_static("req-1"));
+        assert_eq!(TraceContext::from_headers(&headers).trace_id(), "req-1");
+
+        let traceparent = format!("00-{TRACE_ID}-00f067aa0ba902b7-01");
+        headers.insert(TRACEPARENT, HeaderValue::from_str(&traceparent).unwrap());
+        assert_eq!(TraceContext::from_headers(&headers).trace_id(), TRACE_ID);
+
+        let generated = TraceContext::from_headers(&HeaderMap::new());
+        assert!(is_hex(generated.trace_id(), 32));
+    }
+
+    #[test]
+    fn from_user_properties_ignores_key_case() {
+        let properties = vec![("X-Request-Id".to_owned(), "mqtt-1".to_owned())];
+        assert_eq!(
+            TraceContext::from_user_properties(&properties).trace_id(),
+            "mqtt-1"
+        );
+    }
+
+    #[test]
+    fn propagation_keeps_trace_id_with_new_parent() {
+        let context = TraceContext::with_id(TRACE_ID);
+        let traceparent = context.traceparent().unwrap();
+        assert_eq!(
+            TraceContext::from_traceparent(&traceparent),
+            Some(context.clone())
+        );
+        assert_ne!(context.traceparent(), Some(traceparent));
+        let fields = context.propagation();
+        assert_eq!(fields[0], (REQUEST_ID, TRACE_ID.to_owned()));
+        assert_eq!(fields[1].0, TRACEPARENT);
+
+        let request_id = TraceContext::with_id("req-1");
+        assert_eq!(request_id.traceparent(), None);
+        assert_eq!(request_id.propagation(), [(REQUEST_ID, "req-1".to_owned())]);
+    }
+
+    #[tokio::test]
+    async fn scope_is_inherited_by_spawn() {
+        assert_eq!(current_id(), None);
+        let context = TraceContext::with_id("scoped");
+        let inner = context
+            .scope(async { spawn(async { current_id() }).await.unwrap() })
+            .await;
+        assert_eq!(inner.as_deref(), Some("scoped"));
+        assert_eq!(current_id(), None);
+    }
+}
@@ -1,13 +1,347 @@
 //! yaml 工具
+//!
+//! 加载配置文件时支持:
+//! - 环境变量插值: `${VAR}` 在变量未设置时报错, `${VAR:-default}` 在变量未设置或为空时使用默认值,
+//!   `$${` 输出字面量 `${`. 插值在解析 YAML 之后进行, 只替换字符串值, 注释和键不受影响,
+//!   变量的值中有引号、换行等字符也不会破坏文档结构. 结果总是字符串, 数字等类型的字段用环境变量覆盖.
+//! - 分层加载 (见 [from_layered_yaml]): `mysql.yaml` -> `mysql.{APP_ENV}.yaml` -> 环境变量覆盖.
+//! - 加密值: 合并完成后, `ENC[AES256_GCM,...]` 形式的字符串会被解密, 见 [crate::secret].
 
-use anyhow::Result;
+use crate::secret::decrypt_yaml;
+use anyhow::{Context, Result, bail};
 use serde::de::DeserializeOwned;
-use std::fs::File;
-use std::io::BufReader;
+use serde_yaml::{Mapping, Value};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 /// 从 yaml 文件加载配置, 并转到对应的实例.
+///
+/// 文件内容会先进行环境变量插值, 其中的加密值会被解密.
 pub fn from_yaml_file<T: DeserializeOwned>(path: &str) -> Result<T> {
-    let file = File::open(path)?;
-    let options = serde_yaml::from_reader(BufReader::new(file))?;
-    Ok(options)
+    let mut value = read_yaml(Path::new(path))?;
+    decrypt_yaml(&mut value).with_context(|| format!("配置文件 {path} 解密失败"))?;
+    Ok(serde_yaml::from_value(value)?)
+}
+
+/// 分层加载 yaml 配置, 合并后转到对应的实例.
+///
+/// 按以下顺序合并, 后面的覆盖前面的:
+/// 1. `path` 指定的文件, 如 `config/mysql.yaml`.
+/// 2. 同目录下对应环境的文件, 如 `config/mysql.production.yaml`, 不存在时跳过. 环境见 [app_env].
+/// 3. 以 `{env_prefix}__` 开头的环境变量, 多级字段用 `__` 分隔,
+///    如 `MYSQL__HOST` 覆盖 `host`, `MQTT__SUBSCRIBES` 覆盖 `subscribes`.
+///
+/// 对象按字段递归合并, 其他值 (包括数组) 直接替换. 合并后解密其中的加密值.
+///
+/// # Arguments
+///
+/// * `path` - 基础配置文件路径.
+/// * `env_prefix` - 环境变量前缀, 如 `MYSQL`.
+///
+/// # Errors
+///
+/// 文件读取失败、插值失败、YAML 解析失败或解密失败时返回错误.
+pub fn from_layered_yaml<T: DeserializeOwned>(path: &str, env_prefix: &str) -> Result<T> {
+    let value = load_layered(path, env_prefix)?;
+    serde_yaml::from_value(value).with_context(|| format!("解析配置文件 {path} 失败"))
+}
+
+/// 当前运行环境, 取自 `APP_ENV`, 默认 `development`.
+pub fn app_env() -> String {
+    env::var("APP_ENV").unwrap_or_else(|_| "development".into())
+}
+
+/// 替换文本中的 `${VAR}` 和 `${VAR:-default}`.
+///
+/// # Errors
+///
+/// 变量未设置且没有默认值, 或者 `${` 没有闭合时返回错误.
+pub fn interpolate(text: &str) -> Result<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        if let Some(escaped) = tail.strip_prefix("$${") {
+            out.push_str("${");
+            rest = escaped;
+        } else if let Some(expr) = tail.strip_prefix("${") {
+            let Some(end) = expr.find('}') else {
+                bail!("`${{` 没有闭合");
+            };
+            out.push_str(&resolve(&expr[..end])?);
+            rest = &expr[end + 1..];
+        } else {
+            out.push('$');
+            rest = &tail[1..];
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// 对 yaml 中所有的字符串值调用 `f`, 第二个参数是字段路径, 如 `auth.token`, `subscribes[0]`.
+pub(crate) fn for_each_string(
+    value: &mut Value,
+    f: &mut impl FnMut(&mut String, &str) -> Result<()>,
+) -> Result<()> {
+    visit_strings(value, f, &mut String::new())
+}
+
+fn visit_strings(
+    value: &mut Value,
+    f: &mut impl FnMut(&mut String, &str) -> Result<()>,
+    path: &mut String,
+) -> Result<()> {
+    match value {
+        Value::String(s) => f(s, path)?,
+        Value::Mapping(map) => {
+            for (k, v) in map.iter_mut() {
+                let len = path.len();
+                if !path.is_empty() {
+                    path.push('.');
+                }
+                path.push_str(k.as_str().unwrap_or("?"));
+                visit_strings(v, f, path)?;
+                path.truncate(len);
+            }
+        }
+        Value::Sequence(items) => {
+            for (i, v) in items.iter_mut().enumerate() {
+                let len = path.len();
+                path.push_str(&format!("[{i}]"));
+                visit_strings(v, f, path)?;
+                path.truncate(len);
+            }
+        }
+        Value::Tagged(tagged) => visit_strings(&mut tagged.value, f, path)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// 分层加载并合并, 返回合并后的 yaml 值.
+pub(crate) fn load_layered(path: &str, env_prefix: &str) -> Result<Value> {
+    let path = Path::new(path);
+    let mut value = read_yaml(path)?;
+
+    let profile = profile_path(path, &app_env());
+    if profile.exists() {
+        merge(&mut value, read_yaml(&profile)?);
+    }
+
+    apply_env_overrides(&mut value, env_prefix, env::vars());
+    decrypt_yaml(&mut value).with_context(|| format!("配置文件 {} 解密失败", path.display()))?;
+    Ok(value)
+}
+
+/// 读取文件, 解析为 yaml 值后对字符串值插值.
+fn read_yaml(path: &Path) -> Result<Value> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("读取配置文件 {} 失败", path.display()))?;
+    let mut value: Value = serde_yaml::from_str(&text)
+        .with_context(|| format!("解析配置文件 {} 失败", path.display()))?;
+    for_each_string(&mut value, &mut |s, field| {
+        if s.contains('$') {
+            *s = interpolate(s).with_context(|| format!("字段 {field}"))?;
+        }
+        Ok(())
+    })
+    .with_context(|| format!("配置文件 {} 插值失败", path.display()))?;
+    Ok(value)
+}
+
+/// 解析 `VAR` 或 `VAR:-default`.
+fn resolve(expr: &str) -> Result<String> {
+    let (name, default) = match expr.split_once(":-") {
+        Some((name, default)) => (name.trim(), Some(default)),
+        None => (expr.trim(), None),
+    };
+    match (env::var(name), default) {
+        (Ok(v), Some(default)) if v.is_empty() => Ok(default.to_owned()),
+        (Ok(v), _) => Ok(v),
+        (Err(_), Some(default)) => Ok(default.to_owned()),
+        (Err(_), None) => bail!("环境变量 {name} 未设置"),
+    }
+}
+
+/// `config/mysql.yaml` -> `config/mysql.{env}.yaml`.
+fn profile_path(path: &Path, env: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{stem}.{env}.{}", ext.to_string_lossy()),
+        None => format!("{stem}.{env}"),
+    };
+    path.with_file_name(name)
+}
+
+/// 深度合并: 映射按键递归合并, 其他值直接替换.
+pub(crate) fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Mapping(base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// 用 `{prefix}__A__B=v` 形式的环境变量覆盖 `a.b`.
+///
+/// 原值为数字、布尔值等时按 yaml 标量解析, 如 `MYSQL__PORT=3307` 得到数字;
+/// 原值为字符串, 或者文件中没有这个字段 (无法推断类型) 时保持字符串,
+/// 避免 `MYSQL__PASSWORD=123456` 被解析成数字. 所以数字等类型的字段要先在文件中写出来才能覆盖.
+fn apply_env_overrides(
+    value: &mut Value,
+    prefix: &str,
+    vars: impl Iterator<Item = (String, String)>,
+) {
+    let prefix = format!("{prefix}__");
+    for (name, raw) in vars {
+        let Some(rest) = name.strip_prefix(&prefix) else {
+            continue;
+        };
+        let keys: Vec<String> = rest.split("__").map(str::to_lowercase).collect();
+        if keys.iter().any(String::is_empty) {
+            continue;
+        }
+        set_path(value, &keys, &raw);
+    }
+}
+
+fn set_path(value: &mut Value, keys: &[String], raw: &str) {
+    let Some((first, rest)) = keys.split_first() else {
+        *value = match value {
+            // 缺失的字段在上一层插入为 `Null`
+            Value::String(_) | Value::Null => Value::String(raw.to_owned()),
+            _ => serde_yaml::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_owned())),
+        };
+        return;
+    };
+    if !value.is_mapping() {
+        *value = Value::Mapping(Mapping::new());
+    }
+    if let Value::Mapping(map) = value {
+        let key = Value::String(first.clone());
+        let child = map.entry(key).or_insert(Value::Null);
+        set_path(child, rest, raw);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn interpolate_defaults_and_escapes() {
+        let text = "a ${YAML_TEST_UNSET:-fallback} $${LITERAL} $5";
+        assert_eq!(interpolate(text).unwrap(), "a fallback ${LITERAL} $5");
+        assert!(interpolate("${YAML_TEST_UNSET}").is_err());
+        assert!(interpolate("${YAML_TEST_UNSET").is_err());
+        let path = env::var("PATH").unwrap();
+        assert_eq!(interpolate("${PATH}").unwrap(), path);
+    }
+
+    #[test]
+    fn read_yaml_interpolates_only_string_values() {
+        let dir = env::temp_dir().join(format!("yaml-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.yaml");
+        let text = concat!(
+            "# ${YAML_TEST_UNSET}\n",
+            "name: \"${YAML_TEST_UNSET:-a \\\"b\\\": c # d}\"\n",
+            "port: 8080 # ${YAML_TEST_UNSET}\n",
+            "tags:\n",
+            "  - \"${YAML_TEST_UNSET:-x\\ny}\"\n",
+        );
+        fs::write(&path, text).unwrap();
+
+        let value = read_yaml(&path).unwrap();
+        assert_eq!(value["name"].as_str(), Some("a \"b\": c # d"));
+        assert_eq!(value["port"].as_u64(), Some(8080));
+        assert_eq!(value["tags"][0].as_str(), Some("x\ny"));
+
+        fs::write(&path, "outer:\n  inner: ${YAML_TEST_UNSET}\n").unwrap();
+        let err = format!("{:#}", read_yaml(&path).unwrap_err());
+        assert!(err.contains("outer.inner"), "{err}");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn env_overrides_keep_string_types() {
+        let mut value: Value =
+            serde_yaml::from_str("host: db\nport: 3306\npassword: \"x\"\npool:\n  max: 10\n")
+                .unwrap();
+        apply_env_overrides(
+            &mut value,
+            "MYSQL",
+            vars(&[
+                ("MYSQL__PORT", "3307"),
+                ("MYSQL__PASSWORD", "123456"),
+                ("MYSQL__POOL__MAX", "20"),
+                ("MYSQL__NEW__FIELD", "true"),
+                ("MYSQL__USER", "123456"),
+                ("MYSQL__", "ignored"),
+                ("REDIS__PORT", "6380"),
+            ]),
+        );
+        assert_eq!(value["port"].as_u64(), Some(3307));
+        assert_eq!(value["password"].as_str(), Some("123456"));
+        assert_eq!(value["pool"]["max"].as_u64(), Some(20));
+        // 文件中没有的字段无法推断类型, 保持字符串
+        assert_eq!(value["new"]["field"].as_str(), Some("true"));
+        assert_eq!(value["user"].as_str(), Some("123456"));
+        assert_eq!(value["host"].as_str(), Some("db"));
+    }
+
+    #[test]
+    fn env_overrides_parse_null_as_string_and_keep_scalar_types() {
+        let mut value: Value =
+            serde_yaml::from_str("password:\nenabled: false\nratio: 0.5\n").unwrap();
+        apply_env_overrides(
+            &mut value,
+            "APP",
+            vars(&[
+                ("APP__PASSWORD", "007"),
+                ("APP__ENABLED", "true"),
+                ("APP__RATIO", "0.75"),
+            ]),
+        );
+        assert_eq!(value["password"].as_str(), Some("007"));
+        assert_eq!(value["enabled"].as_bool(), Some(true));
+        assert_eq!(value["ratio"].as_f64(), Some(0.75));
+    }
+
+    #[test]
+    fn merge_replaces_sequences_and_merges_mappings() {
+        let mut base: Value = serde_yaml::from_str("a: {x: 1, y: 2}\nlist: [1, 2]\n").unwrap();
+        let overlay: Value = serde_yaml::from_str("a: {y: 3}\nlist: [9]\n").unwrap();
+        merge(&mut base, overlay);
+        assert_eq!(base["a"]["x"].as_u64(), Some(1));
+        assert_eq!(base["a"]["y"].as_u64(), Some(3));
+        assert_eq!(base["list"].as_sequence().map(Vec::len), Some(1));
+    }
+
+    #[test]
+    fn profile_path_inserts_env() {
+        assert_eq!(
+            profile_path(Path::new("config/mysql.yaml"), "production"),
+            PathBuf::from("config/mysql.production.yaml")
+        );
+    }
 }
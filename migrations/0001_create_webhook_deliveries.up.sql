@@ -0,0 +1,16 @@
+-- webhook 投递记录, 时间都是 UTC
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    id               BIGINT UNSIGNED   NOT NULL AUTO_INCREMENT,
+    endpoint         VARCHAR(64)       NOT NULL COMMENT '接收方名称',
+    event            VARCHAR(128)      NOT NULL COMMENT '事件类型',
+    payload          MEDIUMTEXT        NOT NULL COMMENT '消息体, 按原样发送',
+    status           VARCHAR(16)       NOT NULL COMMENT 'pending / delivered / dead',
+    attempts         INT UNSIGNED      NOT NULL DEFAULT 0 COMMENT '已经尝试的次数',
+    next_attempt_at  DATETIME(3)       NOT NULL COMMENT '下一次投递的时间',
+    last_status_code SMALLINT UNSIGNED NULL COMMENT '最后一次收到的状态码',
+    last_error       VARCHAR(1024)     NULL COMMENT '最后一次失败的原因',
+    created_at       DATETIME(3)       NOT NULL,
+    delivered_at     DATETIME(3)       NULL,
+    PRIMARY KEY (id),
+    KEY idx_status_next_attempt_at (status, next_attempt_at)
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COMMENT = 'webhook 投递记录';